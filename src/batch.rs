@@ -0,0 +1,745 @@
+//! Experimental batch ("rollup") merging.
+//!
+//! Instead of merging ready PRs one by one, they are all merged into an
+//! integration branch created from the tip of their base branch. Once the
+//! required statuses have passed on the integration branch, the base branch is
+//! fast-forwarded to it, which makes Github consider all the PRs in the batch
+//! as merged. If the statuses fail, the batch is split in half and the first
+//! half is tried again, until the culprit PR is isolated and rejected.
+//!
+//! Octobors doesn't keep any state between runs, so the state of a batch is
+//! entirely stored in the integration branch: its head commit message lists
+//! the PRs (and their SHAs) that were merged in it.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    context,
+    process::{latest_check_runs, CheckRun, Pr},
+};
+use anyhow::Result;
+use octocrab::models::StatusState;
+use tracing as log;
+
+/// The integration branch for a base branch is named `{BRANCH_PREFIX}{base}`.
+const BRANCH_PREFIX: &str = "octobors/batch/";
+
+/// First line of the integration branch merge commits.
+const MESSAGE_HEADER: &str = "octobors batch";
+
+/// A PR, as included in a batch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchEntry {
+    pub number: u64,
+    pub sha: String,
+}
+
+impl BatchEntry {
+    fn from_pr(pr: &Pr) -> Self {
+        Self {
+            number: pr.number,
+            sha: pr.commit_sha.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatchState {
+    /// No batch is being tested.
+    Idle,
+    /// The entries have been merged into the integration branch, whose head is `head_sha`.
+    Testing {
+        head_sha: String,
+        entries: Vec<BatchEntry>,
+    },
+}
+
+/// The result of the required statuses on the integration branch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CiOutcome {
+    Pending,
+    Success,
+    Failure,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatchAction {
+    /// Fast-forward the base branch to the head of the integration branch.
+    FastForward { head_sha: String },
+    /// Delete the integration branch.
+    DeleteBranch,
+    /// Remove a PR that broke the build from the batch.
+    Reject { number: u64 },
+    /// Create a new integration branch, and merge the entries into it.
+    Start { entries: Vec<BatchEntry> },
+}
+
+/// Computes what needs to be done given the current state of the batch.
+///
+/// `open` contains all the open PRs, and `ready` the ones that could be merged
+/// right now.
+pub fn step(
+    state: &BatchState,
+    ci: CiOutcome,
+    open: &[BatchEntry],
+    ready: &[BatchEntry],
+) -> Vec<BatchAction> {
+    let start = |entries: &[BatchEntry]| {
+        if entries.is_empty() {
+            None
+        } else {
+            Some(BatchAction::Start {
+                entries: entries.to_vec(),
+            })
+        }
+    };
+
+    match state {
+        BatchState::Idle => start(ready).into_iter().collect(),
+        BatchState::Testing { head_sha, entries } => {
+            if let Some(outdated) = entries.iter().find(|entry| !open.contains(entry)) {
+                log::info!(
+                    "PR #{} was closed or updated, abandoning the batch",
+                    outdated.number
+                );
+                return std::iter::once(BatchAction::DeleteBranch)
+                    .chain(start(ready))
+                    .collect();
+            }
+
+            match ci {
+                CiOutcome::Pending => Vec::new(),
+                CiOutcome::Success => vec![
+                    BatchAction::FastForward {
+                        head_sha: head_sha.clone(),
+                    },
+                    BatchAction::DeleteBranch,
+                ],
+                CiOutcome::Failure => match entries.as_slice() {
+                    [culprit] => vec![
+                        BatchAction::DeleteBranch,
+                        BatchAction::Reject {
+                            number: culprit.number,
+                        },
+                    ],
+                    _ => {
+                        // Some PRs of the first half may not be ready anymore.
+                        let half: Vec<_> = entries[..entries.len() / 2]
+                            .iter()
+                            .filter(|entry| ready.contains(entry))
+                            .cloned()
+                            .collect();
+                        std::iter::once(BatchAction::DeleteBranch)
+                            .chain(start(&half))
+                            .collect()
+                    }
+                },
+            }
+        }
+    }
+}
+
+fn format_message(entries: &[BatchEntry]) -> String {
+    let mut message = format!("{MESSAGE_HEADER}\n");
+    for entry in entries {
+        message += &format!("\n#{} {}", entry.number, entry.sha);
+    }
+    message
+}
+
+/// Parses the entries out of an integration branch commit message. Returns
+/// `None` if the commit wasn't made by us.
+fn parse_message(message: &str) -> Option<Vec<BatchEntry>> {
+    let mut lines = message.lines();
+    if lines.next()? != MESSAGE_HEADER {
+        return None;
+    }
+    Some(
+        lines
+            .filter_map(|line| {
+                let (number, sha) = line.strip_prefix('#')?.split_once(' ')?;
+                Some(BatchEntry {
+                    number: number.parse().ok()?,
+                    sha: sha.trim().to_owned(),
+                })
+            })
+            .collect(),
+    )
+}
+
+/// The operations needed on Github to run a batch, for a single base branch.
+///
+/// This is a trait so the batch logic can be tested without hitting the Github API.
+#[allow(async_fn_in_trait)]
+pub trait BatchClient {
+    /// Returns the head SHA and commit message of the integration branch, if it exists.
+    async fn integration_head(&self) -> Result<Option<(String, String)>>;
+    /// Returns the state of the required statuses for the given commit.
+    async fn ci_outcome(&self, sha: &str) -> Result<CiOutcome>;
+    /// Creates the integration branch from the tip of the base branch.
+    async fn create_integration_branch(&self) -> Result<()>;
+    /// Merges a commit into the integration branch, returning the new head, or
+    /// `None` if there was a conflict.
+    async fn merge_into_integration(&self, sha: &str, message: &str) -> Result<Option<String>>;
+    /// Fast-forwards the base branch to the given commit. Returns `false` if the
+    /// base branch moved since the integration branch was created.
+    async fn fast_forward_base(&self, sha: &str) -> Result<bool>;
+    async fn delete_integration_branch(&self) -> Result<()>;
+    /// Lets the PR author know the PR broke the batch, and prevents it from
+    /// being batched again.
+    async fn reject(&self, number: u64) -> Result<()>;
+}
+
+/// Reads the current state of the batch from the integration branch.
+async fn current_state(client: &impl BatchClient) -> Result<BatchState> {
+    Ok(match client.integration_head().await? {
+        None => BatchState::Idle,
+        Some((head_sha, message)) => match parse_message(&message) {
+            Some(entries) if !entries.is_empty() => BatchState::Testing { head_sha, entries },
+            _ => {
+                log::warn!("Integration branch wasn't created by us, ignoring it");
+                BatchState::Idle
+            }
+        },
+    })
+}
+
+/// Runs one step of the batch for a single base branch.
+pub async fn run(
+    client: &impl BatchClient,
+    open: &[BatchEntry],
+    ready: &[BatchEntry],
+) -> Result<()> {
+    let state = current_state(client).await?;
+    let ci = match &state {
+        BatchState::Idle => CiOutcome::Pending,
+        BatchState::Testing { head_sha, .. } => client.ci_outcome(head_sha).await?,
+    };
+
+    for action in step(&state, ci, open, ready) {
+        log::info!("Batch: {:?}", action);
+        match action {
+            BatchAction::FastForward { head_sha } => {
+                if !client.fast_forward_base(&head_sha).await? {
+                    // Something was pushed to the base branch while the batch was
+                    // tested, so it has to be tested again on top of it.
+                    log::info!("The base branch moved, restarting the batch");
+                    client.delete_integration_branch().await?;
+                    if let BatchState::Testing { entries, .. } = &state {
+                        let entries: Vec<_> = entries
+                            .iter()
+                            .filter(|entry| ready.contains(entry))
+                            .cloned()
+                            .collect();
+                        if !entries.is_empty() {
+                            start(client, entries).await?;
+                        }
+                    }
+                    break;
+                }
+            }
+            BatchAction::DeleteBranch => client.delete_integration_branch().await?,
+            BatchAction::Reject { number } => client.reject(number).await?,
+            BatchAction::Start { entries } => start(client, entries).await?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Creates the integration branch, and merges the entries into it.
+async fn start(client: &impl BatchClient, entries: Vec<BatchEntry>) -> Result<()> {
+    client.create_integration_branch().await?;
+    let mut merged = Vec::new();
+    for entry in entries {
+        let mut candidate = merged.clone();
+        candidate.push(entry.clone());
+        if client
+            .merge_into_integration(&entry.sha, &format_message(&candidate))
+            .await?
+            .is_some()
+        {
+            merged = candidate;
+        } else {
+            log::info!("PR #{} conflicts with the batch", entry.number);
+            client.reject(entry.number).await?;
+        }
+    }
+    if merged.is_empty() {
+        client.delete_integration_branch().await?;
+    }
+    Ok(())
+}
+
+/// [`BatchClient`] backed by the Github API.
+struct GithubBatch<'a> {
+    client: &'a context::Client,
+    config: &'a context::RepoConfig,
+    base: &'a str,
+    branch: String,
+}
+
+impl BatchClient for GithubBatch<'_> {
+    async fn integration_head(&self) -> Result<Option<(String, String)>> {
+        let repo = &self.config.name;
+        match self.client.get_branch_head(repo, &self.branch).await? {
+            None => Ok(None),
+            Some(sha) => {
+                let message = self.client.get_commit_message(repo, &sha).await?;
+                Ok(Some((sha, message)))
+            }
+        }
+    }
+
+    async fn ci_outcome(&self, sha: &str) -> Result<CiOutcome> {
+        let repo = &self.config.name;
+        let mut statuses: BTreeMap<_, _> = self
+            .client
+            .get_commit_statuses(repo, sha)
+            .await?
+            .into_iter()
+            .flat_map(|status| Some((status.context?, status.state)))
+            .collect();
+        // Check runs count as statuses named after them, as they do for PRs.
+        let check_runs = self.client.get_check_runs(repo, sha).await?;
+        for check_run in latest_check_runs(
+            check_runs
+                .into_iter()
+                .map(CheckRun::from_octocrab_check_run)
+                .collect(),
+        ) {
+            if let Some(status) = check_run.status(&self.config.conclusion_map) {
+                statuses.entry(check_run.name).or_insert(status.state);
+            }
+        }
+        let outcome = |required: &[String]| {
+            let mut outcome = CiOutcome::Success;
+            for required in required {
+                match statuses.get(required) {
+                    Some(StatusState::Success) => {}
+                    Some(StatusState::Failure | StatusState::Error) => return CiOutcome::Failure,
+                    _ => outcome = CiOutcome::Pending,
+                }
+            }
+            outcome
+        };
+        let flat = outcome(&self.config.required_statuses_for(self.base));
+        let groups = &self.config.required_status_groups;
+        if groups.is_empty() || flat == CiOutcome::Failure {
+            return Ok(flat);
+        }
+        // One of the groups must pass too.
+        let group_outcomes: Vec<_> = groups.iter().map(|group| outcome(group)).collect();
+        Ok(if group_outcomes.contains(&CiOutcome::Success) {
+            flat
+        } else if group_outcomes.iter().all(|o| *o == CiOutcome::Failure) {
+            CiOutcome::Failure
+        } else {
+            CiOutcome::Pending
+        })
+    }
+
+    async fn create_integration_branch(&self) -> Result<()> {
+        let repo = &self.config.name;
+        if self
+            .client
+            .get_branch_head(repo, &self.branch)
+            .await?
+            .is_some()
+        {
+            log::warn!("Removing leftover integration branch '{}'", self.branch);
+            self.client.delete_branch(repo, &self.branch).await?;
+        }
+        let base_sha = self
+            .client
+            .get_branch_head(repo, self.base)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Base branch '{}' doesn't exist", self.base))?;
+        self.client
+            .create_branch(repo, &self.branch, &base_sha)
+            .await
+    }
+
+    async fn merge_into_integration(&self, sha: &str, message: &str) -> Result<Option<String>> {
+        self.client
+            .merge_into_branch(&self.config.name, &self.branch, sha, message)
+            .await
+    }
+
+    async fn fast_forward_base(&self, sha: &str) -> Result<bool> {
+        self.client
+            .fast_forward_branch(&self.config.name, self.base, sha)
+            .await
+    }
+
+    async fn delete_integration_branch(&self) -> Result<()> {
+        self.client
+            .delete_branch(&self.config.name, &self.branch)
+            .await
+    }
+
+    async fn reject(&self, number: u64) -> Result<()> {
+        crate::process::post_comment(
+            self.client,
+            &self.config.name,
+            number,
+            "This PR couldn't be merged as part of a batch, either because of a conflict or \
+             because it made the required checks fail. It has been removed from the batch."
+                .to_owned(),
+        )
+        .await?;
+        // Batching is only enabled along with a block label.
+        let Some(label) = &self.config.block_merge_label else {
+            return Ok(());
+        };
+        crate::process::add_labels(
+            self.client,
+            &self.config.name,
+            number,
+            &mut Vec::new(),
+            std::iter::once(label),
+        )
+        .await
+    }
+}
+
+/// Runs one step of batch merging for every base branch targeted by the open PRs.
+pub async fn process(
+    client: &context::Client,
+    config: &context::RepoConfig,
+    open: &[Pr],
+    ready: &[Pr],
+) -> Result<()> {
+    let mut bases: BTreeMap<&str, (Vec<BatchEntry>, Vec<BatchEntry>)> = BTreeMap::new();
+    for pr in open {
+        bases
+            .entry(pr.base_ref.as_str())
+            .or_default()
+            .0
+            .push(BatchEntry::from_pr(pr));
+    }
+    for pr in ready {
+        bases
+            .entry(pr.base_ref.as_str())
+            .or_default()
+            .1
+            .push(BatchEntry::from_pr(pr));
+    }
+
+    for (base, (open, ready)) in bases {
+        let batch = GithubBatch {
+            client,
+            config,
+            base,
+            branch: format!("{BRANCH_PREFIX}{base}"),
+        };
+        run(&batch, &open, &ready).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{self, MockServer};
+    use std::cell::RefCell;
+
+    fn entry(number: u64) -> BatchEntry {
+        BatchEntry {
+            number,
+            sha: format!("sha{number}"),
+        }
+    }
+
+    fn testing(numbers: &[u64]) -> BatchState {
+        BatchState::Testing {
+            head_sha: "head".to_owned(),
+            entries: numbers.iter().copied().map(entry).collect(),
+        }
+    }
+
+    #[test]
+    fn idle_starts_batch_with_ready_prs() {
+        let ready = vec![entry(1), entry(2)];
+        assert_eq!(
+            step(&BatchState::Idle, CiOutcome::Pending, &ready, &ready),
+            vec![BatchAction::Start {
+                entries: ready.clone()
+            }]
+        );
+        assert_eq!(
+            step(&BatchState::Idle, CiOutcome::Pending, &ready, &[]),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn pending_batch_waits() {
+        let open = vec![entry(1), entry(2), entry(3)];
+        assert_eq!(
+            step(&testing(&[1, 2]), CiOutcome::Pending, &open, &open),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn green_batch_fast_forwards() {
+        let open = vec![entry(1), entry(2)];
+        assert_eq!(
+            step(&testing(&[1, 2]), CiOutcome::Success, &open, &open),
+            vec![
+                BatchAction::FastForward {
+                    head_sha: "head".to_owned()
+                },
+                BatchAction::DeleteBranch,
+            ]
+        );
+    }
+
+    #[test]
+    fn red_batch_is_bisected() {
+        let open = vec![entry(1), entry(2), entry(3), entry(4)];
+        assert_eq!(
+            step(&testing(&[1, 2, 3, 4]), CiOutcome::Failure, &open, &open),
+            vec![
+                BatchAction::DeleteBranch,
+                BatchAction::Start {
+                    entries: vec![entry(1), entry(2)]
+                },
+            ]
+        );
+        assert_eq!(
+            step(&testing(&[1, 2]), CiOutcome::Failure, &open, &open),
+            vec![
+                BatchAction::DeleteBranch,
+                BatchAction::Start {
+                    entries: vec![entry(1)]
+                },
+            ]
+        );
+        assert_eq!(
+            step(&testing(&[1]), CiOutcome::Failure, &open, &open),
+            vec![BatchAction::DeleteBranch, BatchAction::Reject { number: 1 }]
+        );
+    }
+
+    #[test]
+    fn bisection_skips_prs_no_longer_ready() {
+        let open = vec![entry(1), entry(2), entry(3), entry(4)];
+        let ready = vec![entry(2), entry(3), entry(4)];
+        assert_eq!(
+            step(&testing(&[1, 2, 3, 4]), CiOutcome::Failure, &open, &ready),
+            vec![
+                BatchAction::DeleteBranch,
+                BatchAction::Start {
+                    entries: vec![entry(2)]
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn updated_pr_abandons_batch() {
+        let mut open = vec![entry(1), entry(2)];
+        open[1].sha = "new-sha".to_owned();
+        assert_eq!(
+            step(&testing(&[1, 2]), CiOutcome::Success, &open, &open),
+            vec![
+                BatchAction::DeleteBranch,
+                BatchAction::Start {
+                    entries: open.clone()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn message_roundtrip() {
+        let entries = vec![entry(1), entry(42)];
+        assert_eq!(
+            parse_message(&format_message(&entries)),
+            Some(entries.clone())
+        );
+        assert_eq!(parse_message("Some human commit"), None);
+    }
+
+    /// Records the calls made to it, and simulates an integration branch.
+    #[derive(Default)]
+    struct MockClient {
+        head: RefCell<Option<(String, String)>>,
+        ci: Option<CiOutcome>,
+        conflicts: Vec<String>,
+        base_moved: bool,
+        calls: RefCell<Vec<String>>,
+    }
+
+    impl BatchClient for MockClient {
+        async fn integration_head(&self) -> Result<Option<(String, String)>> {
+            Ok(self.head.borrow().clone())
+        }
+        async fn ci_outcome(&self, _sha: &str) -> Result<CiOutcome> {
+            Ok(self.ci.unwrap())
+        }
+        async fn create_integration_branch(&self) -> Result<()> {
+            self.calls.borrow_mut().push("create".to_owned());
+            *self.head.borrow_mut() = Some(("base".to_owned(), String::new()));
+            Ok(())
+        }
+        async fn merge_into_integration(&self, sha: &str, message: &str) -> Result<Option<String>> {
+            if self.conflicts.iter().any(|c| c == sha) {
+                return Ok(None);
+            }
+            self.calls.borrow_mut().push(format!("merge {sha}"));
+            let head = format!("merged-{sha}");
+            *self.head.borrow_mut() = Some((head.clone(), message.to_owned()));
+            Ok(Some(head))
+        }
+        async fn fast_forward_base(&self, sha: &str) -> Result<bool> {
+            if self.base_moved {
+                return Ok(false);
+            }
+            self.calls.borrow_mut().push(format!("fast-forward {sha}"));
+            Ok(true)
+        }
+        async fn delete_integration_branch(&self) -> Result<()> {
+            self.calls.borrow_mut().push("delete".to_owned());
+            *self.head.borrow_mut() = None;
+            Ok(())
+        }
+        async fn reject(&self, number: u64) -> Result<()> {
+            self.calls.borrow_mut().push(format!("reject #{number}"));
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn full_batch_lifecycle() {
+        let open = vec![entry(1), entry(2)];
+        let mut client = MockClient::default();
+
+        // Start the batch.
+        run(&client, &open, &open).await.unwrap();
+        assert_eq!(
+            client.calls.take(),
+            vec!["create", "merge sha1", "merge sha2"]
+        );
+        assert_eq!(
+            current_state(&client).await.unwrap(),
+            BatchState::Testing {
+                head_sha: "merged-sha2".to_owned(),
+                entries: open.clone()
+            }
+        );
+
+        // CI is still running.
+        client.ci = Some(CiOutcome::Pending);
+        run(&client, &open, &open).await.unwrap();
+        assert!(client.calls.take().is_empty());
+
+        // CI fails, try again with the first half.
+        client.ci = Some(CiOutcome::Failure);
+        run(&client, &open, &open).await.unwrap();
+        assert_eq!(client.calls.take(), vec!["delete", "create", "merge sha1"]);
+
+        // The first half passes.
+        client.ci = Some(CiOutcome::Success);
+        run(&client, &open, &open).await.unwrap();
+        assert_eq!(
+            client.calls.take(),
+            vec!["fast-forward merged-sha1", "delete"]
+        );
+        assert_eq!(current_state(&client).await.unwrap(), BatchState::Idle);
+    }
+
+    #[tokio::test]
+    async fn moved_base_restarts_batch() {
+        let open = vec![entry(1), entry(2)];
+        let mut client = MockClient::default();
+        run(&client, &open, &open).await.unwrap();
+        client.calls.take();
+
+        // Something was pushed to the base branch while CI was running.
+        client.ci = Some(CiOutcome::Success);
+        client.base_moved = true;
+        run(&client, &open, &[entry(2)]).await.unwrap();
+        assert_eq!(client.calls.take(), vec!["delete", "create", "merge sha2"]);
+        assert_eq!(
+            current_state(&client).await.unwrap(),
+            BatchState::Testing {
+                head_sha: "merged-sha2".to_owned(),
+                entries: vec![entry(2)]
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn conflicting_pr_is_rejected() {
+        let open = vec![entry(1), entry(2)];
+        let client = MockClient {
+            conflicts: vec!["sha1".to_owned()],
+            ..Default::default()
+        };
+        run(&client, &open, &open).await.unwrap();
+        assert_eq!(
+            client.calls.take(),
+            vec!["create", "reject #1", "merge sha2"]
+        );
+        assert_eq!(
+            current_state(&client).await.unwrap(),
+            BatchState::Testing {
+                head_sha: "merged-sha2".to_owned(),
+                entries: vec![entry(2)]
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn check_runs_count_as_statuses() {
+        const STATUSES: &str = r#"{"state": "success", "sha": "head", "total_count": 1,
+            "statuses": [{"state": "success", "context": "build"}]}"#;
+        let check_runs = |conclusion: &str| -> &'static str {
+            Box::leak(
+                format!(
+                    r#"{{"total_count": 1, "check_runs": [{{
+                        "id": 7,
+                        "node_id": "CR_7",
+                        "head_sha": "head",
+                        "url": "https://api.github.com/repos/org/repo/check-runs/7",
+                        "conclusion": "{conclusion}",
+                        "output": {{"title": null, "summary": null, "text": null,
+                                   "annotations_count": 0, "annotations_url": ""}},
+                        "name": "lint"
+                    }}]}}"#
+                )
+                .into_boxed_str(),
+            )
+        };
+        let config = context::RepoConfig {
+            name: "repo".to_owned(),
+            required_statuses: vec!["build".to_owned(), "lint".to_owned()],
+            ..Default::default()
+        };
+        for (conclusion, expected) in [
+            ("success", CiOutcome::Success),
+            ("failure", CiOutcome::Failure),
+        ] {
+            let server = MockServer::new(vec![
+                ("200 OK", STATUSES),
+                ("200 OK", check_runs(conclusion)),
+            ]);
+            let client = test_utils::client(&server);
+            let batch = GithubBatch {
+                client: &client,
+                config: &config,
+                base: "main",
+                branch: format!("{BRANCH_PREFIX}main"),
+            };
+            assert_eq!(batch.ci_outcome("head").await.unwrap(), expected);
+            let requests = server.requests();
+            assert_eq!(
+                requests[1].line,
+                "GET /repos/org/repo/commits/head/check-runs?per_page=100"
+            );
+        }
+    }
+}
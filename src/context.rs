@@ -1,15 +1,101 @@
 use anyhow::{Context as _, Result};
+use chrono::{DateTime, Datelike, NaiveTime, Utc, Weekday};
 use http::header::HeaderName;
 use octocrab::{
     models,
-    params::{pulls::Sort, Direction},
+    params::{
+        checks::{CheckRunConclusion, CheckRunOutput, CheckRunStatus},
+        pulls::Sort,
+        repos::{Commitish, Reference},
+        Direction,
+    },
 };
-use std::{cell::RefCell, fmt};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    fmt,
+    future::Future,
+    path::PathBuf,
+    time::Duration,
+};
+use tracing as log;
+
+/// The name of the check run reporting whether PRs can be merged.
+pub const CHECK_RUN_NAME: &str = "octobors/automerge";
+
+/// The characters encoded in branch names in URLs, which may have slashes.
+const BRANCH_NAME: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'/')
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.');
+
+/// The header selecting the version of GitHub's REST API.
+const API_VERSION_HEADER: &str = "x-github-api-version";
+
+/// The token scopes octobors needs: the `repo` scope, and the narrower scopes it includes.
+const NEEDED_SCOPES: &[&str] = &[
+    "repo",
+    "repo:status",
+    "repo_deployment",
+    "public_repo",
+    "repo:invite",
+    "security_events",
+];
+
+/// The scopes listed in an `x-oauth-scopes` header (e.g. `repo, admin:org`) that aren't needed.
+fn excess_scopes(granted: &str, needed: &[&str]) -> Vec<String> {
+    granted
+        .split(',')
+        .map(str::trim)
+        .filter(|scope| !scope.is_empty() && !needed.contains(scope))
+        .map(str::to_owned)
+        .collect()
+}
+
+/// How many times a request failing because of a transient error is retried, by default.
+const DEFAULT_RETRIES: u32 = 2;
+
+/// How many requests are sent between two checks of the remaining rate limit quota.
+const THROTTLE_REFRESH_INTERVAL: u32 = 100;
+
+/// Whether the error is likely to go away if the request is retried, e.g. a
+/// server error or a connection issue.
+fn is_transient(err: &octocrab::Error) -> bool {
+    match err {
+        octocrab::Error::GitHub { source, .. } => source.status_code.is_server_error(),
+        // Error responses that aren't JSON usually come from a proxy in front
+        // of the API, e.g. a 502 page.
+        octocrab::Error::Hyper { .. }
+        | octocrab::Error::Service { .. }
+        | octocrab::Error::Serde { .. } => true,
+        _ => false,
+    }
+}
 
 pub struct Client {
     pub inner: octocrab::Octocrab,
     pub owner: String,
     pub bot_nick: RefCell<Option<String>>,
+    /// Whether a user is a collaborator, by (repo, user).
+    pub collaborators: RefCell<HashMap<(String, String), bool>>,
+    /// The default branch of each repo.
+    pub default_branches: RefCell<HashMap<String, String>>,
+    /// The combined status of the head of branches, by (repo, branch).
+    pub branch_statuses: RefCell<HashMap<(String, String), models::StatusState>>,
+    /// Whether a branch has a merge queue, by (repo, branch).
+    pub merge_queues: RefCell<HashMap<(String, String), bool>>,
+    /// The members of the org's teams, by team slug.
+    pub team_members: RefCell<HashMap<String, Vec<String>>>,
+    /// How many times requests failing because of transient errors are retried.
+    pub retries: u32,
+    /// Delay before the first retry, doubled on each subsequent retry.
+    pub retry_delay: Duration,
+    /// Limits how many requests are in flight, depending on the remaining rate limit quota.
+    pub throttle: crate::throttle::Throttle,
+    /// How many requests were sent since the remaining quota was last checked.
+    requests_since_refresh: Cell<u32>,
 }
 
 impl Client {
@@ -25,7 +111,7 @@ impl Client {
         };
         for (key, value) in extra_headers {
             let name = HeaderName::from_lowercase(key.to_lowercase().as_bytes())?;
-            builder = builder.add_header(name, value.to_string());
+            builder = builder.add_header(name, value.clone());
         }
         let inner = builder
             .personal_token(token)
@@ -35,26 +121,131 @@ impl Client {
             inner,
             owner,
             bot_nick: RefCell::new(None),
+            collaborators: RefCell::new(HashMap::new()),
+            default_branches: RefCell::new(HashMap::new()),
+            branch_statuses: RefCell::new(HashMap::new()),
+            merge_queues: RefCell::new(HashMap::new()),
+            team_members: RefCell::new(HashMap::new()),
+            retries: DEFAULT_RETRIES,
+            retry_delay: Duration::from_secs(1),
+            throttle: Default::default(),
+            requests_since_refresh: Cell::new(0),
         })
     }
 
+    /// Sends a request, retrying it with an exponential backoff if it fails
+    /// because of a transient error.
+    async fn with_retries<T, F, Fut>(&self, mut request: F) -> octocrab::Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = octocrab::Result<T>>,
+    {
+        let mut delay = self.retry_delay;
+        let mut attempt = 0;
+        loop {
+            let result = {
+                let _permit = self.throttle.acquire().await;
+                request().await
+            };
+            self.refresh_throttle().await;
+            match result {
+                Err(err) if attempt < self.retries && is_transient(&err) => {
+                    attempt += 1;
+                    log::warn!("Request failed, retrying in {delay:?}: {err}");
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Resizes the throttle from the remaining quota every few requests. The headers of the
+    /// typed responses aren't available, so the quota is checked separately, which doesn't count
+    /// against the rate limit.
+    async fn refresh_throttle(&self) {
+        let requests = self.requests_since_refresh.get() + 1;
+        if requests < THROTTLE_REFRESH_INTERVAL {
+            self.requests_since_refresh.set(requests);
+            return;
+        }
+        self.requests_since_refresh.set(0);
+        if let Err(err) = self.get_rate_limit().await {
+            log::debug!("Could not refresh the throttle: {err:#}");
+        }
+    }
+
     /// Get the currently open pull requests for the repo.
     ///
-    /// Only the most recently pull requests are included as pagination is not
-    /// handled. This is OK as we are not interested in outdated PRs as they
-    /// won't have been updated since we last checked.
-    pub async fn get_pull_requests(&self, repo: &str) -> Result<Vec<models::pulls::PullRequest>> {
-        Ok(self
-            .inner
-            .pulls(&self.owner, repo)
-            .list()
-            .state(octocrab::params::State::Open)
-            .direction(Direction::Descending)
-            .sort(Sort::Updated)
-            .send()
+    /// When fetching the most recently updated pull requests without a limit
+    /// only the first page is included as pagination is not handled. This is OK
+    /// as we are not interested in outdated PRs as they won't have been updated
+    /// since we last checked. Other orders put the PRs we are interested in
+    /// anywhere, so every page is fetched, up to `limit` PRs.
+    pub async fn get_pull_requests(
+        &self,
+        repo: &str,
+        sort: FetchSort,
+        direction: FetchDirection,
+        limit: Option<usize>,
+    ) -> Result<Vec<models::pulls::PullRequest>> {
+        let pulls = self.inner.pulls(&self.owner, repo);
+        let list = || {
+            pulls
+                .list()
+                .state(octocrab::params::State::Open)
+                .direction(direction)
+                .sort(sort)
+        };
+        let recent_first = sort == FetchSort::Updated && direction == FetchDirection::Descending;
+        if recent_first && limit.is_none() {
+            return Ok(self
+                .with_retries(|| async { list().send().await })
+                .await
+                .context("unable to retrieve pull requests")?
+                .items);
+        }
+
+        let per_page = limit.map_or(100, |limit| limit.clamp(1, 100));
+        let mut prs = Vec::new();
+        for page in 1u32.. {
+            let items = self
+                .with_retries(|| async { list().per_page(per_page as u8).page(page).send().await })
+                .await
+                .context("unable to retrieve pull requests")?
+                .items;
+            let last = items.len() < per_page;
+            prs.extend(items);
+            if last || limit.is_some_and(|limit| prs.len() >= limit) {
+                break;
+            }
+        }
+        if let Some(limit) = limit {
+            prs.truncate(limit);
+        }
+        Ok(prs)
+    }
+
+    /// Retrieves a single pull request by number.
+    pub async fn get_pull_request(
+        &self,
+        repo: &str,
+        pr_number: u64,
+    ) -> Result<models::pulls::PullRequest> {
+        self.get_pull_request_from(&self.owner, repo, pr_number)
+            .await
+    }
+
+    /// Get a pull request of a repo owned by someone else than `owner`.
+    pub async fn get_pull_request_from(
+        &self,
+        owner: &str,
+        repo: &str,
+        pr_number: u64,
+    ) -> Result<models::pulls::PullRequest> {
+        self.with_retries(|| async { self.inner.pulls(owner, repo).get(pr_number).await })
             .await
-            .context("unable to retrieve pull requests")?
-            .items)
+            .with_context(|| format!("unable to retrieve pull request {owner}/{repo}#{pr_number}"))
     }
 
     /// Retrieves all the comments (that are not associated to a review) for a given pull request.
@@ -65,21 +256,130 @@ impl Client {
     ) -> Result<Vec<models::issues::Comment>> {
         let mut comments = Vec::new();
         let page = self
-            .inner
-            .issues(&self.owner, repo)
-            .list_comments(pr_number)
-            .send()
+            .with_retries(|| async {
+                self.inner
+                    .issues(&self.owner, repo)
+                    .list_comments(pr_number)
+                    .send()
+                    .await
+            })
             .await
             .context("Could not get comments for PR")?;
         let mut page = Some(page);
         while let Some(previous) = page {
+            let next = previous.next;
             comments.extend(previous.items);
-            page = self.inner.get_page(&previous.next).await?;
+            page = self.with_retries(|| self.inner.get_page(&next)).await?;
         }
         tracing::info!(?comments, "comments we got from github api");
         Ok(comments)
     }
 
+    /// Replaces the body of an existing comment.
+    pub async fn update_comment(&self, repo: &str, comment_id: u64, body: &str) -> Result<()> {
+        self.inner
+            .issues(&self.owner, repo)
+            .update_comment(comment_id.into(), body)
+            .await
+            .context("Could not update comment")?;
+        Ok(())
+    }
+
+    /// Replaces the body of an issue.
+    pub async fn update_issue_body(&self, repo: &str, issue: u64, body: &str) -> Result<()> {
+        self.inner
+            .issues(&self.owner, repo)
+            .update(issue)
+            .body(body)
+            .send()
+            .await
+            .context("Could not update issue")?;
+        Ok(())
+    }
+
+    /// Get the events of a PR's timeline, e.g. commits, reviews, comments or label changes.
+    pub async fn get_timeline(&self, repo: &str, pr_number: u64) -> Result<Vec<TimelineEvent>> {
+        let mut timeline = Vec::new();
+        let page = self
+            .with_retries(|| async {
+                self.inner
+                    .issues(&self.owner, repo)
+                    .list_timeline_events(pr_number)
+                    .per_page(100)
+                    .send()
+                    .await
+            })
+            .await
+            .context("Could not get timeline for PR")?;
+        let mut page = Some(page);
+        while let Some(previous) = page {
+            for event in &previous.items {
+                // Commits are dated by their committer, and reviews by their submission.
+                let at = event
+                    .created_at
+                    .or(event.submitted_at)
+                    .or_else(|| event.committer.as_ref()?.date);
+                let Some(at) = at else {
+                    continue;
+                };
+                timeline.push(TimelineEvent {
+                    event: event.event.clone(),
+                    at,
+                    actor: event.actor.as_ref().map(|actor| actor.login.clone()),
+                    label: event.label.as_ref().map(|label| label.name.clone()),
+                    requested_reviewer: event
+                        .requested_reviewer
+                        .as_ref()
+                        .map(|reviewer| reviewer.login.clone()),
+                });
+            }
+            page = self
+                .with_retries(|| self.inner.get_page(&previous.next))
+                .await?;
+        }
+        Ok(timeline)
+    }
+
+    /// Get when the last commit of a PR was made, by committer date.
+    pub async fn get_last_commit_at(
+        &self,
+        repo: &str,
+        pr_number: u64,
+    ) -> Result<Option<DateTime<Utc>>> {
+        #[derive(serde::Deserialize)]
+        struct Commit {
+            commit: Details,
+        }
+        #[derive(serde::Deserialize)]
+        struct Details {
+            committer: Option<Committer>,
+        }
+        #[derive(serde::Deserialize)]
+        struct Committer {
+            date: Option<DateTime<Utc>>,
+        }
+        #[derive(serde::Serialize)]
+        struct PerPage {
+            per_page: u8,
+        }
+
+        let route = format!("/repos/{}/{repo}/pulls/{pr_number}/commits", self.owner);
+        let page: octocrab::Page<Commit> = self
+            .with_retries(|| self.inner.get(&route, Some(&PerPage { per_page: 100 })))
+            .await
+            .context("Could not get commits for PR")?;
+        let mut last_commit_at = None;
+        let mut page = Some(page);
+        while let Some(previous) = page {
+            let next = previous.next;
+            for commit in previous.items {
+                last_commit_at = last_commit_at.max(commit.commit.committer.and_then(|c| c.date));
+            }
+            page = self.with_retries(|| self.inner.get_page(&next)).await?;
+        }
+        Ok(last_commit_at)
+    }
+
     /// Get the reviews for a PR
     pub async fn get_pull_request_reviews(
         &self,
@@ -88,28 +388,62 @@ impl Client {
     ) -> Result<Vec<models::pulls::Review>> {
         let mut reviews = Vec::new();
         let page = self
-            .inner
-            .pulls(&self.owner, repo)
-            .list_reviews(pr_number)
-            .send()
+            .with_retries(|| async {
+                self.inner
+                    .pulls(&self.owner, repo)
+                    .list_reviews(pr_number)
+                    .send()
+                    .await
+            })
             .await
             .context("Could not get reviews for PR")?;
         let mut page = Some(page);
         while let Some(previous) = page {
+            let next = previous.next;
             reviews.extend(previous.items);
-            page = self.inner.get_page(&previous.next).await?;
+            page = self.with_retries(|| self.inner.get_page(&next)).await?;
         }
         tracing::info!(?reviews, "reviews we got from github api");
         Ok(reviews)
     }
 
+    /// Get the files changed by a PR
+    pub async fn get_pull_request_files(
+        &self,
+        repo: &str,
+        pr_number: u64,
+    ) -> Result<Vec<models::repos::DiffEntry>> {
+        let mut files = Vec::new();
+        let page = self
+            .with_retries(|| async {
+                self.inner
+                    .pulls(&self.owner, repo)
+                    .list_files(pr_number)
+                    .await
+            })
+            .await
+            .context("Could not get changed files for PR")?;
+        let mut page = Some(page);
+        while let Some(previous) = page {
+            let next = previous.next;
+            files.extend(previous.items);
+            page = self.with_retries(|| self.inner.get_page(&next)).await?;
+        }
+        Ok(files)
+    }
+
     /// Get the statuses for a PR
     pub async fn get_pull_request_statuses(
         &self,
         repo: &str,
         pr: &crate::process::Pr,
     ) -> Result<Vec<models::Status>> {
-        let reference = octocrab::params::repos::Reference::Commit(pr.commit_sha.clone());
+        self.get_commit_statuses(repo, &pr.commit_sha).await
+    }
+
+    /// Get the statuses for a commit
+    pub async fn get_commit_statuses(&self, repo: &str, sha: &str) -> Result<Vec<models::Status>> {
+        let reference = Reference::Commit(sha.to_owned());
 
         // This used to be calling `self.inner.repos(&self.owner,repo).combined_status_for_ref(&reference)`
         // but that does not let us get more statuses then 30 which is a problem in some repos.
@@ -125,137 +459,1774 @@ impl Client {
             reference = reference.ref_url(),
         );
         let combined_status: octocrab::models::CombinedStatus = self
-            .inner
-            .get(
-                route,
-                Some(&PerPage {
-                    per_page: Some(100),
-                }),
-            )
+            .with_retries(|| {
+                self.inner.get(
+                    &route,
+                    Some(&PerPage {
+                        per_page: Some(100),
+                    }),
+                )
+            })
             .await
             .context("Could not get statuses for commit")?;
 
         Ok(combined_status.statuses)
     }
 
-    pub(crate) async fn get_bot_nick(&self) -> Result<String> {
+    /// Get the SHA the given branch points to, if the branch exists.
+    pub async fn get_branch_head(&self, repo: &str, branch: &str) -> Result<Option<String>> {
+        let reference = Reference::Branch(branch.to_owned());
+        match self
+            .inner
+            .repos(&self.owner, repo)
+            .get_ref(&reference)
+            .await
         {
-            let bot_nick = self.bot_nick.borrow();
-            if let Some(cached) = &*bot_nick {
-                return Ok(cached.clone());
+            Ok(r) => match r.object {
+                models::repos::Object::Commit { sha, .. }
+                | models::repos::Object::Tag { sha, .. } => Ok(Some(sha)),
+                _ => Ok(None),
+            },
+            Err(octocrab::Error::GitHub { source, .. })
+                if source.status_code == http::StatusCode::NOT_FOUND =>
+            {
+                Ok(None)
             }
+            Err(err) => Err(err).context("Could not get branch head"),
         }
-        let nick = self
+    }
+
+    /// Get the message of a commit
+    pub async fn get_commit_message(&self, repo: &str, sha: &str) -> Result<String> {
+        #[derive(serde::Deserialize)]
+        struct GitCommit {
+            message: String,
+        }
+        let route = format!(
+            "/repos/{owner}/{repo}/git/commits/{sha}",
+            owner = self.owner
+        );
+        let commit: GitCommit = self
             .inner
-            .current()
-            .user()
+            .get(route, None::<&()>)
             .await
-            .context("Getting the bot user id")?
-            .login;
-        *self.bot_nick.borrow_mut() = Some(nick.clone());
-        Ok(nick)
+            .context("Could not get commit")?;
+        Ok(commit.message)
     }
-}
 
-/// Configuration options available for the action
-#[derive(serde::Deserialize)]
-pub struct Config {
-    /// The user or organisation that owns the repos
-    pub owner: String,
+    /// Create a branch pointing at the given SHA
+    pub async fn create_branch(&self, repo: &str, branch: &str, sha: &str) -> Result<()> {
+        self.inner
+            .repos(&self.owner, repo)
+            .create_ref(&Reference::Branch(branch.to_owned()), sha)
+            .await
+            .with_context(|| format!("Could not create branch '{branch}'"))?;
+        Ok(())
+    }
 
-    /// The repos to be run on, and their config
-    pub repos: Vec<RepoConfig>,
+    /// Delete a branch
+    pub async fn delete_branch(&self, repo: &str, branch: &str) -> Result<()> {
+        self.inner
+            .repos(&self.owner, repo)
+            .delete_ref(&Reference::Branch(branch.to_owned()))
+            .await
+            .with_context(|| format!("Could not delete branch '{branch}'"))?;
+        Ok(())
+    }
 
-    /// Whether to skip applying the changes or not.
-    pub dry_run: bool,
+    /// Merge the given SHA into a branch, returning the SHA of the merge commit.
+    ///
+    /// Returns `None` if the merge could not be done because of a conflict.
+    pub async fn merge_into_branch(
+        &self,
+        repo: &str,
+        branch: &str,
+        sha: &str,
+        message: &str,
+    ) -> Result<Option<String>> {
+        match self
+            .inner
+            .repos(&self.owner, repo)
+            .merge(sha, branch)
+            .commit_message(message)
+            .send()
+            .await
+        {
+            // Nothing to merge, the branch already contains the commit.
+            Ok(None) => Ok(self.get_branch_head(repo, branch).await?),
+            Ok(Some(commit)) => Ok(Some(commit.sha)),
+            Err(octocrab::Error::GitHub { source, .. })
+                if source.status_code == http::StatusCode::CONFLICT =>
+            {
+                Ok(None)
+            }
+            Err(err) => Err(err).with_context(|| format!("Could not merge into '{branch}'")),
+        }
+    }
 
-    /// The base URL to use GitHub API.  This may be useful if you are using a
-    /// proxy for the GitHub API or an enterprise installation.
-    pub github_api_base: Option<String>,
+    /// Move a branch to the given SHA, which must be a descendant of its current head.
+    ///
+    /// Returns `false` if it isn't, because the branch moved in the meantime.
+    pub async fn fast_forward_branch(&self, repo: &str, branch: &str, sha: &str) -> Result<bool> {
+        #[derive(serde::Serialize)]
+        struct UpdateRef<'a> {
+            sha: &'a str,
+            force: bool,
+        }
+        let route = format!(
+            "/repos/{owner}/{repo}/git/refs/heads/{branch}",
+            owner = self.owner,
+        );
+        let result: octocrab::Result<models::repos::Ref> = self
+            .inner
+            .patch(route, Some(&UpdateRef { sha, force: false }))
+            .await;
+        match result {
+            Ok(_) => Ok(true),
+            // "Update is not a fast forward"
+            Err(octocrab::Error::GitHub { source, .. })
+                if source.status_code == http::StatusCode::UNPROCESSABLE_ENTITY =>
+            {
+                Ok(false)
+            }
+            Err(err) => {
+                Err(err).with_context(|| format!("Could not fast-forward branch '{branch}'"))
+            }
+        }
+    }
 
-    /// Extra headers to add to each request made to GitHub's API.
-    #[serde(default)]
-    pub extra_headers: Vec<(String, String)>,
-}
+    /// Whether the user is a collaborator of the repo. The result is cached for the whole run.
+    pub(crate) async fn is_collaborator(&self, repo: &str, user: &str) -> Result<bool> {
+        let key = (repo.to_owned(), user.to_owned());
+        if let Some(cached) = self.collaborators.borrow().get(&key) {
+            return Ok(*cached);
+        }
+        let route = format!("/repos/{}/{repo}/collaborators/{user}", self.owner);
+        let is_collaborator = self
+            .with_retries(|| async {
+                let response = self.inner._get(route.as_str()).await?;
+                if let Some(rate_limit) = RateLimit::from_headers(response.headers()) {
+                    self.throttle.observe(rate_limit.remaining);
+                }
+                // Non-collaborators get a 404, any other error doesn't tell.
+                let status = response.status();
+                if status == http::StatusCode::NOT_FOUND {
+                    return Ok(false);
+                }
+                if !status.is_success() {
+                    octocrab::map_github_error(response).await?;
+                }
+                Ok(true)
+            })
+            .await
+            .context("Could not check whether user is a collaborator")?;
+        self.collaborators.borrow_mut().insert(key, is_collaborator);
+        Ok(is_collaborator)
+    }
 
-impl fmt::Debug for Config {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let Config {
-            owner,
-            repos,
-            dry_run,
-            github_api_base,
-            // not included since it contains secrets that we don't want in logs
-            extra_headers: _,
-        } = self;
+    /// Get the check runs for a commit, other than the octobors one.
+    pub async fn get_check_runs(
+        &self,
+        repo: &str,
+        sha: &str,
+    ) -> Result<Vec<models::checks::CheckRun>> {
+        let check_runs = self
+            .with_retries(|| async {
+                self.inner
+                    .checks(&self.owner, repo)
+                    .list_check_runs_for_git_ref(Commitish(sha.to_owned()))
+                    .per_page(100)
+                    .send()
+                    .await
+            })
+            .await
+            .context("Could not get check runs for commit")?
+            .check_runs
+            .into_iter()
+            .filter(|check_run| check_run.name != CHECK_RUN_NAME)
+            .collect();
+        Ok(check_runs)
+    }
 
-        f.debug_struct("Config")
-            .field("owner", owner)
-            .field("repos", repos)
-            .field("dry_run", dry_run)
-            .field("github_api_base", github_api_base)
-            .field("extra_headers", &"[REDACTED]")
-            .finish()
+    /// The default branch of the repo. The result is cached for the whole run.
+    pub(crate) async fn get_default_branch(&self, repo: &str) -> Result<String> {
+        if let Some(cached) = self.default_branches.borrow().get(repo) {
+            return Ok(cached.clone());
+        }
+        let default_branch = self
+            .with_retries(|| async { self.inner.repos(&self.owner, repo).get().await })
+            .await
+            .context("Could not get repo")?
+            .default_branch
+            .context("The repo has no default branch")?;
+        self.default_branches
+            .borrow_mut()
+            .insert(repo.to_owned(), default_branch.clone());
+        Ok(default_branch)
     }
-}
 
-#[derive(Debug, serde::Deserialize)]
-pub struct RepoConfig {
-    /// The name of the repo
-    pub name: String,
+    /// The logins of a team's members. The result is cached for the whole run.
+    pub(crate) async fn get_team_members(&self, team: &str) -> Result<Vec<String>> {
+        #[derive(serde::Deserialize)]
+        struct Member {
+            login: String,
+        }
+        #[derive(serde::Serialize)]
+        struct PerPage {
+            per_page: u8,
+        }
 
-    /// The label added when a PR does not have a body
-    pub needs_description_label: Option<String>,
+        if let Some(cached) = self.team_members.borrow().get(team) {
+            return Ok(cached.clone());
+        }
+        let route = format!("/orgs/{}/teams/{team}/members", self.owner);
+        let page: octocrab::Page<Member> = self
+            .with_retries(|| self.inner.get(&route, Some(&PerPage { per_page: 100 })))
+            .await
+            .with_context(|| format!("Could not get the members of team '{team}'"))?;
+        let mut members = Vec::new();
+        let mut page = Some(page);
+        while let Some(previous) = page {
+            let next = previous.next;
+            members.extend(previous.items.into_iter().map(|user| user.login));
+            page = self.with_retries(|| self.inner.get_page(&next)).await?;
+        }
+        self.team_members
+            .borrow_mut()
+            .insert(team.to_owned(), members.clone());
+        Ok(members)
+    }
 
-    /// The list of statuss that are required to be passed for the PR to be
-    /// automerged
-    pub required_statuses: Vec<String>,
+    /// The combined state of the statuses of a branch's head commit. The result is cached for the
+    /// whole run.
+    pub(crate) async fn get_branch_status(
+        &self,
+        repo: &str,
+        branch: &str,
+    ) -> Result<models::StatusState> {
+        #[derive(serde::Deserialize)]
+        struct CombinedStatus {
+            state: models::StatusState,
+        }
 
-    /// The label applied when all of the PR's required status checks have passed
-    pub ci_passed_label: Option<String>,
+        let key = (repo.to_owned(), branch.to_owned());
+        if let Some(cached) = self.branch_statuses.borrow().get(&key) {
+            return Ok(*cached);
+        }
+        let route = format!(
+            "/repos/{owner}/{repo}/commits/{branch}/status",
+            owner = self.owner,
+            branch = utf8_percent_encode(branch, BRANCH_NAME),
+        );
+        let status: CombinedStatus = self
+            .with_retries(|| self.inner.get(&route, None::<&()>))
+            .await
+            .with_context(|| format!("Could not get the status of branch '{branch}'"))?;
+        self.branch_statuses.borrow_mut().insert(key, status.state);
+        Ok(status.state)
+    }
 
-    /// Label applied when a PR has 1 or more reviewers and all of them are accepted
-    pub reviewed_label: Option<String>,
+    /// Creates the octobors check run on a commit, or updates it if it already exists. Without a
+    /// conclusion, the check run is in progress.
+    pub async fn upsert_check_run(
+        &self,
+        repo: &str,
+        sha: &str,
+        conclusion: Option<CheckRunConclusion>,
+        summary: &str,
+    ) -> Result<()> {
+        let checks = self.inner.checks(&self.owner, repo);
+        let existing = checks
+            .list_check_runs_for_git_ref(Commitish(sha.to_owned()))
+            .per_page(100)
+            .send()
+            .await
+            .context("Could not get check runs for commit")?
+            .check_runs
+            .into_iter()
+            .find(|check_run| check_run.name == CHECK_RUN_NAME)
+            // A completed check run can't be put back in progress, a new one replaces it.
+            .filter(|check_run| conclusion.is_some() || check_run.completed_at.is_none());
+        let output = || CheckRunOutput {
+            title: CHECK_RUN_NAME.to_owned(),
+            summary: summary.to_owned(),
+            text: None,
+            annotations: Vec::new(),
+            images: Vec::new(),
+        };
+        match existing {
+            Some(check_run) => {
+                let update = checks.update_check_run(check_run.id).output(output());
+                let update = match conclusion {
+                    Some(conclusion) => update
+                        .status(CheckRunStatus::Completed)
+                        .conclusion(conclusion),
+                    None => update.status(CheckRunStatus::InProgress),
+                };
+                update.send().await.context("Could not update check run")?;
+            }
+            None => {
+                let create = checks
+                    .create_check_run(CHECK_RUN_NAME, sha)
+                    .output(output());
+                let create = match conclusion {
+                    Some(conclusion) => create
+                        .status(CheckRunStatus::Completed)
+                        .conclusion(conclusion),
+                    None => create.status(CheckRunStatus::InProgress),
+                };
+                create.send().await.context("Could not create check run")?;
+            }
+        }
+        Ok(())
+    }
 
-    /// Label that can be manually added to PRs to not block on reviews, for trivial changes
-    ///
-    /// If a reviewer has submitted a request-changes review *before* the bot merged the PR, then
-    /// an approval review will be required, and the PR won't automatically get merged until then.
-    /// If there's a `block_merge_label` set, it has priority over this label being set.
-    pub skip_review_label: Option<String>,
+    /// Sets a commit status on a commit.
+    pub async fn create_status(
+        &self,
+        repo: &str,
+        sha: &str,
+        state: models::StatusState,
+        context: &str,
+        description: &str,
+    ) -> Result<()> {
+        self.inner
+            .repos(&self.owner, repo)
+            .create_status(sha.to_owned(), state)
+            .context(context.to_owned())
+            .description(description.to_owned())
+            .send()
+            .await
+            .context("Could not create commit status")?;
+        Ok(())
+    }
 
-    /// Label that can be manually added to PRs to block automerge
-    pub block_merge_label: Option<String>,
+    /// Requests reviews of a PR from the users.
+    pub async fn request_reviewers(
+        &self,
+        repo: &str,
+        pr_number: u64,
+        reviewers: &[String],
+    ) -> Result<()> {
+        #[derive(serde::Serialize)]
+        struct Request<'a> {
+            reviewers: &'a [String],
+        }
 
-    /// The period in seconds between when a PR can be automerged, and when
-    /// the action actually tries to perform the merge
-    pub automerge_grace_period: Option<u64>,
+        let route = format!(
+            "/repos/{owner}/{repo}/pulls/{pr_number}/requested_reviewers",
+            owner = self.owner
+        );
+        let _: serde::de::IgnoredAny = self
+            .inner
+            .post(route, Some(&Request { reviewers }))
+            .await
+            .context("Could not request reviewers")?;
+        Ok(())
+    }
 
-    /// The method to use for merging the PR, defaults to `merge` if we fail
-    /// to parse or it is unset by the user
-    #[serde(default)]
-    pub merge_method: MergeMethod,
+    /// Get how many commits `base` has that `head` doesn't.
+    pub async fn get_commits_behind(&self, repo: &str, base: &str, head: &str) -> Result<u64> {
+        #[derive(serde::Deserialize)]
+        struct Comparison {
+            behind_by: u64,
+        }
 
-    /// Whether a "comment" review counts as requesting changes. False by default.
-    #[serde(default)]
-    pub comment_requests_change: bool,
+        let route = format!(
+            "/repos/{owner}/{repo}/compare/{base}...{head}",
+            owner = self.owner
+        );
+        let comparison: Comparison = self
+            .with_retries(|| self.inner.get(&route, None::<&()>))
+            .await
+            .context("Could not compare commits")?;
+        Ok(comparison.behind_by)
+    }
+
+    /// Submits a review requesting changes on a PR.
+    pub async fn request_changes(&self, repo: &str, pr_number: u64, body: &str) -> Result<()> {
+        #[derive(serde::Serialize)]
+        struct NewReview<'a> {
+            body: &'a str,
+            event: &'static str,
+        }
+
+        let route = format!(
+            "/repos/{owner}/{repo}/pulls/{pr_number}/reviews",
+            owner = self.owner
+        );
+        let review = NewReview {
+            body,
+            event: "REQUEST_CHANGES",
+        };
+        let _: serde::de::IgnoredAny = self
+            .inner
+            .post(route, Some(&review))
+            .await
+            .context("Could not request changes")?;
+        Ok(())
+    }
+
+    /// Replaces the body of a review on a PR.
+    pub async fn update_review(
+        &self,
+        repo: &str,
+        pr_number: u64,
+        review_id: u64,
+        body: &str,
+    ) -> Result<()> {
+        #[derive(serde::Serialize)]
+        struct Update<'a> {
+            body: &'a str,
+        }
+
+        let route = format!(
+            "/repos/{owner}/{repo}/pulls/{pr_number}/reviews/{review_id}",
+            owner = self.owner
+        );
+        let _: serde::de::IgnoredAny = self
+            .inner
+            .put(route, Some(&Update { body }))
+            .await
+            .context("Could not update review")?;
+        Ok(())
+    }
+
+    /// Dismisses a review on a PR.
+    pub async fn dismiss_review(
+        &self,
+        repo: &str,
+        pr_number: u64,
+        review_id: u64,
+        message: &str,
+    ) -> Result<()> {
+        #[derive(serde::Serialize)]
+        struct Dismissal<'a> {
+            message: &'a str,
+            event: &'static str,
+        }
+
+        let route = format!(
+            "/repos/{owner}/{repo}/pulls/{pr_number}/reviews/{review_id}/dismissals",
+            owner = self.owner
+        );
+        let dismissal = Dismissal {
+            message,
+            event: "DISMISS",
+        };
+        let _: serde::de::IgnoredAny = self
+            .inner
+            .put(route, Some(&dismissal))
+            .await
+            .context("Could not dismiss review")?;
+        Ok(())
+    }
+
+    /// Merges the base branch of a PR into its head branch.
+    pub async fn update_branch(&self, repo: &str, pr_number: u64) -> Result<()> {
+        self.inner
+            .pulls(&self.owner, repo)
+            .update_branch(pr_number)
+            .await
+            .context("Could not update the PR branch")?;
+        Ok(())
+    }
+
+    /// Get all the labels of the repo.
+    pub async fn get_labels(&self, repo: &str) -> Result<Vec<crate::labels::Label>> {
+        let mut labels = Vec::new();
+        let page = self
+            .with_retries(|| async {
+                self.inner
+                    .issues(&self.owner, repo)
+                    .list_labels_for_repo()
+                    .per_page(100)
+                    .send()
+                    .await
+            })
+            .await
+            .context("Could not get the labels of the repo")?;
+        let mut page = Some(page);
+        while let Some(previous) = page {
+            let next = previous.next;
+            labels.extend(
+                previous
+                    .items
+                    .into_iter()
+                    .map(|label| crate::labels::Label {
+                        name: label.name,
+                        color: label.color,
+                        description: label.description.unwrap_or_default(),
+                    }),
+            );
+            page = self.with_retries(|| self.inner.get_page(&next)).await?;
+        }
+        Ok(labels)
+    }
+
+    pub async fn create_label(&self, repo: &str, label: &crate::labels::Label) -> Result<()> {
+        self.inner
+            .issues(&self.owner, repo)
+            .create_label(&label.name, &label.color, &label.description)
+            .await
+            .context("Could not create label")?;
+        Ok(())
+    }
+
+    /// Changes the color and description of an existing label.
+    pub async fn update_label(&self, repo: &str, label: &crate::labels::Label) -> Result<()> {
+        #[derive(serde::Serialize)]
+        struct Update<'a> {
+            color: &'a str,
+            description: &'a str,
+        }
+
+        let route = format!(
+            "/repos/{owner}/{repo}/labels/{name}",
+            owner = self.owner,
+            name = utf8_percent_encode(&label.name, NON_ALPHANUMERIC),
+        );
+        let update = Update {
+            color: &label.color,
+            description: &label.description,
+        };
+        let _: serde::de::IgnoredAny = self
+            .inner
+            .patch(route, Some(&update))
+            .await
+            .context("Could not update label")?;
+        Ok(())
+    }
+
+    /// Reacts to a PR with the given reaction, removing the other reactions of the bot among the
+    /// given ones, so that only the latest is shown.
+    pub async fn set_reaction(
+        &self,
+        repo: &str,
+        pr_number: u64,
+        content: models::reactions::ReactionContent,
+        managed: &[models::reactions::ReactionContent],
+    ) -> Result<()> {
+        let bot_nick = self.get_bot_nick().await?;
+        let reactions = self
+            .with_retries(|| async {
+                self.inner
+                    .issues(&self.owner, repo)
+                    .list_reactions(pr_number)
+                    .per_page(100)
+                    .send()
+                    .await
+            })
+            .await
+            .context("Could not get the reactions to the PR")?
+            .items;
+        let mut present = false;
+        for reaction in reactions {
+            if reaction.user.login != bot_nick {
+                continue;
+            }
+            if reaction.content == content {
+                present = true;
+            } else if managed.contains(&reaction.content) {
+                let route = format!(
+                    "/repos/{owner}/{repo}/issues/{pr_number}/reactions/{id}",
+                    owner = self.owner,
+                    id = reaction.id
+                );
+                self.inner
+                    ._delete(route, None::<&()>)
+                    .await
+                    .context("Could not delete reaction")?;
+            }
+        }
+        if !present {
+            self.inner
+                .issues(&self.owner, repo)
+                .create_reaction(pr_number, content)
+                .await
+                .context("Could not react to the PR")?;
+        }
+        Ok(())
+    }
+
+    /// Marks a draft PR as ready for review. Only the GraphQL API can do it.
+    pub async fn mark_ready_for_review(&self, node_id: &str) -> Result<()> {
+        #[derive(serde::Serialize)]
+        struct Mutation<'a> {
+            query: &'static str,
+            variables: Variables<'a>,
+        }
+        #[derive(serde::Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Variables<'a> {
+            pull_request_id: &'a str,
+        }
+        #[derive(serde::Deserialize)]
+        struct Response {
+            #[serde(default)]
+            errors: Vec<GraphqlError>,
+        }
+        #[derive(serde::Deserialize)]
+        struct GraphqlError {
+            message: String,
+        }
+        let mutation = Mutation {
+            query: "mutation($pullRequestId: ID!) {
+  markPullRequestReadyForReview(input: {pullRequestId: $pullRequestId}) {
+    pullRequest { isDraft }
+  }
+}",
+            variables: Variables {
+                pull_request_id: node_id,
+            },
+        };
+        let response: Response = self
+            .inner
+            .graphql(&mutation)
+            .await
+            .context("Could not mark the PR as ready for review")?;
+        // GraphQL errors are reported in a successful response.
+        if !response.errors.is_empty() {
+            let messages: Vec<_> = response.errors.into_iter().map(|e| e.message).collect();
+            anyhow::bail!(
+                "Could not mark the PR as ready for review: {}",
+                messages.join(", ")
+            );
+        }
+        Ok(())
+    }
+
+    /// Get the state of the latest deployment of a commit, for each environment it was deployed to.
+    pub async fn get_deployment_statuses(
+        &self,
+        repo: &str,
+        sha: &str,
+    ) -> Result<HashMap<String, DeploymentState>> {
+        #[derive(serde::Serialize)]
+        struct Query<'a> {
+            sha: &'a str,
+            per_page: u8,
+        }
+        #[derive(serde::Deserialize)]
+        struct Deployment {
+            id: u64,
+            environment: String,
+        }
+        #[derive(serde::Deserialize)]
+        struct DeploymentStatus {
+            state: DeploymentState,
+        }
+
+        let route = format!("/repos/{owner}/{repo}/deployments", owner = self.owner);
+        // Deployments are listed from the newest to the oldest.
+        let query = Query { sha, per_page: 100 };
+        let deployments: Vec<Deployment> = self
+            .with_retries(|| self.inner.get(&route, Some(&query)))
+            .await
+            .context("Could not get deployments for commit")?;
+
+        let mut states = HashMap::new();
+        for deployment in deployments {
+            if states.contains_key(&deployment.environment) {
+                continue;
+            }
+            let route = format!(
+                "/repos/{owner}/{repo}/deployments/{id}/statuses",
+                owner = self.owner,
+                id = deployment.id,
+            );
+            // Statuses are listed from the newest to the oldest, too.
+            let statuses: Vec<DeploymentStatus> = self
+                .with_retries(|| self.inner.get(&route, None::<&()>))
+                .await
+                .context("Could not get deployment statuses")?;
+            let state = statuses
+                .into_iter()
+                .next()
+                .map_or(DeploymentState::Pending, |status| status.state);
+            states.insert(deployment.environment, state);
+        }
+        Ok(states)
+    }
+
+    /// Finds a comment with the given body that the bot posted on a PR since the given time.
+    pub async fn find_recent_bot_comment(
+        &self,
+        repo: &str,
+        pr_number: u64,
+        body: &str,
+        since: DateTime<Utc>,
+    ) -> Result<Option<u64>> {
+        let bot_nick = self.get_bot_nick().await?;
+        let comments: Vec<_> = self
+            .get_pull_request_comments(repo, pr_number)
+            .await?
+            .into_iter()
+            .flat_map(crate::process::Comment::from_octocrab_comment)
+            .collect();
+        Ok(
+            crate::process::find_identical_comment(&comments, &bot_nick, body, since)
+                .map(|comment| comment.id),
+        )
+    }
+
+    /// Gets how many requests are left before hitting the rate limit, from the headers GitHub
+    /// sends back. Checking it doesn't count against the rate limit.
+    pub async fn get_rate_limit(&self) -> Result<Option<RateLimit>> {
+        let response = self
+            .inner
+            ._get("/rate_limit")
+            .await
+            .context("Could not get the rate limit")?;
+        let rate_limit = RateLimit::from_headers(response.headers());
+        if let Some(rate_limit) = &rate_limit {
+            self.throttle.observe(rate_limit.remaining);
+        }
+        Ok(rate_limit)
+    }
+
+    /// Warns when the token has more scopes than octobors needs. Tokens that aren't classic
+    /// personal access tokens (e.g. of GitHub apps) don't report their scopes.
+    pub async fn audit_token_scopes(&self) -> Result<()> {
+        let response = self
+            .inner
+            ._get("/rate_limit")
+            .await
+            .context("Could not get the token scopes")?;
+        let scopes = match response.headers().get("x-oauth-scopes") {
+            Some(scopes) => scopes.to_str().context("Invalid token scopes")?,
+            None => {
+                log::debug!("No token scopes reported");
+                return Ok(());
+            }
+        };
+        let excess = excess_scopes(scopes, NEEDED_SCOPES);
+        if !excess.is_empty() {
+            log::warn!(
+                "The token has scopes octobors doesn't need, consider removing them: {}",
+                excess.join(", ")
+            );
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn get_bot_nick(&self) -> Result<String> {
+        {
+            let bot_nick = self.bot_nick.borrow();
+            if let Some(cached) = &*bot_nick {
+                return Ok(cached.clone());
+            }
+        }
+        let nick = self
+            .inner
+            .current()
+            .user()
+            .await
+            .context("Getting the bot user id")?
+            .login;
+        *self.bot_nick.borrow_mut() = Some(nick.clone());
+        Ok(nick)
+    }
+}
+
+/// How many requests are left before hitting GitHub's rate limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub remaining: u64,
+    /// When the rate limit is reset.
+    pub reset: DateTime<Utc>,
+}
+
+impl RateLimit {
+    /// Reads the `x-ratelimit-remaining` and `x-ratelimit-reset` headers of a response.
+    fn from_headers(headers: &http::HeaderMap) -> Option<Self> {
+        let header = |name: &str| headers.get(name)?.to_str().ok()?.trim().parse::<u64>().ok();
+        let reset = DateTime::from_timestamp(header("x-ratelimit-reset")? as i64, 0)?;
+        Some(Self {
+            remaining: header("x-ratelimit-remaining")?,
+            reset,
+        })
+    }
+
+    /// Whether fewer requests than the given threshold are left.
+    pub fn is_low(&self, threshold: u64) -> bool {
+        self.remaining < threshold
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeploymentState {
+    Error,
+    Failure,
+    Inactive,
+    InProgress,
+    Queued,
+    Pending,
+    Success,
+    #[serde(other)]
+    Unknown,
+}
+
+/// Configuration options available for the action
+#[derive(serde::Deserialize)]
+pub struct Config {
+    /// The user or organisation that owns the repos
+    pub owner: String,
+
+    /// The repos to be run on, and their config
+    pub repos: Vec<RepoConfig>,
+
+    /// Whether to skip applying the changes or not. Same as `mode = "DryRun"`, which has
+    /// priority over it.
+    #[serde(default)]
+    pub dry_run: bool,
+
+    /// Which of the changes are applied. Defaults to all of them, unless `dry_run` is set.
+    pub mode: Option<Mode>,
+
+    /// The base URL to use GitHub API.  This may be useful if you are using a
+    /// proxy for the GitHub API or an enterprise installation.
+    pub github_api_base: Option<String>,
+
+    /// Extra headers to add to each request made to GitHub's API.
+    #[serde(default)]
+    pub extra_headers: Vec<(String, String)>,
+
+    /// The version of GitHub's REST API to request, e.g. `2022-11-28`, sent in the
+    /// `X-GitHub-Api-Version` header. Features that version lacks aren't used.
+    pub github_api_version: Option<String>,
+
+    /// How many times requests to GitHub's API failing because of transient
+    /// errors are retried. Defaults to 2.
+    pub fetch_retries: Option<u32>,
+
+    /// Up to how many seconds to wait between processing two repos, picked at random, to spread
+    /// the requests made to GitHub's API. There's no delay by default.
+    pub stagger_repos_secs: Option<u64>,
+
+    /// Below how many remaining requests to GitHub's API a warning is logged, after processing
+    /// each repo. The rate limit isn't checked by default.
+    pub rate_limit_warning_threshold: Option<u64>,
+
+    /// A webhook notified of what the bot does to PRs.
+    pub notification_webhook: Option<NotificationWebhook>,
+
+    /// A CSV file to write what was decided for each PR to, at the end of the run.
+    pub report_path: Option<PathBuf>,
+
+    /// Whether to append to the report rather than replacing it on each run.
+    #[serde(default)]
+    pub append_to_report: bool,
+
+    /// An issue whose body is replaced with a table of the evaluated PRs at the end of each run.
+    pub dashboard: Option<Dashboard>,
+}
+
+/// Which changes are applied to PRs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+pub enum Mode {
+    /// Nothing is applied, changes are only logged.
+    DryRun,
+    /// Only comments (and check runs) are posted, labels are left untouched and nothing is merged.
+    Audit,
+    /// Everything is applied.
+    Full,
+}
+
+impl Config {
+    /// Reads the configuration from a TOML file.
+    pub fn load(path: &std::path::Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("could not read {}", path.display()))?;
+        toml::from_str(&contents)
+            .with_context(|| format!("invalid configuration in {}", path.display()))
+    }
+
+    pub fn mode(&self) -> Mode {
+        match self.mode {
+            Some(mode) => mode,
+            None if self.dry_run => Mode::DryRun,
+            None => Mode::Full,
+        }
+    }
+
+    /// The headers to add to each request made to GitHub's API.
+    pub fn request_headers(&self) -> Vec<(String, String)> {
+        let mut headers = self.extra_headers.clone();
+        if let Some(version) = &self.github_api_version {
+            headers.push((API_VERSION_HEADER.to_owned(), version.trim().to_owned()));
+        }
+        headers
+    }
+
+    /// Things in the configuration that are likely mistakes, but don't prevent running.
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if let Some(version) = &self.github_api_version {
+            if chrono::NaiveDate::parse_from_str(version.trim(), "%Y-%m-%d").is_err() {
+                warnings.push(format!(
+                    "`github_api_version` should be a date like 2022-11-28, not `{version}`"
+                ));
+            }
+        }
+        for repo in &self.repos {
+            if repo.mark_ready_when_approved && repo.mark_ready_label.is_none() {
+                warnings.push(format!(
+                    "{}: `mark_ready_when_approved` is set but there's no `mark_ready_label`, so \
+                     no draft is marked as ready",
+                    repo.name
+                ));
+            }
+            if repo.batch_merge && repo.block_merge_label.is_none() {
+                warnings.push(format!(
+                    "{}: `batch_merge` is set but there's no `block_merge_label` to keep PRs that \
+                     broke a batch out of the next ones, so PRs are merged one at a time",
+                    repo.name
+                ));
+            }
+            if repo.ci_passed_label.is_some() && !repo.requires_statuses() {
+                let consequence = if repo.ci_label_requires_statuses {
+                    "so it's never applied"
+                } else {
+                    "so it's applied to all PRs"
+                };
+                warnings.push(format!(
+                    "{}: `ci_passed_label` is set but there are no `required_statuses`, {consequence}",
+                    repo.name
+                ));
+            }
+        }
+        warnings
+    }
+
+    /// The repos whose `active_schedule` is active at the given time.
+    pub fn active_repos(&self, now: DateTime<Utc>) -> Vec<&RepoConfig> {
+        self.repos
+            .iter()
+            .filter(|repo| match &repo.active_schedule {
+                Some(schedule) if !schedule.is_active(now) => {
+                    log::info!("Skipping {}, outside of its active schedule", repo.name);
+                    false
+                }
+                _ => true,
+            })
+            .collect()
+    }
+
+    /// The configuration of the repo with the given name.
+    pub fn repo(&self, name: &str) -> anyhow::Result<&RepoConfig> {
+        self.repos
+            .iter()
+            .find(|repo| repo.name == name)
+            .with_context(|| format!("repo `{name}` isn't in the configuration"))
+    }
+}
+
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Config {
+            owner,
+            repos,
+            dry_run,
+            mode,
+            github_api_base,
+            // not included since it contains secrets that we don't want in logs
+            extra_headers: _,
+            github_api_version,
+            fetch_retries,
+            stagger_repos_secs,
+            rate_limit_warning_threshold,
+            notification_webhook,
+            report_path,
+            append_to_report,
+            dashboard,
+        } = self;
+
+        f.debug_struct("Config")
+            .field("owner", owner)
+            .field("repos", repos)
+            .field("dry_run", dry_run)
+            .field("mode", mode)
+            .field("github_api_base", github_api_base)
+            .field("extra_headers", &"[REDACTED]")
+            .field("github_api_version", github_api_version)
+            .field("fetch_retries", fetch_retries)
+            .field("stagger_repos_secs", stagger_repos_secs)
+            .field("rate_limit_warning_threshold", rate_limit_warning_threshold)
+            .field("notification_webhook", notification_webhook)
+            .field("report_path", report_path)
+            .field("append_to_report", append_to_report)
+            .field("dashboard", dashboard)
+            .finish()
+    }
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+pub struct RepoConfig {
+    /// The name of the repo
+    pub name: String,
+
+    /// When the repo is processed. Outside of it, the repo is skipped entirely, which saves API
+    /// calls. Always by default.
+    pub active_schedule: Option<ActiveSchedule>,
+
+    /// The label added when a PR does not have a body
+    pub needs_description_label: Option<String>,
+
+    /// The template of PR descriptions. PRs whose description only has lines from the
+    /// template are considered as lacking a description.
+    pub description_template: Option<String>,
+
+    /// The list of statuss that are required to be passed for the PR to be
+    /// automerged
+    pub required_statuses: Vec<String>,
+
+    /// Groups of statuses, of which at least one must have all its statuses passed for the PR
+    /// to be automerged, on top of the `required_statuses`.
+    #[serde(default)]
+    pub required_status_groups: Vec<Vec<String>>,
+
+    /// Statuses required on top of the `required_statuses` for PRs targeting some base branches.
+    #[serde(default)]
+    pub base_branch_statuses: Vec<BaseBranchStatuses>,
+
+    /// Globs of statuses that never block PRs, even if they're required, e.g. the best-effort
+    /// legs of a build matrix. See `path_labels` for the syntax.
+    #[serde(default)]
+    pub tolerated_status_patterns: Vec<String>,
+
+    /// Statuses required on top of the `required_statuses` for PRs with some labels, e.g. a slow
+    /// test suite that PRs opt into.
+    #[serde(default)]
+    pub conditional_required_statuses: Vec<LabelStatuses>,
+
+    /// Whether to leave the `ci_passed_label` alone when there are no `required_statuses`,
+    /// rather than applying it to all PRs.
+    #[serde(default)]
+    pub ci_label_requires_statuses: bool,
+
+    /// How the conclusions of check runs are interpreted, by conclusion, e.g. `cancelled`. Check
+    /// runs count as statuses named after them. See [`Verdict::of_conclusion`] for the defaults.
+    #[serde(default)]
+    pub conclusion_map: HashMap<String, Verdict>,
+
+    /// Statuses that block the merge when they're reported on the PR, unless they're successful.
+    #[serde(default)]
+    pub forbidden_statuses: Vec<String>,
+
+    /// Whether forbidden statuses block the merge even when they're successful.
+    #[serde(default)]
+    pub forbid_successful_statuses: bool,
+
+    /// The label applied when all of the PR's required status checks have passed
+    pub ci_passed_label: Option<String>,
+
+    /// Label applied when a PR has 1 or more reviewers and all of them are accepted
+    pub reviewed_label: Option<String>,
+
+    /// Label that can be manually added to PRs to not block on reviews, for trivial changes
+    ///
+    /// If a reviewer has submitted a request-changes review *before* the bot merged the PR, then
+    /// an approval review will be required, and the PR won't automatically get merged until then.
+    /// If there's a `block_merge_label` set, it has priority over this label being set.
+    pub skip_review_label: Option<String>,
+
+    /// Label that can be manually added to PRs to block automerge
+    pub block_merge_label: Option<String>,
+
+    /// Label that must be added to PRs for them to be automerged, when set.
+    ///
+    /// If there's a `block_merge_label` set, it has priority over this label being set.
+    pub automerge_label: Option<String>,
+
+    /// The period in seconds between when a PR can be automerged, and when
+    /// the action actually tries to perform the merge
+    pub automerge_grace_period: Option<u64>,
+
+    /// Up to how many seconds are added to the grace period of each PR, picked from its number,
+    /// so PRs that become ready together are merged one after the other.
+    pub grace_period_jitter_secs: Option<u64>,
+
+    /// Whether PRs that have been ready to merge for longer than the grace period, since their
+    /// last commit, skip the grace period when updated in other ways. When they become ready is
+    /// recorded in a comment.
+    #[serde(default)]
+    pub grace_period_once_per_commit: bool,
+
+    /// The method to use for merging the PR, defaults to `merge` if we fail
+    /// to parse or it is unset by the user
+    #[serde(default)]
+    pub merge_method: MergeMethod,
+
+    /// When `merge_method` is `Squash`, merge PRs with a single commit with
+    /// `single_commit_merge_method` instead, to keep the commit as-is.
+    #[serde(default)]
+    pub squash_only_if_multiple_commits: bool,
+
+    /// How to merge PRs with a single commit, when `squash_only_if_multiple_commits` is set.
+    /// Defaults to `Rebase`.
+    pub single_commit_merge_method: Option<MergeMethod>,
+
+    /// Whether to merge PRs with the GraphQL API, which signs merge commits, rather than with the
+    /// REST API.
+    #[serde(default)]
+    pub signed_merges: bool,
+
+    /// A command to run once a PR has been merged, with its arguments. It gets the
+    /// `OCTOBORS_REPO`, `OCTOBORS_PR`, `OCTOBORS_SHA` and `OCTOBORS_MERGE_SHA` environment
+    /// variables.
+    pub post_merge_command: Option<Vec<String>>,
+
+    /// Whether PRs are merged directly, or added to the merge queue of their base branch.
+    #[serde(default)]
+    pub merge_strategy: MergeStrategy,
+
+    /// How merges done by the bot are recorded on PRs, for other tools to find them.
+    pub record_merges: Option<MergeRecord>,
+
+    /// How many seconds to wait before trying to merge a commit again, after failing to. Failures
+    /// are recorded in a comment on the PR. Merges are retried on each run by default.
+    pub merge_retry_cooldown_secs: Option<u64>,
+
+    /// Whether to react to PRs to tell whether they're going to be merged (🚀), are waiting on
+    /// something like CI (👀), or on a human (😕). False by default.
+    #[serde(default)]
+    pub use_reactions: bool,
+
+    /// Whether to mark draft PRs with the `mark_ready_label` as ready for review, once they're
+    /// approved and their CI passes, so that they get merged. False by default.
+    #[serde(default)]
+    pub mark_ready_when_approved: bool,
+
+    /// The label opting draft PRs in to `mark_ready_when_approved`.
+    pub mark_ready_label: Option<String>,
+
+    /// Whether to create the labels managed by octobors (e.g. the `ci_passed_label`) when they're
+    /// missing from the repo. False by default.
+    #[serde(default)]
+    pub sync_labels: bool,
+
+    /// The color and description of the labels created by `sync_labels`, by label name.
+    #[serde(default)]
+    pub label_styles: HashMap<String, LabelStyle>,
+
+    /// With `sync_labels`, also update the existing labels to match their `label_styles`.
+    #[serde(default)]
+    pub force_label_sync: bool,
+
+    /// The marker of the lines of PR bodies referencing PRs that must be merged first, e.g.
+    /// `octobors-wait-for:` for `octobors-wait-for: org/repo#123`.
+    pub linked_pr_marker: Option<String>,
+
+    /// Merge PRs even if commits were pushed to them since they were analyzed. False by
+    /// default, the merge is skipped until the next run instead.
+    #[serde(default)]
+    pub merge_new_pushes: bool,
+
+    /// Stop trying to merge PRs of the repo for the rest of the run once the bot turns out not to
+    /// be allowed to merge them. False by default.
+    #[serde(default)]
+    pub stop_merging_when_forbidden: bool,
+
+    /// When squash merging, use the PR title as-is for the commit title, and the PR body without
+    /// the PR URL for the commit message. False by default.
+    #[serde(default)]
+    pub squash_pr_title: bool,
+
+    /// Whether `squash_pr_title` should still append the PR number to the commit title. False by
+    /// default.
+    #[serde(default)]
+    pub squash_pr_title_with_number: bool,
+
+    /// The markdown header (e.g. `## Changelog`) of the section of PR bodies used as the merge
+    /// commit's message, rather than the whole body. PRs lacking it use their whole body.
+    pub changelog_section_header: Option<String>,
+
+    /// Whether to end merge commit messages with an `Approved-by:` trailer for each reviewer who
+    /// approved the PR. False by default.
+    #[serde(default)]
+    pub approved_by_trailers: bool,
+
+    /// Whether a "comment" review counts as requesting changes. False by default.
+    #[serde(default)]
+    pub comment_requests_change: bool,
+
+    /// With `comment_requests_change`, whether only comments from reviewers whose review was
+    /// requested count as requesting changes, rather than anyone's. False by default.
+    #[serde(default)]
+    pub comments_only_from_requested_reviewers: bool,
 
     /// Whether the bot should watch for comments asking why it's stuck, and answer them. False by
     /// default.
     #[serde(default)]
     pub react_to_comments: bool,
+
+    /// Whether the "unstable" mergeable state, meaning that a check that isn't required is
+    /// failing, should block the merge. False by default.
+    #[serde(default)]
+    pub block_on_unstable: bool,
+
+    /// Experimental: instead of merging PRs one by one, merge all the ready PRs into an
+    /// integration branch, and fast-forward the base branch to it once its required statuses
+    /// have passed. Needs `block_merge_label`, to keep PRs that broke a batch out of the next
+    /// ones. False by default.
+    #[serde(default)]
+    pub batch_merge: bool,
+
+    /// Whether draft PRs get their labels and comments updated like other PRs. They're never
+    /// merged either way. False by default.
+    #[serde(default)]
+    pub evaluate_drafts: bool,
+
+    /// Whether the bot should maintain a single comment on blocked PRs, explaining why they're
+    /// blocked. The comment is edited as the situation evolves. False by default.
+    #[serde(default)]
+    pub proactive_status_comment: bool,
+
+    /// Whether the bot should request changes with a review listing why PRs are blocked, update
+    /// it when the reasons change, and dismiss it once they aren't. False by default.
+    #[serde(default)]
+    pub block_via_review: bool,
+
+    /// Reviewers requested automatically on PRs that have none and weren't reviewed yet.
+    pub auto_assign_reviewers: Option<AutoAssignReviewers>,
+
+    /// Reviewers whose change requests don't block PRs, e.g. a style bot. They're still mentioned
+    /// when the bot explains why PRs are blocked.
+    #[serde(default)]
+    pub advisory_reviewers: Vec<String>,
+
+    /// The bot accounts whose PRs can be merged, e.g. `dependabot[bot]`. When set, PRs opened
+    /// by other bot accounts aren't, in case they're compromised. Any author by default.
+    pub trusted_authors: Option<Vec<String>>,
+
+    /// A comment posted once on PRs when they're only waiting for the grace period to end
+    /// before being merged.
+    pub ready_comment: Option<String>,
+
+    /// Labels deferring the merge of PRs by `soft_block_period_secs` from when they were added,
+    /// after which PRs are merged anyway unless something else blocks them. The bot warns about
+    /// it with a comment.
+    #[serde(default)]
+    pub soft_block_labels: Vec<String>,
+
+    /// How long, in seconds, soft block labels defer merges. A day by default.
+    pub soft_block_period_secs: Option<u64>,
+
+    /// Who the bot answers to when mentioned, when `react_to_comments` is set. Anyone by default.
+    pub respond_to_authors: Option<AuthorFilter>,
+
+    /// The environments the PR must have been successfully deployed to before being merged
+    #[serde(default)]
+    pub required_deployments: Vec<String>,
+
+    /// An external service that must approve PRs before they're merged.
+    pub external_gate: Option<ExternalGate>,
+
+    /// How long, in seconds, a required status can stay pending before being reported as stuck.
+    /// Stuck statuses still block the merge.
+    pub pending_status_timeout_secs: Option<u64>,
+
+    /// What the grace period is measured from.
+    #[serde(default)]
+    pub grace_anchor: GraceAnchor,
+
+    /// Whether the bot's own label changes are ignored when telling how long ago a PR was last
+    /// active, so they don't restart the grace period or keep the PR from being inactive.
+    #[serde(default)]
+    pub ignore_bot_label_activity: bool,
+
+    /// The order in which open PRs are fetched, and so processed.
+    #[serde(default)]
+    pub fetch_sort: FetchSort,
+
+    /// Whether open PRs are fetched in ascending or descending order.
+    #[serde(default)]
+    pub fetch_direction: FetchDirection,
+
+    /// The maximum number of open PRs fetched each run.
+    pub fetch_limit: Option<usize>,
+
+    /// Labels added to PRs changing files matching a glob.
+    #[serde(default)]
+    pub path_labels: Vec<PathLabel>,
+
+    /// Paths that PRs can only change with more approvals than usual.
+    #[serde(default)]
+    pub sensitive_paths: Vec<SensitivePath>,
+
+    /// How many approvals PRs need depending on their size. When a PR changes sensitive paths
+    /// too, it needs the highest of the required approvals.
+    #[serde(default)]
+    pub size_approvals: Vec<SizeApprovals>,
+
+    /// Labels giving PRs priority, from the highest to the lowest. PRs are analyzed concurrently,
+    /// but merged one at a time in priority order, so PRs with higher priority labels are merged
+    /// first.
+    #[serde(default)]
+    pub priority_labels: Vec<String>,
+
+    /// PRs changing more files than this aren't merged automatically.
+    pub max_changed_files: Option<usize>,
+
+    /// PRs with more added and deleted lines than this aren't merged automatically.
+    pub max_total_changes: Option<usize>,
+
+    /// How long, in seconds, comments identical to one the bot already posted aren't posted again.
+    pub comment_dedup_window_secs: Option<u64>,
+
+    /// How many different people, other than the author, must have reviewed PRs in any way
+    /// (even just commenting).
+    pub min_distinct_reviewers: Option<usize>,
+
+    /// After how many days approvals don't count anymore, even if there were no new commits.
+    pub approval_max_age_days: Option<u64>,
+
+    /// How many approvals override each change request. By default, any change request blocks
+    /// PRs, however many approvals they have.
+    pub approvals_per_change_request: Option<usize>,
+
+    /// Users who must approve every PR, on top of the other reviewers. They can still merge
+    /// their own PRs.
+    #[serde(default)]
+    pub mandatory_reviewers: Vec<String>,
+
+    /// Users who must approve PRs targeting some base branches, on top of the
+    /// `mandatory_reviewers`, e.g. release managers for release branches.
+    #[serde(default)]
+    pub base_branch_mandatory_reviewers: Vec<BaseBranchReviewers>,
+
+    /// Whether PRs must contain all the commits of their base branch to be merged.
+    #[serde(default)]
+    pub require_up_to_date: bool,
+
+    /// Whether only PRs targeting the repo's default branch can be merged.
+    #[serde(default)]
+    pub default_branch_only: bool,
+
+    /// Whether PRs on which no status nor check run (other than octobors' own) was reported are
+    /// blocked, so that untested changes aren't merged when CI wasn't triggered.
+    #[serde(default)]
+    pub require_at_least_one_check: bool,
+
+    /// Whether PRs must be approved by someone who isn't in any of the author's `teams`.
+    #[serde(default)]
+    pub require_cross_team_approval: bool,
+
+    /// The slugs of the org's teams considered by `require_cross_team_approval`.
+    #[serde(default)]
+    pub teams: Vec<String>,
+
+    /// Whether to hold merges while the head of the PR's base branch is failing its checks.
+    #[serde(default)]
+    pub require_base_green: bool,
+
+    /// With `require_up_to_date`, whether to merge the base branch into PR branches that are
+    /// behind it.
+    #[serde(default)]
+    pub auto_update_branch: bool,
+
+    /// Whether to report if PRs can be merged with a check run.
+    #[serde(default)]
+    pub publish_check_run: bool,
+}
+
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct LabelStyle {
+    /// e.g. `0e8a16`
+    pub color: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct PathLabel {
+    /// e.g. `src/api/**`
+    pub glob: String,
+    pub label: String,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct SensitivePath {
+    /// e.g. `infra/**`
+    pub glob: String,
+    /// How many approvals PRs changing matching files need.
+    pub extra_approvals: usize,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct ActiveSchedule {
+    /// The days the schedule applies to, e.g. `["Mon", "Fri"]`. Every day by default.
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+    /// When the repo starts being processed each day, e.g. `"09:00"`.
+    pub start: NaiveTime,
+    /// When the repo stops being processed each day. Schedules ending before they start span
+    /// midnight.
+    pub end: NaiveTime,
+    /// The IANA timezone of `days`, `start` and `end`, e.g. `"Europe/Stockholm"`. UTC by default.
+    #[serde(default = "default_timezone")]
+    pub timezone: chrono_tz::Tz,
+}
+
+fn default_timezone() -> chrono_tz::Tz {
+    chrono_tz::UTC
+}
+
+impl ActiveSchedule {
+    /// Whether the schedule is active at the given time. The part of a schedule spanning
+    /// midnight belongs to the day it started.
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        let local = now.with_timezone(&self.timezone);
+        let (time, day) = (local.time(), local.weekday());
+        let on = |day: Weekday| self.days.is_empty() || self.days.contains(&day);
+        if self.start <= self.end {
+            on(day) && self.start <= time && time < self.end
+        } else {
+            (on(day) && self.start <= time) || (on(day.pred()) && time < self.end)
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct SizeApprovals {
+    /// PRs with at least this many added and deleted lines...
+    pub min_changes: usize,
+    /// ...need at least this many approvals.
+    pub approvals: usize,
+}
+
+#[derive(Clone, serde::Deserialize)]
+pub struct ExternalGate {
+    /// The URL the PR details are posted to.
+    pub url: String,
+
+    /// An extra header to add to the request, e.g. for authentication.
+    pub header: Option<(String, String)>,
+
+    /// How long to wait for an answer, in seconds, before blocking the merge.
+    pub timeout_secs: Option<u64>,
+}
+
+impl fmt::Debug for ExternalGate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ExternalGate {
+            url,
+            // not included since it contains secrets that we don't want in logs
+            header: _,
+            timeout_secs,
+        } = self;
+
+        f.debug_struct("ExternalGate")
+            .field("url", url)
+            .field("header", &"[REDACTED]")
+            .field("timeout_secs", timeout_secs)
+            .finish()
+    }
+}
+
+/// An event of a PR's timeline.
+#[derive(Debug, Clone)]
+pub struct TimelineEvent {
+    pub event: models::Event,
+    pub at: DateTime<Utc>,
+    /// Who caused the event, if known.
+    pub actor: Option<String>,
+    /// The label added or removed, for label changes.
+    pub label: Option<String>,
+    /// The user whose review was requested, or no longer is, for review requests.
+    pub requested_reviewer: Option<String>,
+}
+
+impl TimelineEvent {
+    /// Whether a label was added or removed.
+    pub fn is_label_change(&self) -> bool {
+        matches!(
+            self.event,
+            models::Event::Labeled | models::Event::Unlabeled
+        )
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct AutoAssignReviewers {
+    /// The slug of the org's team the reviewers are picked from, in turn.
+    pub from_team: String,
+    /// How many reviewers are requested.
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Dashboard {
+    /// The repo of the issue.
+    pub repo: String,
+    /// The number of the issue.
+    pub issue: u64,
+}
+
+#[derive(Clone, serde::Deserialize)]
+pub struct NotificationWebhook {
+    /// The URL the notifications are posted to.
+    pub url: String,
+
+    /// The events the webhook is notified of.
+    pub events: Vec<NotificationEvent>,
+}
+
+impl fmt::Debug for NotificationWebhook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let NotificationWebhook {
+            // not included since webhook URLs often contain secrets
+            url: _,
+            events,
+        } = self;
+
+        f.debug_struct("NotificationWebhook")
+            .field("url", &"[REDACTED]")
+            .field("events", events)
+            .finish()
+    }
+}
+
+/// What the bot does to PRs that a webhook can be notified of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum NotificationEvent {
+    /// The PR was merged.
+    Merged,
+    /// The PR became blocked, or is blocked for other reasons than last notified. The notified
+    /// reasons are recorded in a comment on the PR.
+    Blocked,
+    /// The bot commented on the PR.
+    Commented,
+    /// Processing the PR failed.
+    Errored,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(untagged)]
+pub enum AuthorFilter {
+    /// Only the listed users.
+    Users(Vec<String>),
+    /// Only the collaborators of the repo, written as `"collaborators"`.
+    Collaborators(Collaborators),
 }
 
 #[derive(Debug, Clone, Copy, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Collaborators {
+    Collaborators,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
 pub enum MergeMethod {
+    #[default]
     Merge,
     Squash,
     Rebase,
 }
 
-impl Default for MergeMethod {
-    fn default() -> Self {
-        Self::Merge
+impl RepoConfig {
+    /// Whether some statuses must pass for PRs to be merged.
+    pub fn requires_statuses(&self) -> bool {
+        !self.required_statuses.is_empty()
+            || !self.required_status_groups.is_empty()
+            || self
+                .base_branch_statuses
+                .iter()
+                .any(|branch| !branch.statuses.is_empty())
+    }
+
+    /// Whether ready PRs are merged in batches. That needs a `block_merge_label`, as otherwise a
+    /// PR that broke a batch would be batched again and again.
+    pub fn batches_merges(&self) -> bool {
+        self.batch_merge && self.block_merge_label.is_some()
+    }
+
+    /// The labels configured to be added to or removed from PRs, or to be looked for on them.
+    pub fn managed_labels(&self) -> Vec<&str> {
+        let mut labels: Vec<&str> = [
+            &self.needs_description_label,
+            &self.ci_passed_label,
+            &self.reviewed_label,
+            &self.skip_review_label,
+            &self.block_merge_label,
+            &self.automerge_label,
+            &self.mark_ready_label,
+        ]
+        .iter()
+        .filter_map(|label| label.as_deref())
+        .chain(self.path_labels.iter().map(|path| path.label.as_str()))
+        .chain(self.priority_labels.iter().map(String::as_str))
+        .chain(
+            (self.record_merges == Some(MergeRecord::Label)).then_some(crate::merge::MERGED_LABEL),
+        )
+        .collect();
+        labels.sort_unstable();
+        labels.dedup();
+        labels
+    }
+
+    /// The statuses required for PRs targeting the given base branch: the `required_statuses`,
+    /// and those of the matching `base_branch_statuses`.
+    pub fn required_statuses_for(&self, base_ref: &str) -> Vec<String> {
+        let mut statuses = self.required_statuses.clone();
+        let extra = self
+            .base_branch_statuses
+            .iter()
+            .filter(|branch| {
+                crate::path_labels::glob_matches(branch.base_glob.as_bytes(), base_ref.as_bytes())
+            })
+            .flat_map(|branch| &branch.statuses);
+        for status in extra {
+            if !statuses.contains(status) {
+                statuses.push(status.clone());
+            }
+        }
+        statuses
+    }
+
+    /// The users who must approve PRs targeting the given base branch: the
+    /// `mandatory_reviewers`, and those of the matching `base_branch_mandatory_reviewers`.
+    pub fn mandatory_reviewers_for(&self, base_ref: &str) -> Vec<String> {
+        let mut reviewers = self.mandatory_reviewers.clone();
+        let extra = self
+            .base_branch_mandatory_reviewers
+            .iter()
+            .filter(|branch| {
+                crate::path_labels::glob_matches(branch.base_glob.as_bytes(), base_ref.as_bytes())
+            })
+            .flat_map(|branch| &branch.reviewers);
+        for reviewer in extra {
+            if !reviewers.contains(reviewer) {
+                reviewers.push(reviewer.clone());
+            }
+        }
+        reviewers
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct BaseBranchReviewers {
+    /// e.g. `release/*`
+    pub base_glob: String,
+    /// The users who must approve PRs targeting matching branches.
+    pub reviewers: Vec<String>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct BaseBranchStatuses {
+    /// e.g. `release/*`
+    pub base_glob: String,
+    /// The statuses PRs targeting matching branches require, on top of the `required_statuses`.
+    pub statuses: Vec<String>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct LabelStatuses {
+    /// e.g. `needs-e2e`
+    pub label: String,
+    /// The statuses PRs with the label require, on top of the `required_statuses`.
+    pub statuses: Vec<String>,
+}
+
+/// How a check run conclusion is interpreted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+pub enum Verdict {
+    /// Like a successful status.
+    Pass,
+    /// Like a failed status.
+    Fail,
+    /// Like a pending status.
+    Pending,
+    /// As if the check run wasn't reported.
+    Ignore,
+}
+
+impl Verdict {
+    /// The verdict for a check run conclusion, unless configured otherwise. Check runs that
+    /// haven't completed yet have no conclusion, and are pending.
+    pub fn of_conclusion(conclusion: Option<&str>) -> Self {
+        match conclusion {
+            None | Some("stale") => Self::Pending,
+            Some("success" | "neutral" | "skipped") => Self::Pass,
+            // e.g. `failure`, `cancelled`, `timed_out` or `action_required`.
+            Some(_) => Self::Fail,
+        }
+    }
+}
+
+/// How PRs are merged.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+pub enum MergeStrategy {
+    /// By merging them directly.
+    #[default]
+    Direct,
+    /// By adding them to the merge queue of their base branch.
+    AddToMergeQueue,
+}
+
+/// How merges done by the bot are recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+pub enum MergeRecord {
+    /// With a successful commit status on the head of the PR, whose description is the merge
+    /// commit sha.
+    Status,
+    /// With a label on the PR.
+    Label,
+}
+
+/// The order in which open PRs are fetched.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+pub enum FetchSort {
+    /// By creation time.
+    Created,
+    /// By last update time.
+    #[default]
+    Updated,
+    /// By number of comments.
+    Popularity,
+    /// By age, only including PRs updated in the last month.
+    LongRunning,
+}
+
+/// Whether open PRs are fetched in ascending or descending order.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+pub enum FetchDirection {
+    Ascending,
+    #[default]
+    Descending,
+}
+
+/// The moment the automerge grace period starts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+pub enum GraceAnchor {
+    /// The last time the PR was updated.
+    #[default]
+    LastUpdate,
+    /// The last time one of the required statuses was updated, once they all passed.
+    CiCompletion,
+    /// The last time a commit was made or a review was submitted, which ignores updates to the
+    /// PR's metadata, e.g. to its labels.
+    LastCommitOrReview,
+}
+
+impl From<FetchSort> for Sort {
+    fn from(sort: FetchSort) -> Self {
+        match sort {
+            FetchSort::Created => Sort::Created,
+            FetchSort::Updated => Sort::Updated,
+            FetchSort::Popularity => Sort::Popularity,
+            FetchSort::LongRunning => Sort::LongRunning,
+        }
+    }
+}
+
+impl From<FetchDirection> for Direction {
+    fn from(direction: FetchDirection) -> Self {
+        match direction {
+            FetchDirection::Ascending => Direction::Ascending,
+            FetchDirection::Descending => Direction::Descending,
+        }
     }
 }
 
@@ -269,3 +2240,476 @@ impl From<MergeMethod> for octocrab::params::pulls::MergeMethod {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        excess_scopes, Client, Config, DeploymentState, FetchDirection, FetchSort, Mode, RateLimit,
+        NEEDED_SCOPES,
+    };
+    use crate::test_utils::{client, MockServer};
+    use chrono::{DateTime, Utc};
+
+    #[test]
+    fn rate_limit_headers() {
+        let mut headers = http::HeaderMap::new();
+        assert_eq!(RateLimit::from_headers(&headers), None);
+
+        headers.insert("x-ratelimit-remaining", "42".parse().unwrap());
+        assert_eq!(RateLimit::from_headers(&headers), None);
+
+        headers.insert("x-ratelimit-reset", "1700000000".parse().unwrap());
+        let rate_limit = RateLimit::from_headers(&headers).unwrap();
+        assert_eq!(rate_limit.remaining, 42);
+        assert_eq!(rate_limit.reset.timestamp(), 1_700_000_000);
+        assert!(rate_limit.is_low(100));
+        assert!(!rate_limit.is_low(42));
+
+        headers.insert("x-ratelimit-remaining", "lots".parse().unwrap());
+        assert_eq!(RateLimit::from_headers(&headers), None);
+    }
+
+    #[tokio::test]
+    async fn rate_limit_route() {
+        let server = MockServer::new(vec![("200 OK", "{}")]);
+        assert_eq!(client(&server).get_rate_limit().await.unwrap(), None);
+        assert_eq!(server.requests()[0].line, "GET /rate_limit");
+    }
+
+    #[tokio::test]
+    async fn mark_ready_for_review() {
+        let server = MockServer::new(vec![
+            (
+                "200 OK",
+                r#"{"data": {"markPullRequestReadyForReview": {"pullRequest": {"isDraft": false}}}}"#,
+            ),
+            (
+                "200 OK",
+                r#"{"data": null, "errors": [{"message": "Not a draft"}]}"#,
+            ),
+        ]);
+        let client = client(&server);
+        client.mark_ready_for_review("PR_1").await.unwrap();
+        let err = client.mark_ready_for_review("PR_1").await.unwrap_err();
+        assert!(err.to_string().contains("Not a draft"));
+
+        let requests = server.requests();
+        assert_eq!(requests[0].line, "POST /graphql");
+        assert!(requests[0].body.contains("markPullRequestReadyForReview"));
+        assert!(requests[0].body.contains(r#""pullRequestId":"PR_1""#));
+    }
+
+    #[test]
+    fn token_scopes() {
+        assert!(excess_scopes("", NEEDED_SCOPES).is_empty());
+        assert!(excess_scopes("repo", NEEDED_SCOPES).is_empty());
+        assert!(excess_scopes("public_repo, repo:status", NEEDED_SCOPES).is_empty());
+        assert_eq!(
+            excess_scopes("admin:org, repo, delete_repo", NEEDED_SCOPES),
+            vec!["admin:org", "delete_repo"]
+        );
+    }
+
+    #[tokio::test]
+    async fn commits_behind() {
+        let server = MockServer::new(vec![
+            ("200 OK", r#"{"ahead_by": 2, "behind_by": 0}"#),
+            ("200 OK", r#"{"ahead_by": 2, "behind_by": 3}"#),
+        ]);
+        let client = client(&server);
+        assert_eq!(
+            client
+                .get_commits_behind("repo", "main", "abc")
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            client
+                .get_commits_behind("repo", "main", "abc")
+                .await
+                .unwrap(),
+            3
+        );
+        let requests = server.requests();
+        assert_eq!(requests[0].line, "GET /repos/org/repo/compare/main...abc");
+    }
+
+    #[test]
+    fn mode() {
+        let mode = |config: &str| {
+            toml::from_str::<Config>(&format!("owner = \"org\"\nrepos = []\n{config}"))
+                .unwrap()
+                .mode()
+        };
+        assert_eq!(mode(""), Mode::Full);
+        assert_eq!(mode("dry_run = false"), Mode::Full);
+        assert_eq!(mode("dry_run = true"), Mode::DryRun);
+        assert_eq!(mode("mode = \"Audit\""), Mode::Audit);
+        assert_eq!(mode("dry_run = true\nmode = \"Full\""), Mode::Full);
+    }
+
+    #[test]
+    fn ci_label_without_statuses_warning() {
+        let warnings = |repo: &str| {
+            toml::from_str::<Config>(&format!("owner = \"org\"\n[[repos]]\n{repo}"))
+                .unwrap()
+                .warnings()
+        };
+        assert!(warnings("name = \"a\"\nrequired_statuses = []").is_empty());
+        assert!(warnings(
+            "name = \"a\"\nrequired_statuses = [\"test\"]\nci_passed_label = \"ci-passed\""
+        )
+        .is_empty());
+        assert_eq!(
+            warnings("name = \"a\"\nrequired_statuses = []\nci_passed_label = \"ci-passed\""),
+            vec![
+                "a: `ci_passed_label` is set but there are no `required_statuses`, so it's \
+                 applied to all PRs"
+            ]
+        );
+    }
+
+    #[test]
+    fn batch_merge_without_block_label_warning() {
+        let config = |repo: &str| {
+            toml::from_str::<Config>(&format!(
+                "owner = \"org\"\n[[repos]]\nname = \"a\"\nrequired_statuses = []\nbatch_merge = true\n{repo}"
+            ))
+            .unwrap()
+        };
+        let labelled = config("block_merge_label = \"blocked\"");
+        assert!(labelled.warnings().is_empty());
+        assert!(labelled.repos[0].batches_merges());
+        let unlabelled = config("");
+        assert_eq!(
+            unlabelled.warnings(),
+            vec![
+                "a: `batch_merge` is set but there's no `block_merge_label` to keep PRs that \
+                 broke a batch out of the next ones, so PRs are merged one at a time"
+            ]
+        );
+        assert!(!unlabelled.repos[0].batches_merges());
+    }
+
+    #[tokio::test]
+    async fn api_version_header() {
+        let config: Config = toml::from_str(
+            r#"
+            owner = "org"
+            repos = []
+            github_api_version = "2022-11-28"
+            extra_headers = [["x-custom", "value"]]
+            "#,
+        )
+        .unwrap();
+        assert!(config.warnings().is_empty());
+
+        let server = MockServer::new(vec![("200 OK", "{}")]);
+        let client = Client::new(
+            "token".to_owned(),
+            "org".to_owned(),
+            Some(&server.url),
+            &config.request_headers(),
+        )
+        .unwrap();
+        client.get_rate_limit().await.unwrap();
+        let headers = &server.requests()[0].headers;
+        assert!(headers.contains(&("x-github-api-version".to_owned(), "2022-11-28".to_owned())));
+        assert!(headers.contains(&("x-custom".to_owned(), "value".to_owned())));
+    }
+
+    #[test]
+    fn api_version_warnings() {
+        let warnings = |version: &str| {
+            toml::from_str::<Config>(&format!(
+                "owner = \"org\"\ngithub_api_version = \"{version}\"\n\
+                 [[repos]]\nname = \"a\"\nrequired_statuses = []\npublish_check_run = true"
+            ))
+            .unwrap()
+            .warnings()
+        };
+        assert!(warnings("2022-11-28").is_empty());
+        assert_eq!(
+            warnings("v3"),
+            vec!["`github_api_version` should be a date like 2022-11-28, not `v3`"]
+        );
+    }
+
+    #[test]
+    fn active_schedules() {
+        let config: Config = toml::from_str(
+            r#"
+            owner = "org"
+
+            [[repos]]
+            name = "always"
+            required_statuses = []
+
+            [[repos]]
+            name = "office-hours"
+            required_statuses = []
+            active_schedule = { days = ["Mon", "Tue", "Wed", "Thu", "Fri"], start = "09:00", end = "18:00", timezone = "Europe/Helsinki" }
+
+            [[repos]]
+            name = "nightly"
+            required_statuses = []
+            active_schedule = { days = ["Fri"], start = "22:00", end = "02:00" }
+            "#,
+        )
+        .unwrap();
+        let active = |now: &str| {
+            let now = DateTime::parse_from_rfc3339(now)
+                .unwrap()
+                .with_timezone(&Utc);
+            config
+                .active_repos(now)
+                .into_iter()
+                .map(|repo| repo.name.as_str())
+                .collect::<Vec<_>>()
+        };
+        // 2024-03-01 is a Friday.
+        assert_eq!(
+            active("2024-03-01T07:00:00Z"),
+            vec!["always", "office-hours"]
+        );
+        assert_eq!(active("2024-03-01T06:59:00Z"), vec!["always"]);
+        assert_eq!(active("2024-03-01T16:00:00Z"), vec!["always"]);
+        assert_eq!(active("2024-03-01T23:00:00Z"), vec!["always", "nightly"]);
+        assert_eq!(active("2024-03-02T01:59:00Z"), vec!["always", "nightly"]);
+        assert_eq!(active("2024-03-02T08:00:00Z"), vec!["always"]);
+        assert_eq!(active("2024-03-02T23:00:00Z"), vec!["always"]);
+        // Daylight saving time, on Monday 2024-07-01.
+        assert_eq!(
+            active("2024-07-01T06:00:00Z"),
+            vec!["always", "office-hours"]
+        );
+        assert_eq!(active("2024-07-01T05:59:00Z"), vec!["always"]);
+    }
+
+    #[test]
+    fn repo_filter() {
+        let config: Config = toml::from_str(
+            r#"
+            owner = "org"
+            dry_run = true
+
+            [[repos]]
+            name = "first"
+            required_statuses = []
+
+            [[repos]]
+            name = "second"
+            required_statuses = ["test"]
+            "#,
+        )
+        .unwrap();
+        let repo = config.repo("second").unwrap();
+        assert_eq!(repo.name, "second");
+        assert_eq!(repo.required_statuses, vec!["test".to_owned()]);
+        assert!(config.repo("third").is_err());
+    }
+
+    #[tokio::test]
+    async fn pull_request_fetching() {
+        let server = MockServer::new(vec![("200 OK", "[]")]);
+        client(&server)
+            .get_pull_requests("repo", FetchSort::Updated, FetchDirection::Descending, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            server.requests()[0].line,
+            "GET /repos/org/repo/pulls?state=open&sort=updated&direction=desc"
+        );
+
+        let server = MockServer::new(vec![("200 OK", "[]")]);
+        client(&server)
+            .get_pull_requests(
+                "repo",
+                FetchSort::Created,
+                FetchDirection::Ascending,
+                Some(50),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            server.requests()[0].line,
+            "GET /repos/org/repo/pulls?state=open&sort=created&direction=asc&per_page=50&page=1"
+        );
+    }
+
+    #[tokio::test]
+    async fn pull_request_pages() {
+        let page = |numbers: std::ops::Range<u64>| -> &'static str {
+            let prs: Vec<_> = numbers
+                .map(|n| {
+                    format!(
+                        r#"{{
+                            "url": "", "id": {n}, "number": {n},
+                            "head": {{ "ref": "feature", "sha": "somesha" }},
+                            "base": {{ "ref": "main", "sha": "othersha" }}
+                        }}"#
+                    )
+                })
+                .collect();
+            Box::leak(format!("[{}]", prs.join(",")).into_boxed_str())
+        };
+        let server = MockServer::new(vec![("200 OK", page(1..101)), ("200 OK", page(101..201))]);
+        let prs = client(&server)
+            .get_pull_requests(
+                "repo",
+                FetchSort::Created,
+                FetchDirection::Ascending,
+                Some(150),
+            )
+            .await
+            .unwrap();
+        assert_eq!(prs.len(), 150);
+        assert_eq!(prs.last().unwrap().number, 150);
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].line.ends_with("per_page=100&page=1"));
+        assert!(requests[1].line.ends_with("per_page=100&page=2"));
+
+        let server = MockServer::new(vec![("200 OK", page(1..101)), ("200 OK", page(101..103))]);
+        let prs = client(&server)
+            .get_pull_requests("repo", FetchSort::Updated, FetchDirection::Ascending, None)
+            .await
+            .unwrap();
+        assert_eq!(prs.len(), 102);
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn transient_errors_are_retried() {
+        let server = MockServer::new(vec![
+            ("502 Bad Gateway", "<html>Bad gateway</html>"),
+            ("200 OK", "[]"),
+        ]);
+        let reviews = client(&server)
+            .get_pull_request_reviews("repo", 1)
+            .await
+            .unwrap();
+        assert!(reviews.is_empty());
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests
+            .iter()
+            .all(|r| r.line.starts_with("GET /repos/org/repo/pulls/1/reviews")));
+    }
+
+    #[tokio::test]
+    async fn collaborator_checks_are_retried() {
+        let server = MockServer::new(vec![
+            ("502 Bad Gateway", "<html>Bad gateway</html>"),
+            ("204 No Content", ""),
+            ("404 Not Found", r#"{"message": "Not Found"}"#),
+            (
+                "403 Forbidden",
+                r#"{"message": "Resource not accessible by integration"}"#,
+            ),
+        ]);
+        let client = client(&server);
+        assert!(client.is_collaborator("repo", "member").await.unwrap());
+        assert!(!client.is_collaborator("repo", "stranger").await.unwrap());
+        // A forbidden check is neither retried nor taken as a no, nor cached.
+        assert!(client.is_collaborator("repo", "unknown").await.is_err());
+        assert!(!client
+            .collaborators
+            .borrow()
+            .contains_key(&("repo".to_owned(), "unknown".to_owned())));
+        let requests = server.requests();
+        assert_eq!(requests.len(), 4);
+        assert_eq!(requests[0].line, "GET /repos/org/repo/collaborators/member");
+        assert_eq!(requests[1].line, "GET /repos/org/repo/collaborators/member");
+        assert_eq!(
+            requests[2].line,
+            "GET /repos/org/repo/collaborators/stranger"
+        );
+        assert_eq!(
+            requests[3].line,
+            "GET /repos/org/repo/collaborators/unknown"
+        );
+    }
+
+    #[tokio::test]
+    async fn deployment_statuses_are_retried() {
+        let server = MockServer::new(vec![
+            ("502 Bad Gateway", "<html>Bad gateway</html>"),
+            ("200 OK", r#"[{"id": 1, "environment": "production"}]"#),
+            ("502 Bad Gateway", "<html>Bad gateway</html>"),
+            ("200 OK", r#"[{"state": "success"}]"#),
+        ]);
+        let states = client(&server)
+            .get_deployment_statuses("repo", "somesha")
+            .await
+            .unwrap();
+        assert_eq!(states["production"], DeploymentState::Success);
+        let requests = server.requests();
+        assert_eq!(requests.len(), 4);
+        assert_eq!(
+            requests[3].line,
+            "GET /repos/org/repo/deployments/1/statuses"
+        );
+    }
+
+    #[tokio::test]
+    async fn in_progress_check_run() {
+        const CHECK_RUNS: &str = r#"{"total_count": 1, "check_runs": [{
+            "id": 7,
+            "node_id": "CR_7",
+            "head_sha": "somesha",
+            "url": "https://api.github.com/repos/org/repo/check-runs/7",
+            "conclusion": "action_required",
+            "output": {"title": null, "summary": null, "text": null, "annotations_count": 0,
+                       "annotations_url": ""},
+            "completed_at": "2024-01-01T00:00:00Z",
+            "name": "octobors/automerge"
+        }]}"#;
+        const CREATED: &str = r#"{
+            "id": 8,
+            "node_id": "CR_8",
+            "head_sha": "somesha",
+            "url": "https://api.github.com/repos/org/repo/check-runs/8",
+            "conclusion": null,
+            "output": {"title": null, "summary": null, "text": null, "annotations_count": 0,
+                       "annotations_url": ""},
+            "name": "octobors/automerge"
+        }"#;
+        let server = MockServer::new(vec![("200 OK", CHECK_RUNS), ("201 Created", CREATED)]);
+        client(&server)
+            .upsert_check_run("repo", "somesha", None, "Waiting on CI")
+            .await
+            .unwrap();
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        // The completed check run can't be reopened, so a new one is created, and never neutral.
+        assert_eq!(requests[1].line, "POST /repos/org/repo/check-runs");
+        assert!(requests[1].body.contains(r#""status":"in_progress""#));
+        assert!(!requests[1].body.contains("conclusion"));
+    }
+
+    #[tokio::test]
+    async fn other_errors_are_not_retried() {
+        let server = MockServer::new(vec![("404 Not Found", r#"{"message": "Not Found"}"#)]);
+        assert!(client(&server)
+            .get_pull_request_reviews("repo", 1)
+            .await
+            .is_err());
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn retries_are_bounded() {
+        let server = MockServer::new(vec![
+            ("503 Service Unavailable", r#"{"message": "Unavailable"}"#),
+            ("503 Service Unavailable", r#"{"message": "Unavailable"}"#),
+            ("503 Service Unavailable", r#"{"message": "Unavailable"}"#),
+        ]);
+        assert!(client(&server)
+            .get_pull_request_reviews("repo", 1)
+            .await
+            .is_err());
+        assert_eq!(server.requests().len(), 3);
+    }
+}
@@ -0,0 +1,83 @@
+//! An issue whose body lists the PRs evaluated during the last run, across all repos.
+
+use crate::report::Entry;
+use chrono::{DateTime, Utc};
+
+/// Escapes the characters that would break a markdown table cell.
+fn cell(value: &str) -> String {
+    value.replace('|', "\\|").replace('\n', " ")
+}
+
+/// The body of the dashboard issue: a table of the PRs with what was decided for them, and why.
+/// PRs are only shown as merged once the bot actually merged them, otherwise mergeable PRs are
+/// shown as ready.
+pub fn render(owner: &str, entries: &[Entry], updated_at: DateTime<Utc>) -> String {
+    let mut body = format!(
+        "### Automerge dashboard\nLast updated at {}.\n\n",
+        updated_at.format("%Y-%m-%d %H:%M UTC")
+    );
+    if entries.is_empty() {
+        body += "No open PRs.\n";
+        return body;
+    }
+    body += "| PR | Author | Decision | Block reasons |\n| --- | --- | --- | --- |\n";
+    for entry in entries {
+        body += &format!(
+            "| {owner}/{}#{} | @{} | {} | {} |\n",
+            entry.repo,
+            entry.pr,
+            cell(&entry.author),
+            entry.decision.as_str(),
+            cell(&entry.block_reasons.join(", ")),
+        );
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::Decision;
+
+    fn entry(repo: &str, pr: u64, decision: Decision, block_reasons: &[&str]) -> Entry {
+        Entry {
+            repo: repo.to_owned(),
+            pr,
+            author: "author".to_owned(),
+            decision,
+            block_reasons: block_reasons.iter().map(|r| r.to_string()).collect(),
+            timestamp: "2024-01-01T00:00:00Z".parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn dashboard_markdown() {
+        let updated_at = "2024-01-01T12:30:00Z".parse().unwrap();
+        let entries = [
+            entry("api", 1, Decision::Merged, &[]),
+            entry(
+                "api",
+                2,
+                Decision::Blocked,
+                &["CiNotPassing", "ForbiddenStatus { statuses: [\"a|b\"] }"],
+            ),
+            entry("client", 3, Decision::Noop, &["DraftPr"]),
+            entry("client", 4, Decision::Ready, &[]),
+        ];
+        assert_eq!(
+            render("org", &entries, updated_at),
+            "### Automerge dashboard\nLast updated at 2024-01-01 12:30 UTC.\n\n\
+             | PR | Author | Decision | Block reasons |\n\
+             | --- | --- | --- | --- |\n\
+             | org/api#1 | @author | merged |  |\n\
+             | org/api#2 | @author | blocked | CiNotPassing, ForbiddenStatus { statuses: \
+             [\"a\\|b\"] } |\n\
+             | org/client#3 | @author | noop | DraftPr |\n\
+             | org/client#4 | @author | ready |  |\n"
+        );
+        assert_eq!(
+            render("org", &[], updated_at),
+            "### Automerge dashboard\nLast updated at 2024-01-01 12:30 UTC.\n\nNo open PRs.\n"
+        );
+    }
+}
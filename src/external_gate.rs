@@ -0,0 +1,119 @@
+//! Deferring the merge decision to an external approval service.
+//!
+//! The service is sent a JSON `POST` request describing the PR, and must answer
+//! with `{ "approved": true }` for the PR to be merged. Any other answer, or
+//! failing to answer at all, blocks the merge.
+
+use std::time::Duration;
+
+use crate::{context::ExternalGate, process::Pr};
+use anyhow::{Context as _, Result};
+use http::header::HeaderName;
+use tracing as log;
+
+/// Used when the gate doesn't configure a timeout.
+const DEFAULT_TIMEOUT_SECS: u64 = 10;
+
+#[derive(serde::Serialize)]
+struct GateRequest<'a> {
+    owner: &'a str,
+    repo: &'a str,
+    pr: u64,
+    sha: &'a str,
+}
+
+#[derive(serde::Deserialize)]
+struct GateResponse {
+    approved: bool,
+}
+
+async fn query(gate: &ExternalGate, request: &GateRequest<'_>) -> Result<bool> {
+    // Deliberately not reusing the Github client, so that the Github token isn't
+    // sent to the external service.
+    let mut builder = octocrab::OctocrabBuilder::new();
+    if let Some((key, value)) = &gate.header {
+        let name = HeaderName::from_lowercase(key.to_lowercase().as_bytes())?;
+        builder = builder.add_header(name, value.clone());
+    }
+    let client = builder
+        .build()
+        .context("failed to create external gate client")?;
+
+    let timeout = Duration::from_secs(gate.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS));
+    let response: GateResponse =
+        tokio::time::timeout(timeout, client.post(&gate.url, Some(request)))
+            .await
+            .context("external gate timed out")?
+            .context("external gate request failed")?;
+    Ok(response.approved)
+}
+
+/// Asks the external gate whether the PR can be merged. Errors are logged, and
+/// count as the PR not being approved.
+pub async fn approved(gate: &ExternalGate, owner: &str, repo: &str, pr: &Pr) -> bool {
+    let request = GateRequest {
+        owner,
+        repo,
+        pr: pr.number,
+        sha: &pr.commit_sha,
+    };
+    match query(gate, &request).await {
+        Ok(approved) => {
+            if !approved {
+                log::info!("External gate didn't approve the PR");
+            }
+            approved
+        }
+        Err(err) => {
+            log::warn!("External gate failed, blocking the merge: {err:#}");
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{self, MockServer};
+
+    fn gate(server: &MockServer) -> ExternalGate {
+        ExternalGate {
+            url: format!("{}/approve", server.url),
+            header: Some(("authorization".to_owned(), "secret".to_owned())),
+            timeout_secs: Some(5),
+        }
+    }
+
+    #[tokio::test]
+    async fn approved_response() {
+        let server = MockServer::new(vec![("200 OK", r#"{"approved": true}"#)]);
+        assert!(approved(&gate(&server), "org", "repo", &test_utils::pr(42)).await);
+        let requests = server.requests();
+        assert_eq!(requests[0].line, "POST /approve");
+        assert_eq!(
+            requests[0].body,
+            r#"{"owner":"org","repo":"repo","pr":42,"sha":"somesha"}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn denied_response() {
+        let server = MockServer::new(vec![("200 OK", r#"{"approved": false}"#)]);
+        assert!(!approved(&gate(&server), "org", "repo", &test_utils::pr(42)).await);
+        server.requests();
+    }
+
+    #[tokio::test]
+    async fn error_response() {
+        let server = MockServer::new(vec![(
+            "500 Internal Server Error",
+            r#"{"message": "oops"}"#,
+        )]);
+        assert!(!approved(&gate(&server), "org", "repo", &test_utils::pr(42)).await);
+        server.requests();
+
+        let server = MockServer::new(vec![("200 OK", "not json")]);
+        assert!(!approved(&gate(&server), "org", "repo", &test_utils::pr(42)).await);
+        server.requests();
+    }
+}
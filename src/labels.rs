@@ -0,0 +1,173 @@
+//! Making sure the labels managed by octobors exist in the repo, since adding a missing label to
+//! a PR fails.
+
+use std::collections::HashMap;
+
+use crate::context::{Client, LabelStyle, RepoConfig};
+use anyhow::Result;
+use tracing as log;
+
+/// The color of created labels without a configured one.
+const DEFAULT_COLOR: &str = "ededed";
+
+/// A label of the repo.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub name: String,
+    /// e.g. `ededed`
+    pub color: String,
+    pub description: String,
+}
+
+/// A change to the labels of the repo.
+#[derive(Debug, PartialEq, Eq)]
+pub enum LabelChange {
+    Create(Label),
+    /// Change the color and description of an existing label.
+    Update(Label),
+}
+
+/// Colors are compared regardless of their case and of a leading `#`.
+fn normalize_color(color: &str) -> String {
+    color.trim_start_matches('#').to_ascii_lowercase()
+}
+
+/// The changes making sure the managed labels exist, with their configured style. Existing
+/// labels are only updated if `force` is set.
+pub fn changes(
+    managed: &[&str],
+    existing: &[Label],
+    styles: &HashMap<String, LabelStyle>,
+    force: bool,
+) -> Vec<LabelChange> {
+    let mut changes = Vec::new();
+    for name in managed {
+        let style = styles.get(*name);
+        let wanted = |current: Option<&Label>| Label {
+            name: current.map_or(*name, |label| &label.name).to_owned(),
+            color: style
+                .and_then(|style| style.color.as_deref())
+                .map(normalize_color)
+                .or_else(|| current.map(|label| label.color.clone()))
+                .unwrap_or_else(|| DEFAULT_COLOR.to_owned()),
+            description: style
+                .and_then(|style| style.description.clone())
+                .or_else(|| current.map(|label| label.description.clone()))
+                .unwrap_or_default(),
+        };
+        // Github's label names are case insensitive.
+        let current = existing
+            .iter()
+            .find(|label| label.name.to_lowercase() == name.to_lowercase());
+        match current {
+            None => changes.push(LabelChange::Create(wanted(None))),
+            Some(current) if force && style.is_some() => {
+                let wanted = wanted(Some(current));
+                let current = Label {
+                    color: normalize_color(&current.color),
+                    ..current.clone()
+                };
+                if wanted != current {
+                    changes.push(LabelChange::Update(wanted));
+                }
+            }
+            Some(_) => {}
+        }
+    }
+    changes
+}
+
+/// Creates the missing managed labels of the repo, and updates the existing ones if configured.
+pub async fn sync(client: &Client, config: &RepoConfig, dry_run: bool) -> Result<()> {
+    let existing = client.get_labels(&config.name).await?;
+    let managed = config.managed_labels();
+    for change in changes(
+        &managed,
+        &existing,
+        &config.label_styles,
+        config.force_label_sync,
+    ) {
+        if dry_run {
+            log::info!("dry-run {change:?}");
+            continue;
+        }
+        match change {
+            LabelChange::Create(label) => {
+                log::info!("Creating the label '{}'", label.name);
+                client.create_label(&config.name, &label).await?;
+            }
+            LabelChange::Update(label) => {
+                log::info!("Updating the label '{}'", label.name);
+                client.update_label(&config.name, &label).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn label(name: &str, color: &str, description: &str) -> Label {
+        Label {
+            name: name.to_owned(),
+            color: color.to_owned(),
+            description: description.to_owned(),
+        }
+    }
+
+    #[test]
+    fn missing_labels_are_created() {
+        let mut styles = HashMap::new();
+        styles.insert(
+            "ci-passed".to_owned(),
+            LabelStyle {
+                color: Some("#0E8A16".to_owned()),
+                description: Some("CI passed".to_owned()),
+            },
+        );
+        let existing = vec![label("Reviewed", "ff0000", "")];
+
+        assert_eq!(
+            changes(
+                &["reviewed", "ci-passed", "dont-merge"],
+                &existing,
+                &styles,
+                false
+            ),
+            vec![
+                LabelChange::Create(label("ci-passed", "0e8a16", "CI passed")),
+                LabelChange::Create(label("dont-merge", DEFAULT_COLOR, "")),
+            ]
+        );
+        assert!(changes(&["reviewed"], &existing, &styles, true).is_empty());
+    }
+
+    #[test]
+    fn existing_labels_are_only_updated_when_forced() {
+        let mut styles = HashMap::new();
+        styles.insert(
+            "ci-passed".to_owned(),
+            LabelStyle {
+                color: Some("0e8a16".to_owned()),
+                description: None,
+            },
+        );
+        let existing = vec![label("CI-passed", "FF0000", "Checks passed")];
+
+        assert!(changes(&["ci-passed"], &existing, &styles, false).is_empty());
+        assert_eq!(
+            changes(&["ci-passed"], &existing, &styles, true),
+            vec![LabelChange::Update(label(
+                "CI-passed",
+                "0e8a16",
+                "Checks passed"
+            ))]
+        );
+
+        // Already up to date.
+        let existing = vec![label("ci-passed", "0E8A16", "Checks passed")];
+        assert!(changes(&["ci-passed"], &existing, &styles, true).is_empty());
+    }
+}
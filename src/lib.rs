@@ -67,19 +67,37 @@
     rust_2018_idioms
 )]
 // END - Embark standard lints v0.4
-
 // See https://github.com/rust-lang/rust/issues/87417
 #![allow(ungated_async_fn_track_caller)]
 
+mod batch;
 pub mod context;
+mod dashboard;
+mod external_gate;
+mod labels;
 mod merge;
+mod notify;
+mod path_labels;
+mod post_merge;
 pub mod process;
+mod report;
 mod review;
+#[cfg(test)]
+mod test_utils;
+mod throttle;
 
 use anyhow::{Context, Result};
+use context::{Mode, NotificationEvent};
 use log::Instrument;
-use process::{Actions, Analyzer, Pr};
-use std::path::Path;
+use octocrab::models::IssueState;
+use process::{Actions, Analyzer, Decision, Pr};
+use std::{
+    cell::{Cell, RefCell},
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    path::Path,
+    time::Duration,
+};
 use tracing::{self as log, Level};
 
 pub struct Octobors {
@@ -93,35 +111,191 @@ impl Octobors {
             .context("failed to read GITHUB_TOKEN environment variable")?
             .trim()
             .to_string();
-        let contents = std::fs::read_to_string(path)?;
-        let config: context::Config = toml::from_str(contents.as_str())?;
-        let client = context::Client::new(
+        let config = context::Config::load(path)?;
+        for warning in config.warnings() {
+            log::warn!("{warning}");
+        }
+        let mut client = context::Client::new(
             token,
             config.owner.clone(),
             config.github_api_base.as_deref(),
-            config.extra_headers.as_slice(),
+            &config.request_headers(),
         )?;
+        if let Some(retries) = config.fetch_retries {
+            client.retries = retries;
+        }
 
         Ok(Self { config, client })
     }
 
     pub async fn process_all(&self) -> Result<()> {
-        for repo in self.config.repos.iter() {
-            let span = log::span!(Level::INFO, "repo", name = repo.name.as_str());
+        self.process_all_at(chrono::Utc::now()).await
+    }
 
-            RepoProcessor::new(&self.config, &self.client, repo)
-                .process()
-                .instrument(span)
-                .await?;
+    /// Processes the repos whose schedule is active at the given time.
+    async fn process_all_at(&self, now: chrono::DateTime<chrono::Utc>) -> Result<()> {
+        let mut report = Vec::new();
+        let mut failures = Vec::new();
+        let repos = self.config.active_repos(now);
+        for (i, repo) in repos.into_iter().enumerate() {
+            if let (true, Some(max_secs)) = (i > 0, self.config.stagger_repos_secs) {
+                let delay = stagger_delay(max_secs, RandomState::new().build_hasher().finish());
+                log::debug!("Waiting {delay:?} before processing {}", repo.name);
+                tokio::time::sleep(delay).await;
+            }
+            // A failing repo doesn't prevent processing the other ones, nor reporting on them.
+            if let Err(err) = self.process(repo, &mut report).await {
+                failures.push(format!("{}: {err:#}", repo.name));
+            }
+        }
+        self.write_report(&report)?;
+        self.update_dashboard(&report).await?;
+        if !failures.is_empty() {
+            anyhow::bail!("Failed to process repos: {}", failures.join(", "));
+        }
+        Ok(())
+    }
+
+    /// Checks that each repo can be reached with the token, returning the problems found.
+    pub async fn check_repos(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for repo in &self.config.repos {
+            if let Err(err) = self.client.get_default_branch(&repo.name).await {
+                problems.push(format!("{}: {err:#}", repo.name));
+            }
+        }
+        problems
+    }
+
+    /// Only processes the repo with the given name, which must be in the configuration.
+    pub async fn process_repo(&self, name: &str) -> Result<()> {
+        let mut report = Vec::new();
+        let result = self.process(self.config.repo(name)?, &mut report).await;
+        self.write_report(&report)?;
+        result
+    }
+
+    /// Processes the PRs of a repo, adding what was decided for each of them to the report, even
+    /// when failing to process some of them.
+    async fn process(
+        &self,
+        repo: &context::RepoConfig,
+        report: &mut Vec<report::Entry>,
+    ) -> Result<()> {
+        let span = log::span!(Level::INFO, "repo", name = repo.name.as_str());
+
+        let processor = RepoProcessor::new(&self.config, &self.client, repo);
+        let result = processor.process().instrument(span).await;
+        report.extend(processor.report.into_inner());
+        result?;
+        if let Some(threshold) = self.config.rate_limit_warning_threshold {
+            self.check_rate_limit(threshold).await;
         }
         Ok(())
     }
+
+    fn write_report(&self, report: &[report::Entry]) -> Result<()> {
+        match &self.config.report_path {
+            Some(path) => report::write(path, report, self.config.append_to_report),
+            None => Ok(()),
+        }
+    }
+
+    /// Replaces the body of the dashboard issue with the PRs of all the repos. Only done when
+    /// processing all the repos, as the other ones would be missing.
+    async fn update_dashboard(&self, report: &[report::Entry]) -> Result<()> {
+        let Some(dashboard) = &self.config.dashboard else {
+            return Ok(());
+        };
+        let body = dashboard::render(&self.config.owner, report, chrono::Utc::now());
+        if self.config.mode() == Mode::DryRun {
+            log::info!("Would update the dashboard issue #{}", dashboard.issue);
+            return Ok(());
+        }
+        self.client
+            .update_issue_body(&dashboard.repo, dashboard.issue, &body)
+            .await
+    }
+
+    /// Warns when few requests are left before hitting the rate limit.
+    async fn check_rate_limit(&self, threshold: u64) {
+        match self.client.get_rate_limit().await {
+            Ok(Some(rate_limit)) if rate_limit.is_low(threshold) => log::warn!(
+                remaining = rate_limit.remaining,
+                "Only {} requests left before hitting the rate limit, until {}",
+                rate_limit.remaining,
+                rate_limit.reset
+            ),
+            Ok(Some(rate_limit)) => log::debug!(
+                remaining = rate_limit.remaining,
+                "{} requests left before hitting the rate limit",
+                rate_limit.remaining
+            ),
+            Ok(None) => log::debug!("No rate limit reported"),
+            Err(err) => log::warn!("{err:#}"),
+        }
+    }
+}
+
+/// Checks the configuration file without talking to GitHub, returning the likely mistakes it has.
+/// Fails if it can't be loaded at all.
+pub fn check_config(path: &Path) -> Result<Vec<String>> {
+    Ok(context::Config::load(path)?.warnings())
+}
+
+/// A delay of up to `max_secs`, picked from the given random number.
+fn stagger_delay(max_secs: u64, random: u64) -> Duration {
+    Duration::from_millis(random % (max_secs.saturating_mul(1000).saturating_add(1)))
+}
+
+/// How many seconds it took to merge the PR since it was opened.
+fn time_to_merge_seconds(pr: &Pr, merged_at: chrono::DateTime<chrono::Utc>) -> i64 {
+    (merged_at - pr.created_at).num_seconds().max(0)
+}
+
+/// Sorts PRs by the priority of their labels, highest first. PRs with the same priority keep
+/// their order.
+fn sort_by_priority(prs: &mut [Pr], priority_labels: &[String]) {
+    prs.sort_by_key(|pr| {
+        priority_labels
+            .iter()
+            .position(|label| process::has_label(&pr.labels, label).is_some())
+            .unwrap_or(priority_labels.len())
+    });
+}
+
+/// The span of everything related to a PR. Its decision is recorded once it's been analyzed.
+fn pr_span(pr: &Pr) -> log::Span {
+    log::span!(
+        Level::INFO,
+        "pr",
+        number = pr.number,
+        author = pr.author.as_str(),
+        commit_sha = pr.commit_sha.as_str(),
+        decision = log::field::Empty,
+    )
+}
+
+fn log_decision(decision: Decision, block_reasons: &[String]) {
+    let decision = decision.as_str();
+    log::Span::current().record("decision", decision);
+    log::info!(
+        name: "pr_decision",
+        decision,
+        block_reasons = ?block_reasons,
+        "PR decision: {decision}"
+    );
 }
 
 pub struct RepoProcessor<'a> {
     pub config: &'a context::Config,
     pub client: &'a context::Client,
     pub repo_config: &'a context::RepoConfig,
+    /// Whether the bot turned out not to be allowed to merge PRs, with
+    /// `stop_merging_when_forbidden`.
+    merges_forbidden: Cell<bool>,
+    /// What was decided for each processed PR.
+    report: RefCell<Vec<report::Entry>>,
 }
 
 impl<'a> RepoProcessor<'a> {
@@ -134,51 +308,229 @@ impl<'a> RepoProcessor<'a> {
             config,
             client,
             repo_config,
+            merges_forbidden: Cell::new(false),
+            report: RefCell::new(Vec::new()),
         }
     }
 
     pub async fn process(&self) -> Result<()> {
-        let futures = self
+        if self.repo_config.sync_labels {
+            // Audit mode only gives feedback on PRs, it doesn't touch the repo's labels.
+            let dry_run = self.config.mode() != Mode::Full;
+            labels::sync(self.client, self.repo_config, dry_run).await?;
+        }
+        let mut prs: Vec<_> = self
             .client
-            .get_pull_requests(&self.repo_config.name)
+            .get_pull_requests(
+                &self.repo_config.name,
+                self.repo_config.fetch_sort,
+                self.repo_config.fetch_direction,
+                self.repo_config.fetch_limit,
+            )
             .await?
             .into_iter()
-            .map(|pr| {
-                let span = log::span!(Level::INFO, "pr", number = pr.number);
-                self.process_pr(pr).instrument(span)
-            });
-        futures::future::try_join_all(futures).await?;
+            .map(|pr| self.to_pr(pr))
+            .collect();
+        sort_by_priority(&mut prs, &self.repo_config.priority_labels);
+        let futures = prs
+            .iter()
+            .map(|pr| self.process_pr(pr).instrument(pr_span(pr)));
+        let approvers = futures::future::try_join_all(futures).await?;
+
+        if self.repo_config.batches_merges() {
+            let ready: Vec<_> = approvers
+                .iter()
+                .zip(&prs)
+                .filter(|(approvers, _)| approvers.is_some())
+                .map(|(_, pr)| pr.clone())
+                .collect();
+            match self.config.mode() {
+                Mode::DryRun | Mode::Audit => {
+                    log::info!("dry-run batch merge of {} ready PR(s)", ready.len());
+                }
+                Mode::Full => batch::process(self.client, self.repo_config, &prs, &ready).await?,
+            }
+        } else if self.config.mode() == Mode::Full {
+            self.merge_ready(&prs, approvers).await?;
+        }
         Ok(())
     }
 
-    async fn process_pr(&self, pr: octocrab::models::pulls::PullRequest) -> Result<()> {
-        let pr = Pr::from_octocrab_pull_request(pr);
+    /// Merges the PRs ready to be, given with their approvers, one at a time and in order. The PRs
+    /// are analyzed concurrently, so this is what merges them in priority order.
+    async fn merge_ready(&self, prs: &[Pr], approvers: Vec<Option<Vec<String>>>) -> Result<()> {
+        for (pr, approvers) in prs.iter().zip(approvers) {
+            if let Some(approvers) = approvers {
+                self.merge_pr(pr, &approvers)
+                    .instrument(pr_span(pr))
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Fetches a single PR, then analyzes and applies its actions like [`Self::process`] does.
+    /// Returns whether the PR is ready to be merged.
+    pub async fn process_pr_by_number(&self, number: u64) -> Result<bool> {
+        let pr = self
+            .client
+            .get_pull_request(&self.repo_config.name, number)
+            .await?;
+        let pr = self.to_pr(pr);
+        let approvers = self.process_pr(&pr).instrument(pr_span(&pr)).await?;
+        if let (Some(approvers), Mode::Full) = (&approvers, self.config.mode()) {
+            if !self.repo_config.batches_merges() {
+                self.merge_pr(&pr, approvers)
+                    .instrument(pr_span(&pr))
+                    .await?;
+            }
+        }
+        Ok(approvers.is_some())
+    }
+
+    fn to_pr(&self, pr: octocrab::models::pulls::PullRequest) -> Pr {
+        Pr::from_octocrab_pull_request(pr, self.repo_config.description_template.as_deref())
+    }
+
+    /// Analyzes and applies the actions for a PR, except merging it. Returns the approvers of the PR
+    /// if it's ready to be merged.
+    async fn process_pr(&self, pr: &Pr) -> Result<Option<Vec<String>>> {
+        let result = self.analyze_and_apply(pr).await;
+        self.notify_errored(pr, &result).await;
+        result
+    }
+
+    /// Merges a PR found ready by [`Self::process_pr`].
+    async fn merge_pr(&self, pr: &Pr, approvers: &[String]) -> Result<()> {
+        let result = self.merge(pr, approvers).await;
+        self.notify_errored(pr, &result).await;
+        let decision = match result {
+            Ok(true) => Decision::Merged,
+            _ => Decision::Ready,
+        };
+        self.record_decision(pr, decision, &[]);
+        result.map(|_| ())
+    }
+
+    /// Logs what was decided for the PR, and adds it to the report.
+    fn record_decision(&self, pr: &Pr, decision: Decision, block_reasons: &[String]) {
+        log_decision(decision, block_reasons);
+        self.report.borrow_mut().push(report::Entry {
+            repo: self.repo_config.name.clone(),
+            pr: pr.number,
+            author: pr.author.clone(),
+            decision,
+            block_reasons: block_reasons.to_vec(),
+            timestamp: chrono::Utc::now(),
+        });
+    }
+
+    /// Notifies the webhook that processing the PR failed, if it did.
+    async fn notify_errored<T>(&self, pr: &Pr, result: &Result<T>) {
+        if let (Err(err), Mode::Full) = (result, self.config.mode()) {
+            let reasons = [format!("{err:#}")];
+            self.notify(pr, NotificationEvent::Errored, &reasons).await;
+        }
+    }
 
-        let actions = Analyzer::new(&pr, self.client, self.repo_config)
-            .required_actions()
+    async fn analyze_and_apply(&self, pr: &Pr) -> Result<Option<Vec<String>>> {
+        let analysis = Analyzer::new(pr, self.client, self.repo_config)
+            .analyze()
             .await?;
+        // What's decided for PRs the bot merges depends on how merging them goes.
+        let merged_later = analysis.decision == Decision::Ready
+            && self.config.mode() == Mode::Full
+            && !self.repo_config.batches_merges();
+        if !merged_later {
+            self.record_decision(pr, analysis.decision, &analysis.block_reasons);
+        }
+        let actions = analysis.actions;
+        let approvers = actions.merge.then(|| actions.approvers.clone());
 
-        if self.config.dry_run {
-            log::info!("dry-run {:?}", actions);
-        } else {
-            log::info!("applying {:?}", actions);
-            self.apply(actions, &pr).await?;
+        match self.config.mode() {
+            Mode::DryRun => {
+                log::info!("dry-run {:?}", actions);
+            }
+            Mode::Audit => {
+                let actions = actions.audit();
+                log::info!("auditing {:?}", actions);
+                self.apply(actions, pr).await?;
+            }
+            Mode::Full => {
+                log::info!("applying {:?}", actions);
+                self.apply(actions, pr).await?;
+                self.notify_blocked(pr, &analysis.block_reasons).await?;
+            }
         }
 
-        Ok(())
+        Ok(approvers)
+    }
+
+    /// Notifies the webhook that the PR is blocked, unless it already was for the same reasons.
+    /// Nothing is kept between runs, so the reported reasons are recorded in a comment.
+    async fn notify_blocked(&self, pr: &Pr, reasons: &[String]) -> Result<()> {
+        let subscribed = self
+            .config
+            .notification_webhook
+            .as_ref()
+            .is_some_and(|webhook| webhook.events.contains(&NotificationEvent::Blocked));
+        if !subscribed || pr.state == Some(IssueState::Closed) {
+            return Ok(());
+        }
+        let repo = &self.repo_config.name;
+        let bot_nick = self.client.get_bot_nick().await?;
+        let comments: Vec<_> = self
+            .client
+            .get_pull_request_comments(repo, pr.number)
+            .await?
+            .into_iter()
+            .flat_map(process::Comment::from_octocrab_comment)
+            .collect();
+        let record = process::find_blocked_notified_comment(&comments, &bot_nick);
+        let reported = record.as_ref().map_or(&[][..], |(_, reported)| reported);
+        if reported == reasons {
+            return Ok(());
+        }
+        if !reasons.is_empty() {
+            self.notify(pr, NotificationEvent::Blocked, reasons).await;
+        }
+        let body = process::blocked_notified_comment(reasons);
+        match record {
+            Some((comment, _)) => self.client.update_comment(repo, comment.id, &body).await,
+            None if reasons.is_empty() => Ok(()),
+            None => process::post_comment(self.client, repo, pr.number, body).await,
+        }
     }
 
+    /// Notifies the webhook, if any, of an event. Failing to is logged, but not an error.
+    async fn notify(&self, pr: &Pr, event: NotificationEvent, reasons: &[String]) {
+        if let Some(webhook) = &self.config.notification_webhook {
+            notify::send(webhook, &self.repo_config.name, pr.number, event, reasons).await;
+        }
+    }
+
+    /// Applies the actions to the PR, except merging it, which is done once all PRs are analyzed.
     pub async fn apply(&self, actions: Actions, pr: &Pr) -> Result<()> {
+        if actions == Actions::noop() {
+            return Ok(());
+        }
         let mut labels = pr.labels.iter().cloned().collect();
         let client = &self.client;
         let num = pr.number;
 
+        // The PR may have been closed or merged by someone since it was listed.
+        let current = client.get_pull_request(&self.repo_config.name, num).await?;
+        if current.state != Some(IssueState::Open) || current.merged_at.is_some() {
+            log::info!("The PR isn't open anymore, not applying anything");
+            return Ok(());
+        }
+
         process::remove_labels(
             client,
             &self.repo_config.name,
             num,
             &mut labels,
-            actions.remove_labels.into_iter(),
+            actions.remove_labels,
         )
         .await?;
 
@@ -187,19 +539,734 @@ impl<'a> RepoProcessor<'a> {
             &self.repo_config.name,
             num,
             &mut labels,
-            actions.add_labels.into_iter(),
+            actions.add_labels,
         )
         .await?;
 
         for comment in actions.post_comment {
+            if let Some(window) = self.repo_config.comment_dedup_window_secs {
+                let since = chrono::Utc::now() - chrono::Duration::seconds(window as i64);
+                if let Some(id) = client
+                    .find_recent_bot_comment(&self.repo_config.name, num, &comment, since)
+                    .await?
+                {
+                    log::info!("Not posting a comment identical to the recent comment {id}");
+                    continue;
+                }
+            }
             log::debug!("Posting a comment: {comment}");
             process::post_comment(client, &self.repo_config.name, num, comment).await?;
+            if self.config.mode() == Mode::Full {
+                self.notify(pr, NotificationEvent::Commented, &[]).await;
+            }
+        }
+
+        for (comment_id, body) in actions.update_comment {
+            log::debug!("Updating comment {comment_id}: {body}");
+            client
+                .update_comment(&self.repo_config.name, comment_id, &body)
+                .await?;
+        }
+
+        for review_id in actions.dismiss_reviews {
+            log::debug!("Dismissing review {review_id}");
+            client
+                .dismiss_review(
+                    &self.repo_config.name,
+                    num,
+                    review_id,
+                    "This PR isn't blocked anymore.",
+                )
+                .await?;
+        }
+
+        if !actions.request_reviewers.is_empty() {
+            log::info!(
+                "Requesting reviews from {}",
+                actions.request_reviewers.join(", ")
+            );
+            client
+                .request_reviewers(&self.repo_config.name, num, &actions.request_reviewers)
+                .await?;
+        }
+
+        if let Some(body) = actions.request_changes {
+            log::debug!("Requesting changes: {body}");
+            client
+                .request_changes(&self.repo_config.name, num, &body)
+                .await?;
+        }
+
+        if let Some((review_id, body)) = actions.update_review {
+            log::debug!("Updating review {review_id}: {body}");
+            client
+                .update_review(&self.repo_config.name, num, review_id, &body)
+                .await?;
         }
 
-        if actions.merge {
-            log::info!("Attempting to merge");
-            merge::queue(self.client, pr, self.repo_config).await?;
+        if let Some(reaction) = actions.reaction {
+            log::debug!("Reacting with {reaction:?}");
+            let managed: Vec<_> = process::Reaction::ALL
+                .iter()
+                .map(|reaction| reaction.content())
+                .collect();
+            client
+                .set_reaction(&self.repo_config.name, num, reaction.content(), &managed)
+                .await?;
+        }
+
+        if actions.mark_ready {
+            log::info!("Marking the PR as ready for review");
+            client.mark_ready_for_review(&pr.node_id).await?;
         }
+
+        if actions.update_branch {
+            log::info!("Updating the branch");
+            client.update_branch(&self.repo_config.name, num).await?;
+        }
+
+        if let Some((conclusion, summary)) = actions.check_run {
+            log::debug!("Publishing a {conclusion:?} check run");
+            client
+                .upsert_check_run(
+                    &self.repo_config.name,
+                    &pr.commit_sha,
+                    conclusion.conclusion(),
+                    &summary,
+                )
+                .await?;
+        }
+
         Ok(())
     }
+
+    /// Merges the PR, crediting the given approvers, and returns whether it was. In batch mode,
+    /// ready PRs are merged together by [`batch::process`] instead.
+    async fn merge(&self, pr: &Pr, approvers: &[String]) -> Result<bool> {
+        if self.merges_forbidden.get() {
+            log::info!("Not allowed to merge PRs of this repo, not attempting to merge");
+            return Ok(false);
+        }
+        log::info!("Attempting to merge");
+        let outcome = merge::queue(self.client, pr, self.repo_config, approvers).await?;
+        if let Some(record) = self
+            .repo_config
+            .record_merges
+            .and_then(|how| merge::record(&outcome, how))
+        {
+            self.record_merge(pr, record).await?;
+        }
+        let merged = matches!(outcome, merge::Outcome::Merged(_));
+        match outcome {
+            merge::Outcome::Merged(merge_sha) => {
+                let time_to_merge_seconds = time_to_merge_seconds(pr, chrono::Utc::now());
+                log::info!(
+                    name: "pr_merged",
+                    time_to_merge_seconds,
+                    "Merged {time_to_merge_seconds}s after the PR was opened"
+                );
+                self.notify(pr, NotificationEvent::Merged, &[]).await;
+                if let Some(command) = &self.repo_config.post_merge_command {
+                    post_merge::run(command, &self.repo_config.name, pr, &merge_sha).await;
+                }
+            }
+            merge::Outcome::Aborted(reason) => {
+                if self.repo_config.merge_retry_cooldown_secs.is_some() {
+                    self.record_merge_failure(pr, &reason).await?;
+                }
+            }
+            merge::Outcome::Forbidden(reason) => {
+                if self.repo_config.stop_merging_when_forbidden {
+                    self.merges_forbidden.set(true);
+                }
+                self.explain_merge_forbidden(pr, &reason).await?;
+            }
+            merge::Outcome::Enqueued | merge::Outcome::Skipped => {}
+        }
+        Ok(merged)
+    }
+
+    /// Records that the bot merged the PR.
+    async fn record_merge(&self, pr: &Pr, record: merge::Record) -> Result<()> {
+        let repo = &self.repo_config.name;
+        match record {
+            merge::Record::Status { description } => {
+                log::debug!(
+                    "Recording the merge in the {} status",
+                    merge::MERGED_STATUS_CONTEXT
+                );
+                self.client
+                    .create_status(
+                        repo,
+                        &pr.commit_sha,
+                        octocrab::models::StatusState::Success,
+                        merge::MERGED_STATUS_CONTEXT,
+                        &description,
+                    )
+                    .await
+            }
+            merge::Record::Label(label) => {
+                let mut labels = pr.labels.iter().cloned().collect();
+                process::add_labels(self.client, repo, pr.number, &mut labels, [label]).await
+            }
+        }
+    }
+
+    /// Asks humans to merge the PR, since the bot isn't allowed to, unless it already did.
+    async fn explain_merge_forbidden(&self, pr: &Pr, reason: &str) -> Result<()> {
+        let repo = &self.repo_config.name;
+        let bot_nick = self.client.get_bot_nick().await?;
+        let comments: Vec<_> = self
+            .client
+            .get_pull_request_comments(repo, pr.number)
+            .await?
+            .into_iter()
+            .flat_map(process::Comment::from_octocrab_comment)
+            .collect();
+        if process::has_merge_forbidden_comment(&comments, &bot_nick) {
+            return Ok(());
+        }
+        let body = process::merge_forbidden_comment(reason);
+        process::post_comment(self.client, repo, pr.number, body).await
+    }
+
+    /// Records a failed merge attempt in a comment, editing the previous one if any, so the next
+    /// runs wait for the cooldown before trying again.
+    async fn record_merge_failure(&self, pr: &Pr, reason: &str) -> Result<()> {
+        let repo = &self.repo_config.name;
+        let bot_nick = self.client.get_bot_nick().await?;
+        let comments: Vec<_> = self
+            .client
+            .get_pull_request_comments(repo, pr.number)
+            .await?
+            .into_iter()
+            .flat_map(process::Comment::from_octocrab_comment)
+            .collect();
+        let body = process::merge_failure_comment(&pr.commit_sha, reason);
+        match process::find_merge_failure_comment(&comments, &bot_nick) {
+            Some(comment) => self.client.update_comment(repo, comment.id, &body).await,
+            None => process::post_comment(self.client, repo, pr.number, body).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        collections::HashMap,
+        fmt,
+        sync::{Arc, Mutex},
+    };
+    use test_utils::pr;
+    use tracing::{
+        field::{Field, Visit},
+        span, Event, Subscriber,
+    };
+    use tracing_subscriber::{layer::Context, prelude::*, registry::LookupSpan, Layer};
+
+    /// Fields recorded by name, as formatted with `Debug`.
+    #[derive(Default)]
+    struct Fields(HashMap<String, String>);
+
+    impl Visit for Fields {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.0.insert(field.name().to_owned(), format!("{value:?}"));
+        }
+    }
+
+    /// Captures the fields of spans and events.
+    #[derive(Clone, Default)]
+    struct Capture {
+        spans: Arc<Mutex<HashMap<span::Id, Fields>>>,
+        events: Arc<Mutex<Vec<(String, Fields)>>>,
+    }
+
+    impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for Capture {
+        fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, _: Context<'_, S>) {
+            let mut fields = Fields::default();
+            attrs.record(&mut fields);
+            self.spans.lock().unwrap().insert(id.clone(), fields);
+        }
+
+        fn on_record(&self, id: &span::Id, values: &span::Record<'_>, _: Context<'_, S>) {
+            values.record(self.spans.lock().unwrap().get_mut(id).unwrap());
+        }
+
+        fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+            let mut fields = Fields::default();
+            event.record(&mut fields);
+            let name = event.metadata().name().to_owned();
+            self.events.lock().unwrap().push((name, fields));
+        }
+    }
+
+    #[test]
+    fn stagger_delay_is_bounded() {
+        assert_eq!(stagger_delay(0, 12345), Duration::ZERO);
+        for random in [0, 1, 999, 1000, 5000, 5001, u64::MAX / 3, u64::MAX].iter() {
+            assert!(stagger_delay(5, *random) <= Duration::from_secs(5));
+        }
+        assert_eq!(stagger_delay(5, 5000), Duration::from_secs(5));
+        assert_eq!(stagger_delay(5, 5001), Duration::ZERO);
+        assert!(stagger_delay(u64::MAX, u64::MAX / 2) > Duration::ZERO);
+    }
+
+    #[test]
+    fn time_to_merge() {
+        let mut pr = pr(42);
+        pr.created_at = "2024-01-01T00:00:00Z".parse().unwrap();
+        let merged_at = "2024-01-02T01:30:15Z".parse().unwrap();
+        assert_eq!(time_to_merge_seconds(&pr, merged_at), 91_815);
+        assert_eq!(time_to_merge_seconds(&pr, pr.created_at), 0);
+        // Clock skew between Github and the bot.
+        let before = "2023-12-31T23:59:59Z".parse().unwrap();
+        assert_eq!(time_to_merge_seconds(&pr, before), 0);
+    }
+
+    #[tokio::test]
+    async fn apply_to_merged_pr() {
+        let server = test_utils::MockServer::new(vec![(
+            "200 OK",
+            r#"{
+                "url": "https://api.github.com/repos/org/repo/pulls/42",
+                "id": 1,
+                "number": 42,
+                "state": "closed",
+                "merged_at": "2024-01-01T00:00:00Z",
+                "head": { "ref": "feature", "sha": "somesha" },
+                "base": { "ref": "main", "sha": "othersha" }
+            }"#,
+        )]);
+        let client = test_utils::client(&server);
+        let config: context::Config = toml::from_str(
+            r#"
+            owner = "org"
+
+            [[repos]]
+            name = "repo"
+            required_statuses = []
+            "#,
+        )
+        .unwrap();
+        let processor = RepoProcessor::new(&config, &client, &config.repos[0]);
+
+        let mut actions = Actions::noop();
+        actions
+            .set_merge(true)
+            .set_label("reviewed", process::Presence::Present)
+            .post_comment("Hello".to_owned());
+        processor.apply(actions, &pr(42)).await.unwrap();
+
+        // Nothing was applied after checking the PR.
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].line, "GET /repos/org/repo/pulls/42");
+    }
+
+    /// The comment recording that the webhook was notified that the PR was blocked by failing CI.
+    const BLOCKED_NOTIFIED_COMMENT: &str = r####"[{
+        "id": 1,
+        "node_id": "IC_1",
+        "url": "https://api.github.com/repos/org/repo/issues/comments/1",
+        "html_url": "https://github.com/org/repo/pull/42",
+        "body": "### Blocked\n- CiNotPassing\n",
+        "user": {
+            "login": "bot",
+            "id": 2,
+            "node_id": "U_2",
+            "avatar_url": "https://github.com/bot.png",
+            "gravatar_id": "",
+            "url": "https://api.github.com/users/bot",
+            "html_url": "https://github.com/bot",
+            "followers_url": "https://api.github.com/users/bot/followers",
+            "following_url": "https://api.github.com/users/bot/following",
+            "gists_url": "https://api.github.com/users/bot/gists",
+            "starred_url": "https://api.github.com/users/bot/starred",
+            "subscriptions_url": "https://api.github.com/users/bot/subscriptions",
+            "organizations_url": "https://api.github.com/users/bot/orgs",
+            "repos_url": "https://api.github.com/users/bot/repos",
+            "events_url": "https://api.github.com/users/bot/events",
+            "received_events_url": "https://api.github.com/users/bot/received_events",
+            "type": "Bot",
+            "site_admin": false,
+            "patch_url": null
+        },
+        "created_at": "2024-01-01T00:00:00Z"
+    }]"####;
+
+    fn webhook_config(server: &test_utils::MockServer) -> context::Config {
+        toml::from_str(&format!(
+            r#"
+            owner = "org"
+            notification_webhook = {{ url = "{}/hook", events = ["Blocked"] }}
+
+            [[repos]]
+            name = "repo"
+            required_statuses = []
+            "#,
+            server.url
+        ))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn blocked_is_notified_when_reasons_change() {
+        let comment = BLOCKED_NOTIFIED_COMMENT
+            .trim_start_matches('[')
+            .trim_end_matches(']');
+        let comment: &'static str = Box::leak(comment.to_owned().into_boxed_str());
+        let server = test_utils::MockServer::new(vec![
+            ("200 OK", BLOCKED_NOTIFIED_COMMENT),
+            ("200 OK", "{}"),
+            ("200 OK", comment),
+        ]);
+        let client = test_utils::client(&server);
+        *client.bot_nick.borrow_mut() = Some("bot".to_owned());
+        let config = webhook_config(&server);
+        let processor = RepoProcessor::new(&config, &client, &config.repos[0]);
+
+        processor
+            .notify_blocked(&pr(42), &["MissingReviews".to_owned()])
+            .await
+            .unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[1].line, "POST /hook");
+        assert!(requests[1].body.contains(r#""reasons":["MissingReviews"]"#));
+        assert_eq!(requests[2].line, "POST /repos/org/repo/issues/comments/1");
+        assert!(requests[2]
+            .body
+            .contains(r"### Blocked\n- MissingReviews\n"));
+    }
+
+    #[tokio::test]
+    async fn blocked_is_not_notified_again() {
+        let server = test_utils::MockServer::new(vec![("200 OK", BLOCKED_NOTIFIED_COMMENT)]);
+        let client = test_utils::client(&server);
+        *client.bot_nick.borrow_mut() = Some("bot".to_owned());
+        let config = webhook_config(&server);
+        let processor = RepoProcessor::new(&config, &client, &config.repos[0]);
+
+        processor
+            .notify_blocked(&pr(42), &["CiNotPassing".to_owned()])
+            .await
+            .unwrap();
+
+        // The reasons were only read back.
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].line, "GET /repos/org/repo/issues/42/comments?");
+    }
+
+    #[tokio::test]
+    async fn inactive_repos_are_skipped() {
+        let server = test_utils::MockServer::new(vec![("200 OK", "[]")]);
+        let config: context::Config = toml::from_str(
+            r#"
+            owner = "org"
+
+            [[repos]]
+            name = "office-hours"
+            required_statuses = []
+            active_schedule = { start = "09:00", end = "18:00", timezone = "Europe/Stockholm" }
+
+            [[repos]]
+            name = "nightly"
+            required_statuses = []
+            active_schedule = { start = "22:00", end = "02:00", timezone = "Europe/Stockholm" }
+            "#,
+        )
+        .unwrap();
+        let octobors = Octobors {
+            client: test_utils::client(&server),
+            config,
+        };
+
+        // 10:00 in Stockholm.
+        let now = "2024-03-01T09:00:00Z".parse().unwrap();
+        octobors.process_all_at(now).await.unwrap();
+
+        // Only the PRs of the active repo were listed.
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0]
+            .line
+            .starts_with("GET /repos/org/office-hours/pulls?"));
+    }
+
+    #[tokio::test]
+    async fn report_is_written_when_a_repo_fails() {
+        let server = test_utils::MockServer::new(vec![
+            ("404 Not Found", r#"{"message": "Not Found"}"#),
+            (
+                "200 OK",
+                r#"[{
+                    "url": "https://api.github.com/repos/org/repo/pulls/42",
+                    "id": 1,
+                    "number": 42,
+                    "state": "closed",
+                    "head": { "ref": "feature", "sha": "somesha" },
+                    "base": { "ref": "main", "sha": "othersha" }
+                }]"#,
+            ),
+        ]);
+        let path = std::env::temp_dir().join(format!(
+            "octobors-failing-repo-report-{}.csv",
+            std::process::id()
+        ));
+        let config: context::Config = toml::from_str(&format!(
+            r#"
+            owner = "org"
+            report_path = "{}"
+
+            [[repos]]
+            name = "missing"
+            required_statuses = []
+
+            [[repos]]
+            name = "repo"
+            required_statuses = []
+            "#,
+            path.display()
+        ))
+        .unwrap();
+        let octobors = Octobors {
+            client: test_utils::client(&server),
+            config,
+        };
+
+        let err = octobors.process_all().await.unwrap_err();
+        assert!(format!("{err:#}").contains("missing: "), "{:#}", err);
+
+        // The other repo was still processed and reported on.
+        assert_eq!(server.requests().len(), 2);
+        let report = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(report.lines().count(), 2);
+        assert!(report.lines().nth(1).unwrap().starts_with("repo,42,"));
+    }
+
+    #[tokio::test]
+    async fn process_pr_by_number() {
+        let server = test_utils::MockServer::new(vec![(
+            "200 OK",
+            r#"{
+                "url": "https://api.github.com/repos/org/repo/pulls/42",
+                "id": 1,
+                "number": 42,
+                "state": "closed",
+                "head": { "ref": "feature", "sha": "somesha" },
+                "base": { "ref": "main", "sha": "othersha" }
+            }"#,
+        )]);
+        let client = test_utils::client(&server);
+        let config: context::Config = toml::from_str(
+            r#"
+            owner = "org"
+
+            [[repos]]
+            name = "repo"
+            required_statuses = []
+            "#,
+        )
+        .unwrap();
+        let processor = RepoProcessor::new(&config, &client, &config.repos[0]);
+
+        // Closed, so there's nothing more to fetch or do.
+        assert!(!processor.process_pr_by_number(42).await.unwrap());
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].line, "GET /repos/org/repo/pulls/42");
+    }
+
+    #[test]
+    fn config_check() {
+        let check = |name: &str, contents: &str| {
+            let path = std::env::temp_dir().join(format!(
+                "octobors-config-check-{}-{name}.toml",
+                std::process::id()
+            ));
+            std::fs::write(&path, contents).unwrap();
+            let problems = check_config(&path);
+            std::fs::remove_file(&path).unwrap();
+            problems
+        };
+
+        let valid = "owner = \"org\"\n[[repos]]\nname = \"repo\"\nrequired_statuses = [\"test\"]";
+        assert!(check("valid", valid).unwrap().is_empty());
+
+        let suspicious = "owner = \"org\"\n[[repos]]\nname = \"repo\"\nrequired_statuses = []\n\
+                          ci_passed_label = \"ci-passed\"";
+        assert_eq!(check("suspicious", suspicious).unwrap().len(), 1);
+
+        let invalid = "owner = \"org\"\n[[repos]]\nrequired_statuses = \"test\"";
+        let err = check("invalid", invalid).unwrap_err();
+        assert!(format!("{err:#}").contains("invalid configuration"));
+
+        assert!(check_config(Path::new("/nonexistent/octobors.toml")).is_err());
+    }
+
+    #[tokio::test]
+    async fn merges_in_priority_order() {
+        let current = |number: u64| -> &'static str {
+            Box::leak(
+                format!(
+                    r#"{{
+                        "url": "https://api.github.com/repos/org/repo/pulls/{number}",
+                        "id": {number},
+                        "number": {number},
+                        "mergeable_state": "clean",
+                        "head": {{ "ref": "feature", "sha": "somesha" }},
+                        "base": {{ "ref": "main", "sha": "othersha" }}
+                    }}"#
+                )
+                .into_boxed_str(),
+            )
+        };
+        let merged = r#"{"sha": "mergesha", "merged": true}"#;
+        let server = test_utils::MockServer::new(vec![
+            ("200 OK", current(2)),
+            ("200 OK", merged),
+            ("200 OK", current(3)),
+            ("200 OK", merged),
+        ]);
+        let client = test_utils::client(&server);
+        let config: context::Config = toml::from_str(
+            r#"
+            owner = "org"
+
+            [[repos]]
+            name = "repo"
+            required_statuses = []
+            priority_labels = ["urgent"]
+            "#,
+        )
+        .unwrap();
+        let processor = RepoProcessor::new(&config, &client, &config.repos[0]);
+
+        let mut urgent = pr(2);
+        urgent.labels.insert("urgent".to_owned());
+        let mut prs = vec![pr(1), pr(3), urgent];
+        sort_by_priority(&mut prs, &config.repos[0].priority_labels);
+        // #1 isn't ready.
+        let approvers = vec![Some(Vec::new()), None, Some(Vec::new())];
+        processor.merge_ready(&prs, approvers).await.unwrap();
+
+        let lines: Vec<_> = server
+            .requests()
+            .into_iter()
+            .map(|request| request.line)
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "GET /repos/org/repo/pulls/2",
+                "PUT /repos/org/repo/pulls/2/merge",
+                "GET /repos/org/repo/pulls/3",
+                "PUT /repos/org/repo/pulls/3/merge",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn decision_follows_the_merge() {
+        let current = r#"{
+            "url": "https://api.github.com/repos/org/repo/pulls/42",
+            "id": 1,
+            "number": 42,
+            "mergeable_state": "clean",
+            "head": { "ref": "feature", "sha": "somesha" },
+            "base": { "ref": "main", "sha": "othersha" }
+        }"#;
+        let server = test_utils::MockServer::new(vec![
+            ("200 OK", current),
+            ("200 OK", r#"{"sha": "mergesha", "merged": true}"#),
+            ("200 OK", current),
+            ("200 OK", r#"{"merged": false, "message": "Not this time"}"#),
+        ]);
+        let client = test_utils::client(&server);
+        let config: context::Config = toml::from_str(
+            r#"
+            owner = "org"
+
+            [[repos]]
+            name = "repo"
+            required_statuses = []
+            "#,
+        )
+        .unwrap();
+        let processor = RepoProcessor::new(&config, &client, &config.repos[0]);
+
+        processor.merge_pr(&pr(42), &[]).await.unwrap();
+        // Github didn't merge it, so it's only reported as ready.
+        processor.merge_pr(&pr(42), &[]).await.unwrap();
+        server.requests();
+
+        let decisions: Vec<_> = processor
+            .report
+            .into_inner()
+            .into_iter()
+            .map(|entry| entry.decision)
+            .collect();
+        assert_eq!(decisions, vec![Decision::Merged, Decision::Ready]);
+    }
+
+    #[test]
+    fn priority_order() {
+        let labeled = |number: u64, label: &str| {
+            let mut pr = pr(number);
+            pr.labels.insert(label.to_owned());
+            pr
+        };
+        let mut prs = vec![
+            pr(1),
+            labeled(2, "low"),
+            labeled(3, "urgent"),
+            pr(4),
+            labeled(5, "high"),
+            labeled(6, "urgent"),
+        ];
+        let priority_labels = vec!["urgent".to_owned(), "high".to_owned()];
+        sort_by_priority(&mut prs, &priority_labels);
+        let numbers: Vec<_> = prs.iter().map(|pr| pr.number).collect();
+        assert_eq!(numbers, vec![3, 6, 5, 1, 2, 4]);
+
+        // Without priorities, the order is kept.
+        sort_by_priority(&mut prs, &[]);
+        let numbers: Vec<_> = prs.iter().map(|pr| pr.number).collect();
+        assert_eq!(numbers, vec![3, 6, 5, 1, 2, 4]);
+    }
+
+    #[test]
+    fn decision_is_logged() {
+        let pr = pr(42);
+        let block_reasons = vec!["CiNotPassing".to_owned(), "MissingReviews".to_owned()];
+
+        let capture = Capture::default();
+        let subscriber = tracing_subscriber::registry().with(capture.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            pr_span(&pr).in_scope(|| log_decision(Decision::Blocked, &block_reasons));
+        });
+
+        let spans = capture.spans.lock().unwrap();
+        let span = &spans.values().next().unwrap().0;
+        assert_eq!(span["number"], "42");
+        assert_eq!(span["author"], "\"author\"");
+        assert_eq!(span["commit_sha"], "\"somesha\"");
+        assert_eq!(span["decision"], "\"blocked\"");
+
+        let events = capture.events.lock().unwrap();
+        let (_, event) = events
+            .iter()
+            .find(|(name, _)| name == "pr_decision")
+            .unwrap();
+        assert_eq!(event.0["decision"], "\"blocked\"");
+        assert_eq!(
+            event.0["block_reasons"],
+            r#"["CiNotPassing", "MissingReviews"]"#
+        );
+    }
 }
@@ -95,20 +95,86 @@ async fn main() -> ExitCode {
     }
 }
 
+const USAGE: &str = "Usage:
+    $ octobors [--repo <name>] path/to/config.toml
+    $ octobors --check [--check-remote] path/to/config.toml";
+
 async fn try_main() -> Result<()> {
-    let app = octobors::Octobors::new(&get_config_path()?)?;
+    let args = Args::parse()?;
+    if args.check {
+        return check(&args).await;
+    }
+    let app = octobors::Octobors::new(&args.config_path)?;
     log::info!("configuration: {:?}", app.config);
-    app.process_all().await
+    if let Err(err) = app.client.audit_token_scopes().await {
+        log::warn!("{err:#}");
+    }
+    match &args.repo {
+        Some(repo) => app.process_repo(repo).await,
+        None => app.process_all().await,
+    }
 }
 
-fn get_config_path() -> Result<PathBuf> {
-    let mut args = std::env::args();
-    let _ = args.next();
-    let path = args.next().context(
-        "Missing config file path command line argument
+/// Checks the configuration rather than processing PRs, and fails if it has problems.
+async fn check(args: &Args) -> Result<()> {
+    let mut problems = octobors::check_config(&args.config_path)?;
+    // Only reaching GitHub requires a token.
+    if args.check_remote {
+        let app = octobors::Octobors::new(&args.config_path)?;
+        problems.extend(app.check_repos().await);
+    }
+    for problem in &problems {
+        log::error!("{problem}");
+    }
+    anyhow::ensure!(
+        problems.is_empty(),
+        "found {} problem(s) in the configuration",
+        problems.len()
+    );
+    log::info!("The configuration is valid");
+    Ok(())
+}
+
+struct Args {
+    config_path: PathBuf,
+    /// Only process this repo.
+    repo: Option<String>,
+    /// Only check the configuration.
+    check: bool,
+    /// Also check that the repos can be reached, with `check`.
+    check_remote: bool,
+}
 
-Usage:
-    $ octobors path/to/config.toml",
-    )?;
-    Ok(PathBuf::from(path))
+impl Args {
+    fn parse() -> Result<Self> {
+        let mut config_path = None;
+        let mut repo = None;
+        let mut check = false;
+        let mut check_remote = false;
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            if arg == "--check" {
+                check = true;
+            } else if arg == "--check-remote" {
+                check = true;
+                check_remote = true;
+            } else if arg == "--repo" {
+                repo = Some(
+                    args.next()
+                        .with_context(|| format!("Missing repo name after --repo\n\n{USAGE}"))?,
+                );
+            } else {
+                config_path = Some(PathBuf::from(arg));
+            }
+        }
+        let config_path = config_path.with_context(|| {
+            format!("Missing config file path command line argument\n\n{USAGE}")
+        })?;
+        Ok(Self {
+            config_path,
+            repo,
+            check,
+            check_remote,
+        })
+    }
 }
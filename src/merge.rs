@@ -1,8 +1,10 @@
+use crate::context::{MergeMethod, MergeRecord, MergeStrategy};
+use octocrab::models::pulls::MergeableState;
 use tracing as log;
 
 /// Removes HTML comments (in the form of <!-- comments -->) from the given string.
 /// If running into nested comments, aborts and returns the initial string.
-fn remove_html_comments(body: String) -> String {
+pub(crate) fn remove_html_comments(body: String) -> String {
     let mut result = String::new();
     let mut unfinished_comment = false;
 
@@ -59,13 +61,503 @@ fn format_commit_message(body: String, html_url: String) -> String {
     format!("{}\n\n{}", body, html_url)
 }
 
-/// Queues the pull request for merging
+/// The text of the section of a markdown document under the given header, up to the next header
+/// of the same or a higher level, if it isn't empty.
+fn markdown_section(body: &str, header: &str) -> Option<String> {
+    let header = header.trim();
+    let level = |line: &str| line.len() - line.trim_start_matches('#').len();
+    let header_level = level(header);
+    let section = body
+        .lines()
+        .skip_while(|line| line.trim() != header)
+        .skip(1)
+        .take_while(|line| {
+            let line = line.trim_start();
+            let line_level = level(line);
+            // e.g. `#hashtag` isn't a header.
+            let is_header = line_level > 0 && line[line_level..].starts_with(' ');
+            !is_header || line_level > header_level
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let section = section.trim();
+    if section.is_empty() {
+        None
+    } else {
+        Some(section.to_owned())
+    }
+}
+
+/// Builds the title and message of the commit created when merging the PR.
+///
+/// By default, the title is the PR title followed by the PR number, and the
+/// message is the PR body followed by the PR URL. When squash merging with
+/// `squash_pr_title` set, the title is the PR title alone (unless
+/// `squash_pr_title_with_number` is set), and the message is only the PR body.
+fn commit_title_and_message(
+    config: &crate::context::RepoConfig,
+    method: MergeMethod,
+    pr_number: u64,
+    title: String,
+    body: Option<String>,
+    html_url: String,
+) -> (String, String) {
+    let body = match (&config.changelog_section_header, body) {
+        (Some(header), Some(body)) => {
+            let body = remove_html_comments(body);
+            Some(markdown_section(&body, header).unwrap_or(body))
+        }
+        (_, body) => body,
+    };
+    if matches!(method, MergeMethod::Squash) && config.squash_pr_title {
+        let title = if config.squash_pr_title_with_number {
+            format!("{} (#{})", title, pr_number)
+        } else {
+            title
+        };
+        let message = body.map(remove_html_comments).unwrap_or_default();
+        return (title, message);
+    }
+
+    let title = format!("{} (#{})", title, pr_number);
+    let message = match body {
+        Some(body) => format_commit_message(body, html_url),
+        None => html_url,
+    };
+    (title, message)
+}
+
+/// Ends the commit message with an `Approved-by:` trailer for each of the approvers.
+fn with_approved_by_trailers(message: String, approvers: &[String]) -> String {
+    if approvers.is_empty() {
+        return message;
+    }
+    let trailers = approvers
+        .iter()
+        .map(|user| format!("Approved-by: {user}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    if message.trim().is_empty() {
+        trailers
+    } else {
+        format!("{}\n\n{trailers}", message.trim_end())
+    }
+}
+
+/// A PR as returned by the API, along with what octocrab's model lacks.
+#[derive(serde::Deserialize)]
+struct CurrentPr {
+    #[serde(flatten)]
+    pr: octocrab::models::pulls::PullRequest,
+    /// Set when someone enabled GitHub's native auto-merge on the PR.
+    auto_merge: Option<serde::de::IgnoredAny>,
+}
+
+const MERGE_MUTATION: &str = "mutation($pullRequestId: ID!, $expectedHeadOid: GitObjectID!, \
+$commitHeadline: String!, $commitBody: String!, $mergeMethod: PullRequestMergeMethod!) {
+  mergePullRequest(input: {pullRequestId: $pullRequestId, expectedHeadOid: $expectedHeadOid, \
+commitHeadline: $commitHeadline, commitBody: $commitBody, mergeMethod: $mergeMethod}) {
+    pullRequest { mergeCommit { oid } }
+  }
+}";
+
+#[derive(Debug, PartialEq, Eq, serde::Serialize)]
+struct MergeMutation<'a> {
+    query: &'static str,
+    variables: MergeVariables<'a>,
+}
+
+#[derive(Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MergeVariables<'a> {
+    pull_request_id: &'a str,
+    expected_head_oid: &'a str,
+    commit_headline: &'a str,
+    commit_body: &'a str,
+    merge_method: &'static str,
+}
+
+fn merge_mutation<'a>(
+    node_id: &'a str,
+    sha: &'a str,
+    title: &'a str,
+    message: &'a str,
+    method: MergeMethod,
+) -> MergeMutation<'a> {
+    MergeMutation {
+        query: MERGE_MUTATION,
+        variables: MergeVariables {
+            pull_request_id: node_id,
+            expected_head_oid: sha,
+            commit_headline: title,
+            commit_body: message,
+            merge_method: match method {
+                MergeMethod::Merge => "MERGE",
+                MergeMethod::Squash => "SQUASH",
+                MergeMethod::Rebase => "REBASE",
+            },
+        },
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct GraphqlResponse<T> {
+    data: Option<T>,
+    #[serde(default)]
+    errors: Vec<GraphqlError>,
+}
+
+#[derive(serde::Deserialize)]
+struct GraphqlError {
+    message: String,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MergeData {
+    merge_pull_request: Option<MergePayload>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MergePayload {
+    pull_request: MergedPullRequest,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MergedPullRequest {
+    merge_commit: Option<MergeCommit>,
+}
+
+#[derive(serde::Deserialize)]
+struct MergeCommit {
+    oid: String,
+}
+
+/// Merges a PR with the GraphQL API, which signs the merge commit. Returns the merge commit sha.
+async fn graphql_merge(
+    client: &crate::context::Client,
+    mutation: &MergeMutation<'_>,
+) -> anyhow::Result<String> {
+    // GraphQL errors are reported in a successful response.
+    let response: GraphqlResponse<MergeData> = client.inner.graphql(mutation).await?;
+    if !response.errors.is_empty() {
+        let messages: Vec<_> = response.errors.into_iter().map(|e| e.message).collect();
+        anyhow::bail!("{}", messages.join(", "));
+    }
+    // Without a merge commit, the PR can't be told apart from one that wasn't merged.
+    response
+        .data
+        .and_then(|data| data.merge_pull_request)
+        .and_then(|payload| payload.pull_request.merge_commit)
+        .map(|commit| commit.oid)
+        .ok_or_else(|| anyhow::anyhow!("No merge commit was returned"))
+}
+
+const ENQUEUE_MUTATION: &str = "mutation($pullRequestId: ID!, $expectedHeadOid: GitObjectID!) {
+  enqueuePullRequest(input: {pullRequestId: $pullRequestId, expectedHeadOid: $expectedHeadOid}) {
+    mergeQueueEntry { position }
+  }
+}";
+
+#[derive(Debug, PartialEq, Eq, serde::Serialize)]
+struct EnqueueMutation<'a> {
+    query: &'static str,
+    variables: EnqueueVariables<'a>,
+}
+
+#[derive(Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EnqueueVariables<'a> {
+    pull_request_id: &'a str,
+    expected_head_oid: &'a str,
+}
+
+fn enqueue_mutation<'a>(node_id: &'a str, sha: &'a str) -> EnqueueMutation<'a> {
+    EnqueueMutation {
+        query: ENQUEUE_MUTATION,
+        variables: EnqueueVariables {
+            pull_request_id: node_id,
+            expected_head_oid: sha,
+        },
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EnqueueData {
+    enqueue_pull_request: Option<EnqueuePayload>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EnqueuePayload {
+    merge_queue_entry: Option<MergeQueueEntry>,
+}
+
+#[derive(serde::Deserialize)]
+struct MergeQueueEntry {
+    position: Option<u64>,
+}
+
+/// Adds a PR to the merge queue of its base branch. Returns its position in the queue.
+async fn enqueue(
+    client: &crate::context::Client,
+    mutation: &EnqueueMutation<'_>,
+) -> anyhow::Result<Option<u64>> {
+    let response: GraphqlResponse<EnqueueData> = client.inner.graphql(mutation).await?;
+    if !response.errors.is_empty() {
+        let messages: Vec<_> = response.errors.into_iter().map(|e| e.message).collect();
+        anyhow::bail!("{}", messages.join(", "));
+    }
+    Ok(response
+        .data
+        .and_then(|data| data.enqueue_pull_request)
+        .and_then(|payload| payload.merge_queue_entry)
+        .and_then(|entry| entry.position))
+}
+
+const MERGE_QUEUE_QUERY: &str = "query($owner: String!, $name: String!, $branch: String!) {
+  repository(owner: $owner, name: $name) { mergeQueue(branch: $branch) { id } }
+}";
+
+/// Whether the branch has a merge queue enabled. The result is cached for the whole run.
+async fn has_merge_queue(
+    client: &crate::context::Client,
+    repo: &str,
+    branch: &str,
+) -> anyhow::Result<bool> {
+    let key = (repo.to_owned(), branch.to_owned());
+    if let Some(cached) = client.merge_queues.borrow().get(&key) {
+        return Ok(*cached);
+    }
+    #[derive(serde::Serialize)]
+    struct Query<'a> {
+        query: &'static str,
+        variables: Variables<'a>,
+    }
+    #[derive(serde::Serialize)]
+    struct Variables<'a> {
+        owner: &'a str,
+        name: &'a str,
+        branch: &'a str,
+    }
+    #[derive(serde::Deserialize)]
+    struct Data {
+        repository: Option<Repository>,
+    }
+    #[derive(serde::Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Repository {
+        merge_queue: Option<serde::de::IgnoredAny>,
+    }
+
+    let query = Query {
+        query: MERGE_QUEUE_QUERY,
+        variables: Variables {
+            owner: &client.owner,
+            name: repo,
+            branch,
+        },
+    };
+    let response: GraphqlResponse<Data> = client.inner.graphql(&query).await?;
+    if !response.errors.is_empty() {
+        let messages: Vec<_> = response.errors.into_iter().map(|e| e.message).collect();
+        anyhow::bail!("{}", messages.join(", "));
+    }
+    let has_merge_queue = response
+        .data
+        .and_then(|data| data.repository)
+        .is_some_and(|repo| repo.merge_queue.is_some());
+    client
+        .merge_queues
+        .borrow_mut()
+        .insert(key, has_merge_queue);
+    Ok(has_merge_queue)
+}
+
+const MERGE_QUEUE_ENTRY_QUERY: &str = "query($id: ID!) {
+  node(id: $id) { ... on PullRequest { mergeQueueEntry { position } } }
+}";
+
+/// Whether the PR is already in the merge queue.
+async fn is_enqueued(client: &crate::context::Client, node_id: &str) -> anyhow::Result<bool> {
+    #[derive(serde::Serialize)]
+    struct Query<'a> {
+        query: &'static str,
+        variables: Variables<'a>,
+    }
+    #[derive(serde::Serialize)]
+    struct Variables<'a> {
+        id: &'a str,
+    }
+    #[derive(serde::Deserialize)]
+    struct Data {
+        node: Option<Node>,
+    }
+    #[derive(serde::Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Node {
+        merge_queue_entry: Option<MergeQueueEntry>,
+    }
+
+    let query = Query {
+        query: MERGE_QUEUE_ENTRY_QUERY,
+        variables: Variables { id: node_id },
+    };
+    let response: GraphqlResponse<Data> = client.inner.graphql(&query).await?;
+    if !response.errors.is_empty() {
+        let messages: Vec<_> = response.errors.into_iter().map(|e| e.message).collect();
+        anyhow::bail!("{}", messages.join(", "));
+    }
+    Ok(response
+        .data
+        .and_then(|data| data.node)
+        .is_some_and(|node| node.merge_queue_entry.is_some()))
+}
+
+/// The method to merge a PR with, given how many commits it has. Single commits are kept as-is
+/// rather than squashed, if configured so.
+pub(crate) fn merge_method(
+    config: &crate::context::RepoConfig,
+    commits: Option<u64>,
+) -> MergeMethod {
+    match (config.merge_method, commits) {
+        (MergeMethod::Squash, Some(1)) if config.squash_only_if_multiple_commits => config
+            .single_commit_merge_method
+            .unwrap_or(MergeMethod::Rebase),
+        (method, _) => method,
+    }
+}
+
+/// What `queue` should do with a PR, given the mergeable state Github reports for it.
+#[derive(Debug, PartialEq, Eq)]
+enum MergeDecision {
+    /// Go ahead and merge the PR.
+    Merge,
+    /// Don't merge the PR, for the given reason.
+    Abort(String),
+    /// Github hasn't computed the mergeable state yet, poll it again.
+    Retry,
+    /// We don't know about this state, leave the PR alone.
+    Ignore,
+}
+
+fn merge_decision(
+    mergeable_state: Option<&MergeableState>,
+    head_ref: &str,
+    base_ref: &str,
+    config: &crate::context::RepoConfig,
+) -> MergeDecision {
+    match mergeable_state {
+        Some(MergeableState::Unknown) | None => MergeDecision::Retry,
+        Some(MergeableState::Draft) => {
+            MergeDecision::Abort("PR is a draft and can't be merged".to_owned())
+        }
+        Some(MergeableState::Behind) => MergeDecision::Abort(format!(
+            "PR branch '{}' is behind '{}' and needs to be updated",
+            head_ref, base_ref,
+        )),
+        Some(MergeableState::Dirty) => {
+            MergeDecision::Abort("Github is unable to create a merge commit for the PR".to_owned())
+        }
+        Some(MergeableState::Blocked) => {
+            MergeDecision::Abort("1 or more required checks are pending".to_owned())
+        }
+        Some(MergeableState::Unstable) if config.block_on_unstable => {
+            MergeDecision::Abort("1 or more non-required checks are failing".to_owned())
+        }
+        // So Github might set the state as "unstable" since the automerge
+        // action is currently running, but if we got here then the CI
+        // statuses we actually cared about have all passed, so we "should"
+        // be ok
+        Some(MergeableState::Clean | MergeableState::HasHooks | MergeableState::Unstable) => {
+            MergeDecision::Merge
+        }
+        Some(ms) => {
+            log::warn!("Ignoring unknown merge state {:?}", ms);
+            MergeDecision::Ignore
+        }
+    }
+}
+
+/// What came out of trying to merge a PR.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// The PR was merged, with the given merge commit sha.
+    Merged(String),
+    /// The PR was added to the merge queue of its base branch, which merges it once its checks
+    /// pass.
+    Enqueued,
+    /// The PR couldn't be merged, for the given reason.
+    Aborted(String),
+    /// The bot isn't allowed to merge PRs, e.g. because its token can only read the repo.
+    Forbidden(String),
+    /// The PR was left alone, e.g. because its mergeable state is unknown.
+    Skipped,
+}
+
+/// The commit status recording merges done by the bot.
+pub const MERGED_STATUS_CONTEXT: &str = "octobors/merged";
+
+/// The label recording merges done by the bot.
+pub const MERGED_LABEL: &str = "merged-by-octobors";
+
+/// How a merge done by the bot is recorded on the PR.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Record {
+    /// A successful commit status with the given description, on the head of the PR.
+    Status { description: String },
+    /// A label added to the PR.
+    Label(&'static str),
+}
+
+/// How the outcome of a merge attempt is recorded, if the PR was merged.
+pub fn record(outcome: &Outcome, how: MergeRecord) -> Option<Record> {
+    let Outcome::Merged(sha) = outcome else {
+        return None;
+    };
+    Some(match how {
+        MergeRecord::Status => Record::Status {
+            description: format!("Merged as {sha}"),
+        },
+        MergeRecord::Label => Record::Label(MERGED_LABEL),
+    })
+}
+
+/// The messages, lowercased, of the 403 errors Github returns when the bot isn't allowed to merge.
+const PERMISSION_ERRORS: &[&str] = &[
+    "resource not accessible by integration",
+    "must have admin rights",
+];
+
+/// Whether merging failed because the bot lacks the permission to merge, rather than because of
+/// the PR itself. Exceeded rate limits are also reported with a 403, but are temporary.
+fn is_permission_error(err: &anyhow::Error) -> bool {
+    match err.downcast_ref::<octocrab::Error>() {
+        Some(octocrab::Error::GitHub { source, .. })
+            if source.status_code == http::StatusCode::FORBIDDEN =>
+        {
+            let message = source.message.to_lowercase();
+            !message.contains("rate limit")
+                && PERMISSION_ERRORS
+                    .iter()
+                    .any(|error| message.contains(error))
+        }
+        _ => false,
+    }
+}
+
+/// Queues the pull request for merging, crediting the given approvers in the commit message.
 pub async fn queue(
     client: &crate::context::Client,
     pr: &crate::process::Pr,
     config: &crate::context::RepoConfig,
-) -> Result<(), anyhow::Error> {
+    approvers: &[String],
+) -> Result<Outcome, anyhow::Error> {
     let pr_number = pr.number;
+    let node_id = &pr.node_id;
     let prh = client.inner.pulls(&client.owner, &config.name);
 
     let mut retry_count = 0u32;
@@ -73,12 +565,39 @@ pub async fn queue(
     while retry_count < 3 {
         // See https://docs.github.com/en/free-pro-team@latest/rest/guides/getting-started-with-the-git-database-api#checking-mergeability-of-pull-requests
         // for why we rerequest the PR instead of using a small graphql query
-        let pr = prh.get(pr.number).await?;
-
-        use octocrab::models::pulls::MergeableState;
+        let route = format!("/repos/{}/{}/pulls/{pr_number}", client.owner, config.name);
+        let CurrentPr {
+            pr: current,
+            auto_merge,
+        } = client.inner.get(route, None::<&()>).await?;
+        if auto_merge.is_some() {
+            log::info!("Native auto-merge already enabled, leaving the merge to GitHub");
+            return Ok(Outcome::Skipped);
+        }
+        // PRs are merged once all of them have been analyzed, it may have been closed since.
+        if current.state == Some(octocrab::models::IssueState::Closed)
+            || current.merged_at.is_some()
+        {
+            log::info!("The PR isn't open anymore, not merging");
+            return Ok(Outcome::Skipped);
+        }
+        if current.head.sha != pr.commit_sha && !config.merge_new_pushes {
+            log::warn!(
+                "PR changed since it was analyzed, its head is now {} rather than {}, not merging",
+                current.head.sha,
+                pr.commit_sha
+            );
+            return Ok(Outcome::Skipped);
+        }
+        let pr = current;
 
-        match pr.mergeable_state {
-            Some(MergeableState::Unknown) | None => {
+        let abort_reason = match merge_decision(
+            pr.mergeable_state.as_ref(),
+            &pr.head.ref_field,
+            &pr.base.ref_field,
+            config,
+        ) {
+            MergeDecision::Retry => {
                 // Github started calculating the merge state of the PR if it hadn't
                 // already done so before our request, so if it didn't finish, we need
                 // to poll it again
@@ -89,69 +608,746 @@ pub async fn queue(
 
                 continue;
             }
-            Some(ms) => {
-                let abort_reason = match ms {
-                    MergeableState::Draft => Some("PR is a draft and can't be merged".to_owned()),
-                    MergeableState::Behind => Some(format!(
-                        "PR branch '{}' is behind '{}' and needs to be updated",
-                        pr.head.ref_field, pr.base.ref_field,
-                    )),
-                    MergeableState::Dirty => {
-                        Some("Github is unable to create a merge commit for the PR".to_owned())
-                    }
-                    MergeableState::Blocked => {
-                        Some("1 or more required checks are pending".to_owned())
+            MergeDecision::Ignore => return Ok(Outcome::Skipped),
+            MergeDecision::Abort(reason) => reason,
+            MergeDecision::Merge if config.merge_strategy == MergeStrategy::AddToMergeQueue => {
+                if is_enqueued(client, node_id).await? {
+                    log::info!("Already in the merge queue");
+                    return Ok(Outcome::Skipped);
+                }
+                let base = &pr.base.ref_field;
+                if !has_merge_queue(client, &config.name, base).await? {
+                    log::warn!(
+                        "'{base}' has no merge queue, set `merge_strategy` to \"Direct\" to merge PRs"
+                    );
+                    return Ok(Outcome::Aborted(format!(
+                        "'{base}' has no merge queue to add the PR to"
+                    )));
+                }
+                let mutation = enqueue_mutation(node_id, &pr.head.sha);
+                match enqueue(client, &mutation).await {
+                    Ok(position) => {
+                        log::info!("Added to the merge queue at position {position:?}");
+                        return Ok(Outcome::Enqueued);
                     }
-                    // So Github might set the state as "unstable" since the automerge
-                    // action is currently running, but if we got here then the CI
-                    // statuses we actually cared about have all passed, so we "should"
-                    // be ok
-                    MergeableState::Clean | MergeableState::HasHooks | MergeableState::Unstable => {
-                        let merge = prh
-                            .merge(pr_number)
-                            .title(format!("{} (#{})", pr.title.unwrap_or_default(), pr_number))
-                            .sha(pr.head.sha)
-                            .method(config.merge_method)
-                            .message(match pr.body {
-                                Some(body) => format_commit_message(
-                                    body,
-                                    pr.html_url.map(|url| url.to_string()).unwrap_or_default(),
-                                ),
-                                None => pr.html_url.map(|url| url.to_string()).unwrap_or_default(),
-                            });
-
-                        match merge.send().await {
-                            Ok(res) => {
-                                // Even though the response contains a 'merged' boolean, the API docs
-                                // seem to indicate that this would never be false, so we just assume it merged
-                                log::info!("Successfully merged: {}", res.sha.unwrap_or_default());
-
-                                None
+                    Err(err) => format!("Failed to add the PR to the merge queue: {:#}", err),
+                }
+            }
+            MergeDecision::Merge
+                if merge_method(config, pr.commits) == MergeMethod::Rebase
+                    && pr.rebaseable == Some(false) =>
+            {
+                format!("PR can't be rebased onto '{}'", pr.base.ref_field)
+            }
+            MergeDecision::Merge => {
+                let method = merge_method(config, pr.commits);
+                let (title, message) = commit_title_and_message(
+                    config,
+                    method,
+                    pr_number,
+                    pr.title.unwrap_or_default(),
+                    pr.body,
+                    pr.html_url.map(|url| url.to_string()).unwrap_or_default(),
+                );
+                let message = with_approved_by_trailers(message, approvers);
+                // Either the merge commit sha, or why Github didn't merge the PR.
+                let merged = if config.signed_merges {
+                    let mutation = merge_mutation(node_id, &pr.head.sha, &title, &message, method);
+                    graphql_merge(client, &mutation).await.map(Ok)
+                } else {
+                    prh.merge(pr_number)
+                        .title(title)
+                        .sha(pr.head.sha)
+                        .method(method)
+                        .message(message)
+                        .send()
+                        .await
+                        .map(|res| {
+                            if res.merged {
+                                Ok(res.sha.unwrap_or_default())
+                            } else {
+                                Err(res.message)
                             }
-                            Err(err) => Some(format!("Failed to merge PR: {:#}", err)),
-                        }
-                    }
-                    MergeableState::Unknown => unreachable!(),
-                    _ => {
-                        log::warn!("Ignoring unknown merge state {:?}", ms);
-                        return Ok(());
-                    }
+                        })
+                        .map_err(anyhow::Error::from)
                 };
 
-                if let Some(abort_reason) = abort_reason {
-                    log::warn!("not able to automerge: {}", abort_reason);
+                match merged {
+                    Ok(Ok(sha)) => {
+                        log::info!("Successfully merged: {}", sha);
+
+                        return Ok(Outcome::Merged(sha));
+                    }
+                    // The API docs seem to indicate that this never happens, but the response
+                    // has a 'merged' boolean nonetheless.
+                    Ok(Err(message)) => format!(
+                        "Github didn't merge the PR: {}",
+                        message.as_deref().unwrap_or("no reason given")
+                    ),
+                    Err(err) if is_permission_error(&err) => {
+                        let reason = format!("Not allowed to merge PR: {:#}", err);
+                        log::error!("{}", reason);
+                        return Ok(Outcome::Forbidden(reason));
+                    }
+                    Err(err) => {
+                        if has_merge_queue(client, &config.name, &pr.base.ref_field)
+                            .await
+                            .unwrap_or(false)
+                        {
+                            log::warn!(
+                                "'{}' has a merge queue, set `merge_strategy` to \
+                                 \"AddToMergeQueue\" to add PRs to it",
+                                pr.base.ref_field
+                            );
+                        }
+                        format!("Failed to merge PR: {:#}", err)
+                    }
                 }
             }
-        }
+        };
 
-        return Ok(());
+        log::warn!("not able to automerge: {}", abort_reason);
+        return Ok(Outcome::Aborted(abort_reason));
     }
 
-    Ok(())
+    Ok(Outcome::Skipped)
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::{
+        context::RepoConfig,
+        test_utils::{self, client, MockServer},
+    };
+
+    #[test]
+    fn merge_records() {
+        let merged = Outcome::Merged("mergesha".to_owned());
+        assert_eq!(
+            record(&merged, MergeRecord::Status),
+            Some(Record::Status {
+                description: "Merged as mergesha".to_owned()
+            })
+        );
+        assert_eq!(
+            record(&merged, MergeRecord::Label),
+            Some(Record::Label("merged-by-octobors"))
+        );
+        let aborted = Outcome::Aborted("conflicts".to_owned());
+        assert_eq!(record(&aborted, MergeRecord::Status), None);
+        assert_eq!(record(&Outcome::Skipped, MergeRecord::Label), None);
+    }
+
+    #[test]
+    fn merge_mutation_variables() {
+        let mutation = merge_mutation(
+            "PR_1",
+            "abc",
+            "Fix things (#1)",
+            "Details",
+            MergeMethod::Squash,
+        );
+        assert_eq!(mutation.query, MERGE_MUTATION);
+        assert_eq!(
+            mutation.variables,
+            MergeVariables {
+                pull_request_id: "PR_1",
+                expected_head_oid: "abc",
+                commit_headline: "Fix things (#1)",
+                commit_body: "Details",
+                merge_method: "SQUASH",
+            }
+        );
+    }
+
+    #[test]
+    fn enqueue_mutation_variables() {
+        let mutation = enqueue_mutation("PR_1", "abc");
+        assert_eq!(mutation.query, ENQUEUE_MUTATION);
+        assert_eq!(
+            mutation.variables,
+            EnqueueVariables {
+                pull_request_id: "PR_1",
+                expected_head_oid: "abc",
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn merge_queue() {
+        let config = RepoConfig {
+            name: "repo".to_owned(),
+            merge_strategy: MergeStrategy::AddToMergeQueue,
+            ..Default::default()
+        };
+        let server = MockServer::new(vec![
+            ("200 OK", CLEAN_PR),
+            ("200 OK", r#"{"data": {"node": {"mergeQueueEntry": null}}}"#),
+            (
+                "200 OK",
+                r#"{"data": {"repository": {"mergeQueue": {"id": "MQ_1"}}}}"#,
+            ),
+            (
+                "200 OK",
+                r#"{"data": {"enqueuePullRequest": {"mergeQueueEntry": {"position": 2}}}}"#,
+            ),
+            ("200 OK", CLEAN_PR),
+            ("200 OK", r#"{"data": {"node": {"mergeQueueEntry": null}}}"#),
+            (
+                "200 OK",
+                r#"{"data": {"enqueuePullRequest": {"mergeQueueEntry": {"position": 3}}}}"#,
+            ),
+        ]);
+        let client = client(&server);
+        let outcome = queue(&client, &pr("newsha"), &config, &[]).await;
+        assert_eq!(outcome.unwrap(), Outcome::Enqueued);
+        // The merge queue is only looked up once.
+        let outcome = queue(&client, &pr("newsha"), &config, &[]).await;
+        assert_eq!(outcome.unwrap(), Outcome::Enqueued);
+        let requests = server.requests();
+        assert_eq!(requests.len(), 7);
+        assert!(requests[1].body.contains(r#""id":"PR_42""#));
+        assert!(requests[2].body.contains(r#""branch":"main""#));
+        assert_eq!(requests[3].line, "POST /graphql");
+        assert!(requests[3].body.contains("enqueuePullRequest"));
+        assert!(requests[3].body.contains(r#""expectedHeadOid":"newsha""#));
+        assert!(requests[6].body.contains("enqueuePullRequest"));
+
+        // Without a merge queue, nothing is done.
+        let server = MockServer::new(vec![
+            ("200 OK", CLEAN_PR),
+            ("200 OK", r#"{"data": {"node": {"mergeQueueEntry": null}}}"#),
+            (
+                "200 OK",
+                r#"{"data": {"repository": {"mergeQueue": null}}}"#,
+            ),
+        ]);
+        let outcome = queue(&test_utils::client(&server), &pr("newsha"), &config, &[]).await;
+        assert!(matches!(outcome.unwrap(), Outcome::Aborted(_)));
+        assert_eq!(server.requests().len(), 3);
+
+        // Already queued PRs are left alone.
+        let server = MockServer::new(vec![
+            ("200 OK", CLEAN_PR),
+            (
+                "200 OK",
+                r#"{"data": {"node": {"mergeQueueEntry": {"position": 1}}}}"#,
+            ),
+        ]);
+        let outcome = queue(&test_utils::client(&server), &pr("newsha"), &config, &[]).await;
+        assert_eq!(outcome.unwrap(), Outcome::Skipped);
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn graphql_merge_responses() {
+        let server = MockServer::new(vec![
+            (
+                "200 OK",
+                r#"{"data": {"mergePullRequest": {"pullRequest": {"mergeCommit": {"oid": "def"}}}}}"#,
+            ),
+            (
+                "200 OK",
+                r#"{"data": {"mergePullRequest": null}, "errors": [{"type": "UNPROCESSABLE", "message": "Head branch was modified"}]}"#,
+            ),
+            (
+                "200 OK",
+                r#"{"data": {"mergePullRequest": {"pullRequest": {"mergeCommit": null}}}}"#,
+            ),
+        ]);
+        let client = client(&server);
+        let mutation = merge_mutation("PR_1", "abc", "title", "message", MergeMethod::Merge);
+
+        assert_eq!(graphql_merge(&client, &mutation).await.unwrap(), "def");
+        let err = graphql_merge(&client, &mutation).await.unwrap_err();
+        assert_eq!(err.to_string(), "Head branch was modified");
+        let err = graphql_merge(&client, &mutation).await.unwrap_err();
+        assert_eq!(err.to_string(), "No merge commit was returned");
+
+        let requests = server.requests();
+        assert_eq!(requests[0].line, "POST /graphql");
+        assert!(requests[0].body.contains(r#""pullRequestId":"PR_1""#));
+        assert!(requests[0].body.contains(r#""expectedHeadOid":"abc""#));
+    }
+
+    fn pr(commit_sha: &str) -> crate::process::Pr {
+        crate::process::Pr {
+            commit_sha: commit_sha.to_owned(),
+            ..test_utils::pr(42)
+        }
+    }
+
+    const CLEAN_PR: &str = r#"{
+        "url": "https://api.github.com/repos/org/repo/pulls/42",
+        "id": 1,
+        "number": 42,
+        "mergeable_state": "clean",
+        "head": { "ref": "feature", "sha": "newsha" },
+        "base": { "ref": "main", "sha": "othersha" }
+    }"#;
+
+    #[tokio::test]
+    async fn closed_prs_are_not_merged() {
+        let config = RepoConfig {
+            name: "repo".to_owned(),
+            ..Default::default()
+        };
+        let closed = r#"{
+            "url": "https://api.github.com/repos/org/repo/pulls/42",
+            "id": 1,
+            "number": 42,
+            "state": "closed",
+            "mergeable_state": "clean",
+            "head": { "ref": "feature", "sha": "somesha" },
+            "base": { "ref": "main", "sha": "othersha" }
+        }"#;
+        let server = MockServer::new(vec![("200 OK", closed)]);
+        let outcome = queue(&client(&server), &pr("somesha"), &config, &[]).await;
+        assert_eq!(outcome.unwrap(), Outcome::Skipped);
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn new_pushes_are_not_merged() {
+        let mut config = RepoConfig {
+            name: "repo".to_owned(),
+            ..Default::default()
+        };
+
+        // Someone pushed since the PR was analyzed.
+        let server = MockServer::new(vec![("200 OK", CLEAN_PR)]);
+        let outcome = queue(&client(&server), &pr("oldsha"), &config, &[]).await;
+        assert_eq!(outcome.unwrap(), Outcome::Skipped);
+        assert_eq!(server.requests().len(), 1);
+
+        // The head is the analyzed commit, or the check is disabled.
+        for (sha, merge_new_pushes) in [("newsha", false), ("oldsha", true)].iter() {
+            config.merge_new_pushes = *merge_new_pushes;
+            let server = MockServer::new(vec![
+                ("200 OK", CLEAN_PR),
+                (
+                    "200 OK",
+                    r#"{"sha": "mergesha", "merged": true, "message": "Merged"}"#,
+                ),
+            ]);
+            let outcome = queue(&client(&server), &pr(sha), &config, &[]).await;
+            assert_eq!(outcome.unwrap(), Outcome::Merged("mergesha".to_owned()));
+            let requests = server.requests();
+            assert_eq!(requests[1].line, "PUT /repos/org/repo/pulls/42/merge");
+            assert!(requests[1].body.contains(r#""sha":"newsha""#));
+        }
+    }
+
+    #[tokio::test]
+    async fn native_auto_merge() {
+        let config = RepoConfig {
+            name: "repo".to_owned(),
+            ..Default::default()
+        };
+        let server = MockServer::new(vec![(
+            "200 OK",
+            r#"{
+                "url": "https://api.github.com/repos/org/repo/pulls/42",
+                "id": 1,
+                "number": 42,
+                "mergeable_state": "clean",
+                "head": { "ref": "feature", "sha": "newsha" },
+                "base": { "ref": "main", "sha": "othersha" },
+                "auto_merge": { "merge_method": "squash" }
+            }"#,
+        )]);
+        let outcome = queue(&client(&server), &pr("newsha"), &config, &[]).await;
+        assert_eq!(outcome.unwrap(), Outcome::Skipped);
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].line, "GET /repos/org/repo/pulls/42");
+    }
+
+    #[tokio::test]
+    async fn not_rebaseable() {
+        let mut config = RepoConfig {
+            name: "repo".to_owned(),
+            merge_method: MergeMethod::Rebase,
+            ..Default::default()
+        };
+        let current = r#"{
+            "url": "https://api.github.com/repos/org/repo/pulls/42",
+            "id": 1,
+            "number": 42,
+            "mergeable_state": "clean",
+            "rebaseable": false,
+            "head": { "ref": "feature", "sha": "somesha" },
+            "base": { "ref": "main", "sha": "othersha" }
+        }"#;
+        let server = MockServer::new(vec![("200 OK", current)]);
+        let outcome = queue(&client(&server), &pr("somesha"), &config, &[]).await;
+        assert_eq!(
+            outcome.unwrap(),
+            Outcome::Aborted("PR can't be rebased onto 'main'".to_owned())
+        );
+        assert_eq!(server.requests().len(), 1);
+
+        // Other methods don't rebase the PR.
+        config.merge_method = MergeMethod::Merge;
+        let server = MockServer::new(vec![
+            ("200 OK", current),
+            ("200 OK", r#"{"sha": "mergesha", "merged": true}"#),
+        ]);
+        let outcome = queue(&client(&server), &pr("somesha"), &config, &[]).await;
+        assert_eq!(outcome.unwrap(), Outcome::Merged("mergesha".to_owned()));
+    }
+
+    #[tokio::test]
+    async fn approvers_are_credited() {
+        let config = RepoConfig {
+            name: "repo".to_owned(),
+            approved_by_trailers: true,
+            ..Default::default()
+        };
+        let server = MockServer::new(vec![
+            ("200 OK", CLEAN_PR),
+            ("200 OK", r#"{"sha": "mergesha", "merged": true}"#),
+        ]);
+        let approvers = vec!["alice".to_owned()];
+        let outcome = queue(&client(&server), &pr("newsha"), &config, &approvers).await;
+        assert_eq!(outcome.unwrap(), Outcome::Merged("mergesha".to_owned()));
+        // The approvers found by the analysis are used, without listing the reviews again.
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[1].body.contains("Approved-by: alice"));
+    }
+
+    #[tokio::test]
+    async fn not_merged() {
+        let config = RepoConfig {
+            name: "repo".to_owned(),
+            ..Default::default()
+        };
+        let server = MockServer::new(vec![
+            (
+                "200 OK",
+                r#"{
+                    "url": "https://api.github.com/repos/org/repo/pulls/42",
+                    "id": 1,
+                    "number": 42,
+                    "mergeable_state": "clean",
+                    "head": { "ref": "feature", "sha": "somesha" },
+                    "base": { "ref": "main", "sha": "othersha" }
+                }"#,
+            ),
+            (
+                "200 OK",
+                r#"{"sha": null, "merged": false, "message": "Base branch was modified"}"#,
+            ),
+        ]);
+        let outcome = queue(&client(&server), &pr("somesha"), &config, &[]).await;
+        assert_eq!(
+            outcome.unwrap(),
+            Outcome::Aborted("Github didn't merge the PR: Base branch was modified".to_owned())
+        );
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn forbidden_merge() {
+        let server = MockServer::new(vec![
+            (
+                "200 OK",
+                r#"{
+                    "url": "https://api.github.com/repos/org/repo/pulls/42",
+                    "id": 1,
+                    "number": 42,
+                    "mergeable_state": "clean",
+                    "head": { "ref": "feature", "sha": "somesha" },
+                    "base": { "ref": "main", "sha": "othersha" }
+                }"#,
+            ),
+            (
+                "403 Forbidden",
+                r#"{"message": "Resource not accessible by integration"}"#,
+            ),
+        ]);
+        let client = client(&server);
+        let config = RepoConfig {
+            name: "repo".to_owned(),
+            ..Default::default()
+        };
+        let pr = pr("somesha");
+
+        let outcome = queue(&client, &pr, &config, &[]).await.unwrap();
+        assert!(
+            matches!(&outcome, Outcome::Forbidden(reason) if reason.contains("Resource not accessible")),
+            "{:?}",
+            outcome
+        );
+
+        let requests = server.requests();
+        assert_eq!(requests[1].line, "PUT /repos/org/repo/pulls/42/merge");
+    }
+
+    #[tokio::test]
+    async fn rate_limited_merge() {
+        let server = MockServer::new(vec![
+            (
+                "200 OK",
+                r#"{
+                    "url": "https://api.github.com/repos/org/repo/pulls/42",
+                    "id": 1,
+                    "number": 42,
+                    "mergeable_state": "clean",
+                    "head": { "ref": "feature", "sha": "somesha" },
+                    "base": { "ref": "main", "sha": "othersha" }
+                }"#,
+            ),
+            (
+                "403 Forbidden",
+                r#"{"message": "You have exceeded a secondary rate limit. Please wait a few minutes before you try again."}"#,
+            ),
+        ]);
+        let config = RepoConfig {
+            name: "repo".to_owned(),
+            ..Default::default()
+        };
+
+        // Worth trying again later, unlike a missing permission.
+        let outcome = queue(&client(&server), &pr("somesha"), &config, &[])
+            .await
+            .unwrap();
+        assert!(
+            matches!(&outcome, Outcome::Aborted(reason) if reason.contains("secondary rate limit")),
+            "{:?}",
+            outcome
+        );
+    }
+
+    #[test]
+    fn unstable_merges_by_default() {
+        let config = RepoConfig::default();
+        assert_eq!(
+            merge_decision(Some(&MergeableState::Unstable), "head", "main", &config),
+            MergeDecision::Merge
+        );
+        assert_eq!(
+            merge_decision(Some(&MergeableState::Clean), "head", "main", &config),
+            MergeDecision::Merge
+        );
+        assert_eq!(
+            merge_decision(Some(&MergeableState::HasHooks), "head", "main", &config),
+            MergeDecision::Merge
+        );
+    }
+
+    #[test]
+    fn unstable_blocks_when_configured() {
+        let config = RepoConfig {
+            block_on_unstable: true,
+            ..Default::default()
+        };
+        assert!(matches!(
+            merge_decision(Some(&MergeableState::Unstable), "head", "main", &config),
+            MergeDecision::Abort(_)
+        ));
+        assert_eq!(
+            merge_decision(Some(&MergeableState::Clean), "head", "main", &config),
+            MergeDecision::Merge
+        );
+        assert_eq!(
+            merge_decision(Some(&MergeableState::HasHooks), "head", "main", &config),
+            MergeDecision::Merge
+        );
+    }
+
+    #[test]
+    fn unknown_state_is_retried() {
+        let config = RepoConfig::default();
+        assert_eq!(
+            merge_decision(None, "head", "main", &config),
+            MergeDecision::Retry
+        );
+        assert_eq!(
+            merge_decision(Some(&MergeableState::Unknown), "head", "main", &config),
+            MergeDecision::Retry
+        );
+    }
+
+    #[test]
+    fn single_commit_merge_method() {
+        let mut config = RepoConfig {
+            merge_method: MergeMethod::Squash,
+            ..Default::default()
+        };
+        assert_eq!(merge_method(&config, Some(1)), MergeMethod::Squash);
+
+        config.squash_only_if_multiple_commits = true;
+        assert_eq!(merge_method(&config, Some(1)), MergeMethod::Rebase);
+        assert_eq!(merge_method(&config, Some(2)), MergeMethod::Squash);
+        assert_eq!(merge_method(&config, None), MergeMethod::Squash);
+
+        config.single_commit_merge_method = Some(MergeMethod::Merge);
+        assert_eq!(merge_method(&config, Some(1)), MergeMethod::Merge);
+
+        // Only squash merges are affected.
+        config.merge_method = MergeMethod::Merge;
+        config.single_commit_merge_method = Some(MergeMethod::Rebase);
+        assert_eq!(merge_method(&config, Some(1)), MergeMethod::Merge);
+    }
+
+    #[test]
+    fn default_commit_message() {
+        let config = RepoConfig::default();
+        assert_eq!(
+            commit_title_and_message(
+                &config,
+                config.merge_method,
+                42,
+                "Fix things".to_owned(),
+                Some("Fixes <!-- template -->things.".to_owned()),
+                "https://github.com/org/repo/pull/42".to_owned(),
+            ),
+            (
+                "Fix things (#42)".to_owned(),
+                "Fixes things.\n\nhttps://github.com/org/repo/pull/42".to_owned()
+            )
+        );
+        assert_eq!(
+            commit_title_and_message(
+                &config,
+                config.merge_method,
+                42,
+                "Fix things".to_owned(),
+                None,
+                "https://github.com/org/repo/pull/42".to_owned(),
+            ),
+            (
+                "Fix things (#42)".to_owned(),
+                "https://github.com/org/repo/pull/42".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn squash_commit_message() {
+        let mut config = RepoConfig {
+            merge_method: MergeMethod::Squash,
+            squash_pr_title: true,
+            ..Default::default()
+        };
+        let build = |config: &RepoConfig, body: Option<&str>| {
+            commit_title_and_message(
+                config,
+                config.merge_method,
+                42,
+                "Fix things".to_owned(),
+                body.map(str::to_owned),
+                "https://github.com/org/repo/pull/42".to_owned(),
+            )
+        };
+
+        assert_eq!(
+            build(&config, Some("Fixes <!-- template -->things.")),
+            ("Fix things".to_owned(), "Fixes things.".to_owned())
+        );
+        assert_eq!(
+            build(&config, None),
+            ("Fix things".to_owned(), String::new())
+        );
+
+        config.squash_pr_title_with_number = true;
+        assert_eq!(
+            build(&config, Some("Fixes things.")),
+            ("Fix things (#42)".to_owned(), "Fixes things.".to_owned())
+        );
+
+        // Only applies to squash merges.
+        config.merge_method = MergeMethod::Rebase;
+        assert_eq!(
+            build(&config, Some("Fixes things.")),
+            (
+                "Fix things (#42)".to_owned(),
+                "Fixes things.\n\nhttps://github.com/org/repo/pull/42".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn approved_by_trailers() {
+        let approvers = vec!["alice".to_owned(), "bob".to_owned()];
+        assert_eq!(
+            with_approved_by_trailers(
+                "Fixes things.\n\nhttps://github.com/org/repo/pull/42".to_owned(),
+                &approvers
+            ),
+            "Fixes things.\n\nhttps://github.com/org/repo/pull/42\n\n\
+             Approved-by: alice\nApproved-by: bob"
+        );
+        assert_eq!(
+            with_approved_by_trailers(String::new(), &approvers),
+            "Approved-by: alice\nApproved-by: bob"
+        );
+        assert_eq!(
+            with_approved_by_trailers("Fixes things.\n".to_owned(), &[]),
+            "Fixes things.\n"
+        );
+    }
+
+    #[test]
+    fn changelog_section() {
+        const BODY: &str = "Some context.
+
+## Changelog
+<!-- What users should know. -->
+- Fixed things.
+
+### Details
+Even more.
+
+## Testing
+Ran the tests.";
+        assert_eq!(
+            markdown_section(BODY, "## Changelog").as_deref(),
+            Some("<!-- What users should know. -->\n- Fixed things.\n\n### Details\nEven more.")
+        );
+        assert_eq!(
+            markdown_section(BODY, "### Details").as_deref(),
+            Some("Even more.")
+        );
+        assert_eq!(markdown_section(BODY, "## Missing"), None);
+        assert_eq!(
+            markdown_section("## Changelog\n\n## Testing", "## Changelog"),
+            None
+        );
+        assert_eq!(
+            markdown_section("## Changelog\n#123 fixed", "## Changelog").as_deref(),
+            Some("#123 fixed")
+        );
+
+        let config = RepoConfig {
+            changelog_section_header: Some("## Changelog".to_owned()),
+            ..Default::default()
+        };
+        let build = |body: &str| {
+            commit_title_and_message(
+                &config,
+                config.merge_method,
+                42,
+                "Fix things".to_owned(),
+                Some(body.to_owned()),
+                "https://github.com/org/repo/pull/42".to_owned(),
+            )
+            .1
+        };
+        assert_eq!(
+            build(BODY),
+            "- Fixed things.\n\n### Details\nEven more.\n\nhttps://github.com/org/repo/pull/42"
+        );
+        // Without the section, the whole body is used.
+        assert_eq!(
+            build("Fixes <!-- template -->things."),
+            "Fixes things.\n\nhttps://github.com/org/repo/pull/42"
+        );
+    }
+
     #[test]
     fn remove_comments() {
         use super::remove_html_comments;
@@ -0,0 +1,118 @@
+//! Notifying a generic webhook of what the bot does to PRs.
+//!
+//! The webhook is sent a JSON `POST` request for each subscribed event, e.g.
+//! `{"repo": "repo", "pr": 42, "action": "Merged", "reasons": [], "timestamp": "..."}`.
+//! Failing to deliver a notification is logged, but doesn't fail the run.
+
+use std::time::Duration;
+
+use crate::context::{NotificationEvent, NotificationWebhook};
+use anyhow::{Context as _, Result};
+use chrono::{DateTime, Utc};
+use tracing as log;
+
+/// How long to wait for the webhook to answer.
+const TIMEOUT_SECS: u64 = 10;
+
+#[derive(Debug, serde::Serialize)]
+struct Payload<'a> {
+    repo: &'a str,
+    pr: u64,
+    action: NotificationEvent,
+    reasons: &'a [String],
+    timestamp: DateTime<Utc>,
+}
+
+async fn post(webhook: &NotificationWebhook, payload: &Payload<'_>) -> Result<()> {
+    // Deliberately not reusing the Github client, so that the Github token isn't
+    // sent to the webhook.
+    let client = octocrab::OctocrabBuilder::new()
+        .build()
+        .context("failed to create webhook client")?;
+    let response = tokio::time::timeout(
+        Duration::from_secs(TIMEOUT_SECS),
+        client._post(webhook.url.as_str(), Some(payload)),
+    )
+    .await
+    .context("webhook timed out")?
+    .context("webhook request failed")?;
+    anyhow::ensure!(
+        response.status().is_success(),
+        "webhook answered {}",
+        response.status()
+    );
+    Ok(())
+}
+
+/// Notifies the webhook of the event if it's subscribed to it, with the reasons why the PR is
+/// blocked, or why processing it failed.
+pub async fn send(
+    webhook: &NotificationWebhook,
+    repo: &str,
+    pr: u64,
+    event: NotificationEvent,
+    reasons: &[String],
+) {
+    if !webhook.events.contains(&event) {
+        return;
+    }
+    let payload = Payload {
+        repo,
+        pr,
+        action: event,
+        reasons,
+        timestamp: Utc::now(),
+    };
+    log::debug!("Notifying the webhook: {payload:?}");
+    if let Err(err) = post(webhook, &payload).await {
+        log::warn!("Couldn't notify the webhook of {event:?}: {err:#}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::MockServer;
+
+    fn webhook(server: &MockServer) -> NotificationWebhook {
+        NotificationWebhook {
+            url: format!("{}/hook", server.url),
+            events: vec![NotificationEvent::Merged, NotificationEvent::Errored],
+        }
+    }
+
+    #[tokio::test]
+    async fn subscribed_events() {
+        let server = MockServer::new(vec![("200 OK", "ok")]);
+        let hook = webhook(&server);
+        send(&hook, "repo", 42, NotificationEvent::Blocked, &[]).await;
+        let reasons = ["BranchBehind".to_owned()];
+        send(&hook, "repo", 42, NotificationEvent::Errored, &reasons).await;
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].line, "POST /hook");
+        let body = &requests[0].body;
+        assert!(
+            body.starts_with(
+                r#"{"repo":"repo","pr":42,"action":"Errored","reasons":["BranchBehind"],"timestamp":""#
+            ),
+            "{}",
+            body
+        );
+    }
+
+    #[tokio::test]
+    async fn delivery_failures() {
+        let server = MockServer::new(vec![("500 Internal Server Error", "oops")]);
+        send(
+            &webhook(&server),
+            "repo",
+            42,
+            NotificationEvent::Merged,
+            &[],
+        )
+        .await;
+        assert_eq!(server.requests().len(), 1);
+    }
+}
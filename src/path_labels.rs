@@ -0,0 +1,144 @@
+//! Labeling PRs, and requiring more approvals, according to the paths of the
+//! files they change.
+
+use std::collections::HashSet;
+
+use crate::context::{PathLabel, SensitivePath};
+
+/// Whether the path matches the glob. `*` matches anything but a `/`, `**`
+/// matches anything, including `/`s, and `?` matches a single character other
+/// than `/`.
+pub(crate) fn glob_matches(glob: &[u8], path: &[u8]) -> bool {
+    match glob {
+        [] => path.is_empty(),
+        [b'*', b'*', rest @ ..] => {
+            // `a/**/b` also matches `a/b`, but `**/` only matches whole components, so not `a/xb`.
+            let rest_without_slash = rest.strip_prefix(b"/");
+            (0..=path.len()).any(|i| {
+                glob_matches(rest, &path[i..])
+                    || rest_without_slash.is_some_and(|rest| {
+                        (i == 0 || path[i - 1] == b'/') && glob_matches(rest, &path[i..])
+                    })
+            })
+        }
+        [b'*', rest @ ..] => {
+            let segment_len = path.iter().position(|&c| c == b'/').unwrap_or(path.len());
+            (0..=segment_len).any(|i| glob_matches(rest, &path[i..]))
+        }
+        [b'?', rest @ ..] => match path {
+            [c, path @ ..] if *c != b'/' => glob_matches(rest, path),
+            _ => false,
+        },
+        [g, rest @ ..] => match path {
+            [c, path @ ..] if c == g => glob_matches(rest, path),
+            _ => false,
+        },
+    }
+}
+
+/// The labels of all the globs matching at least one of the paths.
+pub fn labels<'a>(path_labels: &'a [PathLabel], paths: &[String]) -> HashSet<&'a str> {
+    path_labels
+        .iter()
+        .filter(|path_label| {
+            paths
+                .iter()
+                .any(|path| glob_matches(path_label.glob.as_bytes(), path.as_bytes()))
+        })
+        .map(|path_label| path_label.label.as_str())
+        .collect()
+}
+
+/// The most approvals required by the sensitive paths matching at least one of the paths, if any.
+pub fn required_approvals(sensitive_paths: &[SensitivePath], paths: &[String]) -> Option<usize> {
+    sensitive_paths
+        .iter()
+        .filter(|sensitive| {
+            paths
+                .iter()
+                .any(|path| glob_matches(sensitive.glob.as_bytes(), path.as_bytes()))
+        })
+        .map(|sensitive| sensitive.extra_approvals)
+        .max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn globs() {
+        let matches = |glob: &str, path: &str| glob_matches(glob.as_bytes(), path.as_bytes());
+
+        assert!(matches("README.md", "README.md"));
+        assert!(!matches("README.md", "docs/README.md"));
+
+        assert!(matches("*.md", "README.md"));
+        assert!(!matches("*.md", "docs/README.md"));
+        assert!(matches("**/*.md", "docs/README.md"));
+        assert!(matches("**/*.md", "README.md"));
+
+        assert!(matches("src/api/**", "src/api/mod.rs"));
+        assert!(matches("src/api/**", "src/api/v1/users.rs"));
+        assert!(!matches("src/api/**", "src/ui/mod.rs"));
+        assert!(matches("src/**/mod.rs", "src/mod.rs"));
+        assert!(matches("src/**/mod.rs", "src/api/v1/mod.rs"));
+        assert!(!matches("src/**/mod.rs", "src/foomod.rs"));
+        assert!(matches("a/**/b", "a/b"));
+        assert!(matches("a/**/b", "a/x/b"));
+        assert!(!matches("a/**/b", "a/xb"));
+
+        assert!(matches("src/v?/*.rs", "src/v1/lib.rs"));
+        assert!(!matches("src/v?/*.rs", "src/v10/lib.rs"));
+    }
+
+    #[test]
+    fn labels_for_paths() {
+        let path_labels = [
+            ("src/api/**", "area/api"),
+            ("src/ui/**", "area/ui"),
+            ("**/*.md", "docs"),
+        ]
+        .iter()
+        .map(|(glob, label)| PathLabel {
+            glob: glob.to_string(),
+            label: label.to_string(),
+        })
+        .collect::<Vec<_>>();
+        let labels_for = |paths: &[&str]| {
+            let paths: Vec<String> = paths.iter().map(|path| path.to_string()).collect();
+            let mut labels: Vec<_> = labels(&path_labels, &paths).into_iter().collect();
+            labels.sort_unstable();
+            labels
+        };
+
+        assert!(labels_for(&["Cargo.toml"]).is_empty());
+        assert_eq!(labels_for(&["src/api/users.rs"]), vec!["area/api"]);
+        assert_eq!(
+            labels_for(&["src/ui/button.rs", "src/api/users.rs", "src/api/README.md"]),
+            vec!["area/api", "area/ui", "docs"]
+        );
+    }
+
+    #[test]
+    fn approvals_for_paths() {
+        let sensitive_paths = [("infra/**", 2), ("secrets/**", 3)]
+            .iter()
+            .map(|(glob, extra_approvals)| SensitivePath {
+                glob: glob.to_string(),
+                extra_approvals: *extra_approvals,
+            })
+            .collect::<Vec<_>>();
+        let approvals_for = |paths: &[&str]| {
+            let paths: Vec<String> = paths.iter().map(|path| path.to_string()).collect();
+            required_approvals(&sensitive_paths, &paths)
+        };
+
+        assert_eq!(approvals_for(&["src/lib.rs"]), None);
+        assert_eq!(approvals_for(&["src/lib.rs", "infra/main.tf"]), Some(2));
+        assert_eq!(
+            approvals_for(&["secrets/keys.enc", "infra/main.tf"]),
+            Some(3)
+        );
+    }
+}
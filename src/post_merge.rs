@@ -0,0 +1,80 @@
+//! Running a user-provided command once a PR has been merged.
+
+use std::process::{Command, Output};
+
+use crate::process::Pr;
+use anyhow::{Context as _, Result};
+use tracing as log;
+
+/// Builds the command, passing it details about the merge in environment variables.
+fn command(args: &[String], repo: &str, pr: &Pr, merge_sha: &str) -> Result<Command> {
+    let (program, args) = args.split_first().context("empty post-merge command")?;
+    let mut command = Command::new(program);
+    command
+        .args(args)
+        .env("OCTOBORS_REPO", repo)
+        .env("OCTOBORS_PR", pr.number.to_string())
+        .env("OCTOBORS_SHA", &pr.commit_sha)
+        .env("OCTOBORS_MERGE_SHA", merge_sha);
+    Ok(command)
+}
+
+fn log_output(output: &Output) {
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if output.status.success() {
+        log::info!(%stdout, %stderr, "Post-merge command succeeded");
+    } else {
+        log::warn!(%stdout, %stderr, "Post-merge command failed: {}", output.status);
+    }
+}
+
+/// Runs the post-merge command. Failures are logged, but don't fail the run.
+pub async fn run(args: &[String], repo: &str, pr: &Pr, merge_sha: &str) {
+    let mut command = match command(args, repo, pr, merge_sha) {
+        Ok(command) => command,
+        Err(err) => {
+            log::warn!("Invalid post-merge command: {err:#}");
+            return;
+        }
+    };
+    match tokio::task::spawn_blocking(move || command.output()).await {
+        Ok(Ok(output)) => log_output(&output),
+        Ok(Err(err)) => log::warn!("Couldn't run the post-merge command: {err}"),
+        Err(err) => log::warn!("Couldn't run the post-merge command: {err}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+
+    #[test]
+    fn environment() {
+        let pr = Pr {
+            commit_sha: "headsha".to_owned(),
+            ..test_utils::pr(42)
+        };
+        let args: Vec<_> = [
+            "sh",
+            "-c",
+            "echo $OCTOBORS_REPO $OCTOBORS_PR $OCTOBORS_SHA $OCTOBORS_MERGE_SHA",
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+
+        let output = command(&args, "repo", &pr, "mergesha")
+            .unwrap()
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "repo 42 headsha mergesha\n"
+        );
+
+        assert!(command(&[], "repo", &pr, "mergesha").is_err());
+    }
+}
@@ -1,7 +1,11 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
+    fmt,
+};
 
 use crate::{
-    context,
+    context, external_gate, merge, path_labels,
     review::{Approval, CommentEffect, Review, Reviews},
 };
 use anyhow::{Context as _, Error, Result};
@@ -9,11 +13,12 @@ use chrono::{DateTime, Duration, Utc};
 use models::{pulls::PullRequest, IssueState, StatusState};
 use octocrab::models;
 use tracing as log;
+use unicode_normalization::UnicodeNormalization as _;
 
 #[cfg(test)]
 mod tests;
 
-#[derive(PartialEq, Eq, Hash)]
+#[derive(Debug, PartialEq, Eq, Hash)]
 enum BlockReason {
     /// The PR is in the draft status.
     DraftPr,
@@ -25,60 +30,457 @@ enum BlockReason {
     MissingReviews,
     /// The PR is waiting for a PR approval, and a label requires approvals.
     MissingReviewApproval { from_users: Vec<String> },
+    /// Some mandatory reviewers haven't approved the PR.
+    MissingMandatoryApproval { from_users: Vec<String> },
     /// The CI is not done running yet, or it's failing.
     CiNotPassing,
+    /// A required status or check run was never reported, e.g. because its job didn't start.
+    RequiredCheckMissing { context: String },
+    /// Some required statuses have been pending for longer than the configured timeout.
+    StatusPendingTooLong { statuses: Vec<String> },
     /// The PR lacks a description, and a label requires a description.
     MissingDescription,
     /// The merge is blocked by a label.
     BlockedByLabel,
     /// The PR is inside a grace period.
     InsideGracePeriod,
+    /// The external gate didn't approve the PR.
+    ExternalGateNotSatisfied,
+    /// The PR hasn't been successfully deployed to a required environment yet.
+    DeploymentNotSuccessful { env: String },
+    /// The PR changes more files or lines than allowed for automerging.
+    TooLarge,
+    /// The base branch has commits that the PR branch doesn't.
+    BranchBehind,
+    /// Fewer people than required reviewed the PR.
+    InsufficientReviewBreadth,
+    /// Some forbidden statuses are reported on the PR.
+    ForbiddenStatus { statuses: Vec<String> },
+    /// Merging the current commit failed recently.
+    MergeCooldown,
+    /// The PR doesn't target a branch PRs can be merged into.
+    DisallowedBaseBranch,
+    /// PRs are merged by rebasing them, but the PR can't be rebased.
+    NotRebaseable,
+    /// PRs the PR must be merged with haven't been merged yet.
+    WaitingOnLinkedPr { prs: Vec<String> },
+    /// The head of the base branch is failing its checks.
+    BaseBranchRed,
+    /// No status or check run was reported on the PR, e.g. because CI wasn't triggered.
+    NoChecksRan,
+    /// PRs are only merged with the automerge label, which the PR doesn't have.
+    MissingAutomergeLabel,
+    /// The PR was opened by a bot account that isn't trusted.
+    UntrustedBotAuthor,
+    /// Only members of the author's teams approved the PR.
+    MissingCrossTeamApproval,
+    /// Some requested reviewers can't access the repo anymore, e.g. because they left the org.
+    ReviewerLostAccess { users: Vec<String> },
+    /// The PR has a soft block label, which defers the merge until the given time.
+    SoftBlocked { label: String, until: DateTime<Utc> },
+}
+
+/// Whether the labels of a PR let it be merged automatically.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eligibility {
+    /// The PR has the block merge label, which wins over the automerge label.
+    Blocked,
+    /// The PR has the automerge label.
+    OptedIn,
+    /// The PR doesn't have the automerge label, which PRs need to be merged.
+    NotOptedIn,
+    /// No label applies, and PRs are merged by default.
+    Default,
 }
 
+/// Tells that a PR is going to be merged.
+const ALL_GOOD: &str = "All good, this PR is going to be merged.\n";
+
 #[derive(Debug, Clone)]
 pub struct Pr {
     pub id: u64,
+    /// The GraphQL id of the PR.
+    pub node_id: String,
     pub author: String,
+    /// Whether the author is a bot account, e.g. `dependabot[bot]`, rather than a user.
+    pub author_is_bot: bool,
     pub number: u64,
     pub commit_sha: String,
+    pub base_ref: String,
     pub draft: bool,
     pub state: Option<models::IssueState>,
-    pub updated_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    /// When the PR was last updated, unless it never was.
+    pub updated_at: Option<DateTime<Utc>>,
     pub labels: HashSet<String>,
     pub has_description: bool,
+    pub body: String,
     pub requested_reviewers_remaining: usize,
+    /// The users whose review is still requested, which are part of `requested_reviewers_remaining`.
+    pub requested_users: Vec<String>,
+    /// The teams whose review is still requested, which are part of `requested_reviewers_remaining`.
+    pub requested_teams: Vec<String>,
 }
 
 impl Pr {
-    pub fn from_octocrab_pull_request(pr: PullRequest) -> Self {
+    /// The description only counts as such if it's not just the (unedited) `description_template`.
+    pub fn from_octocrab_pull_request(pr: PullRequest, description_template: Option<&str>) -> Self {
         let labels = pr
             .labels
             .unwrap_or_default()
             .into_iter()
             .map(|l| l.name)
             .collect();
+        let requested_teams: Vec<_> = pr
+            .requested_teams
+            .unwrap_or_default()
+            .into_iter()
+            .map(|team| team.slug)
+            .collect();
+        let requested_users: Vec<_> = pr
+            .requested_reviewers
+            .unwrap_or_default()
+            .into_iter()
+            .map(|user| user.login)
+            .collect();
+        let body = pr.body.unwrap_or_default();
         Self {
             id: *pr.id,
+            node_id: pr.node_id.unwrap_or_default(),
+            author_is_bot: pr.user.as_ref().is_some_and(|u| u.r#type == "Bot"),
             author: pr.user.map(|u| u.login).unwrap_or_default(),
             number: pr.number,
             commit_sha: pr.head.sha,
+            base_ref: pr.base.ref_field,
             draft: pr.draft.unwrap_or_default(),
             state: pr.state,
-            updated_at: pr.updated_at.unwrap_or(pr.created_at.unwrap_or_default()),
-            has_description: pr.body.unwrap_or_default() != "",
-            requested_reviewers_remaining: pr
-                .requested_reviewers
-                .map(|rr| rr.len())
-                .unwrap_or_default()
-                + pr.requested_teams.map(|rt| rt.len()).unwrap_or_default(),
+            created_at: pr.created_at.unwrap_or_default(),
+            updated_at: pr.updated_at,
+            has_description: is_description(&body, description_template),
+            body,
+            requested_reviewers_remaining: requested_users.len() + requested_teams.len(),
+            requested_users,
+            requested_teams,
             labels,
         }
     }
+
+    /// The last time anything happened to the PR: its last update, or its creation if it was
+    /// never updated.
+    pub fn last_activity(&self) -> DateTime<Utc> {
+        self.updated_at.unwrap_or(self.created_at)
+    }
+}
+
+/// Finds a comment by the given author with the given body, posted since the given time.
+pub fn find_identical_comment<'a>(
+    comments: &'a [Comment],
+    author: &str,
+    body: &str,
+    since: DateTime<Utc>,
+) -> Option<&'a Comment> {
+    comments.iter().find(|comment| {
+        comment.author == author
+            && comment.created_at >= since
+            && comment.body.trim() == body.trim()
+    })
+}
+
+/// Starts the comments recording failed merge attempts.
+const MERGE_FAILURE_SIGIL: &str = "### Merge attempt failed";
+
+/// The comment recording that merging the given commit failed.
+pub fn merge_failure_comment(commit_sha: &str, reason: &str) -> String {
+    format!("{MERGE_FAILURE_SIGIL}\nMerging {commit_sha} failed: {reason}\n")
+}
+
+/// Finds the latest comment by the given author recording a failed merge attempt.
+pub fn find_merge_failure_comment<'a>(
+    comments: &'a [Comment],
+    author: &str,
+) -> Option<&'a Comment> {
+    comments
+        .iter()
+        .filter(|comment| comment.author == author && comment.body.starts_with(MERGE_FAILURE_SIGIL))
+        .max_by_key(|comment| comment.updated_at)
+}
+
+/// Marks the comment explaining that the bot isn't allowed to merge PRs.
+const MERGE_FORBIDDEN_SIGIL: &str = "### Unable to merge";
+
+/// The comment asking humans to merge a PR, since the bot isn't allowed to.
+pub fn merge_forbidden_comment(reason: &str) -> String {
+    format!(
+        "{MERGE_FORBIDDEN_SIGIL}\nThis PR is ready to be merged, but I'm not allowed to merge \
+         it, so someone will have to do it: {reason}\n"
+    )
+}
+
+/// Whether the given author already explained on the PR that it isn't allowed to merge it.
+pub fn has_merge_forbidden_comment(comments: &[Comment], author: &str) -> bool {
+    comments
+        .iter()
+        .any(|comment| comment.author == author && comment.body.starts_with(MERGE_FORBIDDEN_SIGIL))
+}
+
+/// Starts the comment recording the block reasons last reported to the notification webhook.
+const BLOCKED_NOTIFIED_SIGIL: &str = "### Blocked";
+
+/// The comment recording that the webhook was notified of the given block reasons.
+pub fn blocked_notified_comment(reasons: &[String]) -> String {
+    let mut body = format!("{BLOCKED_NOTIFIED_SIGIL}\n");
+    if reasons.is_empty() {
+        body += "This PR isn't blocked anymore.\n";
+    }
+    for reason in reasons {
+        body += &format!("- {reason}\n");
+    }
+    body
+}
+
+/// Finds the comment by the given author recording the block reasons last reported to the
+/// webhook, and these reasons.
+pub fn find_blocked_notified_comment<'a>(
+    comments: &'a [Comment],
+    author: &str,
+) -> Option<(&'a Comment, Vec<String>)> {
+    let comment = comments
+        .iter()
+        .filter(|comment| {
+            comment.author == author && comment.body.starts_with(BLOCKED_NOTIFIED_SIGIL)
+        })
+        .max_by_key(|comment| comment.updated_at)?;
+    let reasons = comment
+        .body
+        .lines()
+        .filter_map(|line| line.strip_prefix("- "))
+        .map(str::to_owned)
+        .collect();
+    Some((comment, reasons))
+}
+
+/// Starts the comment announcing that a PR is going to be merged once the grace period ends.
+const READY_SIGIL: &str = "### Ready to merge";
+
+/// Starts the comment recording since when a PR has been ready to merge.
+const READY_SINCE_SIGIL: &str = "### Ready since";
+
+/// Starts the comments warning that a soft block label defers the merge.
+const SOFT_BLOCK_SIGIL: &str = "### Merge deferred";
+
+/// How long soft block labels defer merges by default.
+const DEFAULT_SOFT_BLOCK_PERIOD_SECS: u64 = 24 * 60 * 60;
+
+/// The comment warning that the PR will be merged anyway once the soft block ends.
+fn soft_block_comment(label: &str, until: DateTime<Utc>) -> String {
+    format!(
+        "{SOFT_BLOCK_SIGIL}\nThis PR has the `{label}` label, so I'll wait until {} before \
+         merging it. Please block it before then if it shouldn't be merged.\n",
+        until.format("%Y-%m-%d %H:%M UTC")
+    )
+}
+
+/// Command pre-authorizing the merge of a PR as soon as it's approved and its CI passes.
+const MERGE_WHEN_GREEN: &str = "/octobors merge-when-green";
+
+/// Whether a comment contains the merge-when-green command, on a line of its own.
+fn is_merge_when_green(body: &str) -> bool {
+    body.lines().any(|line| line.trim() == MERGE_WHEN_GREEN)
+}
+
+/// A PR that must be merged before another one, e.g. because they change two repos together.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct LinkedPr {
+    owner: String,
+    repo: String,
+    number: u64,
+}
+
+impl fmt::Display for LinkedPr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}#{}", self.owner, self.repo, self.number)
+    }
+}
+
+/// The PRs referenced after the marker in a PR body, e.g. `octobors-wait-for: org/repo#123`, one
+/// per line.
+fn linked_prs(body: &str, marker: &str) -> Vec<LinkedPr> {
+    body.lines()
+        .filter_map(|line| {
+            let reference = line.trim().strip_prefix(marker)?.trim();
+            let (path, number) = reference.split_once('#')?;
+            let (owner, repo) = path.split_once('/')?;
+            Some(LinkedPr {
+                owner: owner.to_owned(),
+                repo: repo.to_owned(),
+                number: number.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// Command waiving a required status for a single PR, e.g. `/octobors skip-check ci/flaky`.
+const SKIP_CHECK: &str = "/octobors skip-check";
+
+/// The statuses waived by the skip-check commands of a comment, one per line.
+fn skip_check_commands(body: &str) -> Vec<&str> {
+    body.lines()
+        .filter_map(|line| line.trim().strip_prefix(SKIP_CHECK))
+        .filter(|status| status.starts_with(char::is_whitespace))
+        .map(str::trim)
+        .collect()
+}
+
+/// Whether a PR body is an actual description, rather than nothing or just lines from the
+/// template, once HTML comments are removed.
+fn is_description(body: &str, template: Option<&str>) -> bool {
+    let Some(template) = template else {
+        return !body.is_empty();
+    };
+    let lines = |text: &str| -> HashSet<String> {
+        merge::remove_html_comments(text.to_owned())
+            .lines()
+            .map(|line| line.trim().to_owned())
+            .filter(|line| !line.is_empty())
+            .collect()
+    };
+    let body = lines(body);
+    !body.is_empty() && !body.is_subset(&lines(template))
+}
+
+/// A comment on a PR, that isn't associated to a review.
+#[derive(Debug, Clone)]
+pub struct Comment {
+    pub id: u64,
+    pub author: String,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Comment {
+    pub fn from_octocrab_comment(comment: models::issues::Comment) -> Option<Self> {
+        Some(Self {
+            id: *comment.id,
+            author: comment.user.login,
+            body: comment.body?,
+            created_at: comment.created_at,
+            updated_at: comment.updated_at.unwrap_or(comment.created_at),
+        })
+    }
+}
+
+/// A file changed by a PR.
+#[derive(Debug, Clone)]
+pub struct ChangedFile {
+    pub path: String,
+    pub additions: u64,
+    pub deletions: u64,
+}
+
+impl ChangedFile {
+    pub fn from_octocrab_diff_entry(entry: models::repos::DiffEntry) -> Self {
+        Self {
+            path: entry.filename,
+            additions: entry.additions,
+            deletions: entry.deletions,
+        }
+    }
+}
+
+/// The latest status reported for a given context on a commit.
+#[derive(Debug, Clone)]
+pub struct CommitStatus {
+    pub state: StatusState,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl CommitStatus {
+    pub fn from_octocrab_status(status: models::Status) -> Option<(String, Self)> {
+        Some((
+            status.context?,
+            Self {
+                state: status.state,
+                updated_at: status.updated_at,
+            },
+        ))
+    }
+}
+
+/// A check run reported on a commit.
+#[derive(Debug, Clone)]
+pub struct CheckRun {
+    pub name: String,
+    /// None until the check run completes.
+    pub conclusion: Option<String>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+impl CheckRun {
+    pub fn from_octocrab_check_run(check_run: models::checks::CheckRun) -> Self {
+        Self {
+            name: check_run.name,
+            conclusion: check_run.conclusion,
+            started_at: check_run.started_at,
+            completed_at: check_run.completed_at,
+        }
+    }
+
+    /// The status the check run counts as, unless it's ignored.
+    pub(crate) fn status(
+        &self,
+        conclusion_map: &HashMap<String, context::Verdict>,
+    ) -> Option<CommitStatus> {
+        let verdict = self
+            .conclusion
+            .as_ref()
+            .and_then(|conclusion| conclusion_map.get(conclusion).copied())
+            .unwrap_or_else(|| context::Verdict::of_conclusion(self.conclusion.as_deref()));
+        let state = match verdict {
+            context::Verdict::Pass => StatusState::Success,
+            context::Verdict::Fail => StatusState::Failure,
+            context::Verdict::Pending => StatusState::Pending,
+            context::Verdict::Ignore => return None,
+        };
+        Some(CommitStatus {
+            state,
+            updated_at: self.completed_at.or(self.started_at),
+        })
+    }
+}
+
+/// Keeps the latest check run of each name, by start time, as re-running a workflow creates new
+/// check runs while the superseded ones linger.
+pub(crate) fn latest_check_runs(check_runs: Vec<CheckRun>) -> Vec<CheckRun> {
+    let mut latest: HashMap<String, CheckRun> = HashMap::new();
+    for check_run in check_runs {
+        match latest.get(&check_run.name) {
+            Some(existing) if existing.started_at >= check_run.started_at => {}
+            _ => {
+                latest.insert(check_run.name.clone(), check_run);
+            }
+        }
+    }
+    latest.into_values().collect()
+}
+
+enum CiStatus {
+    Passed,
+    NotPassed {
+        /// Whether some reported statuses, or all of the required status groups, haven't passed.
+        reported_failing: bool,
+        /// Required statuses that have been pending for too long.
+        pending_too_long: Vec<String>,
+        /// Required statuses that weren't reported at all.
+        missing: Vec<String>,
+    },
 }
 
 enum PrApprovalStatus {
     Approved,
     MissingReview { from_users: Vec<String> },
+    MissingMandatoryApproval { from_users: Vec<String> },
 }
 
 pub struct Analyzer<'a> {
@@ -88,8 +490,33 @@ pub struct Analyzer<'a> {
     // We optionally keep a local version of these fields using `RemoteData`
     // so we can pre-set the data with values in order to not hit the GitHub
     // API in unit tests
-    reviews: RemoteData<Vec<Review>>,
-    statuses: RemoteData<HashMap<String, StatusState>>,
+    statuses: RemoteData<HashMap<String, CommitStatus>>,
+    check_runs: RemoteData<Vec<CheckRun>>,
+    deployments: RemoteData<HashMap<String, context::DeploymentState>>,
+    last_commit_at: RemoteData<Option<DateTime<Utc>>>,
+    commits_behind: RemoteData<u64>,
+    rebaseable: RemoteData<bool>,
+    /// When the PR was last active, which may be earlier than its last update.
+    last_activity: Cell<DateTime<Utc>>,
+    // Needed by several checks, so it's fetched at most once.
+    changed_files: RefCell<RemoteData<Vec<ChangedFile>>>,
+    // Same for the timeline, which tells when the PR was ready for review or labeled, who was
+    // requested to review it, and its activity.
+    timeline: RefCell<RemoteData<Vec<context::TimelineEvent>>>,
+    // Same for the comments, which many checks look through.
+    comments: RefCell<RemoteData<Vec<Comment>>>,
+    // And the reviews.
+    reviews: RefCell<RemoteData<Vec<Review>>>,
+    /// The required statuses waived for this PR, with who waived them.
+    waivers: RefCell<HashMap<String, String>>,
+    /// The advisory reviewers who requested changes, which doesn't block the PR.
+    advisory_concerns: RefCell<Vec<String>>,
+    /// The reviewers who approved the PR.
+    approvers: RefCell<Vec<String>>,
+    /// The requested reviewers who started a review they haven't submitted yet. Github only lists
+    /// the pending review of the authenticated user, so this is limited to the bot's account being
+    /// a requested reviewer.
+    reviews_in_progress: RefCell<Vec<String>>,
 }
 
 impl<'a> Analyzer<'a> {
@@ -98,9 +525,301 @@ impl<'a> Analyzer<'a> {
             pr,
             client,
             config,
-            reviews: RemoteData::Remote,
             statuses: RemoteData::Remote,
+            check_runs: RemoteData::Remote,
+            deployments: RemoteData::Remote,
+            last_commit_at: RemoteData::Remote,
+            commits_behind: RemoteData::Remote,
+            rebaseable: RemoteData::Remote,
+            last_activity: Cell::new(pr.last_activity()),
+            changed_files: RefCell::new(RemoteData::Remote),
+            timeline: RefCell::new(RemoteData::Remote),
+            comments: RefCell::new(RemoteData::Remote),
+            reviews: RefCell::new(RemoteData::Remote),
+            waivers: RefCell::new(HashMap::new()),
+            advisory_concerns: RefCell::new(Vec::new()),
+            approvers: RefCell::new(Vec::new()),
+            reviews_in_progress: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Builds the body of a comment explaining why the PR is blocked, one line per reason.
+    fn block_reasons_message(&self, reasons: &HashSet<BlockReason>) -> String {
+        let mut lines = Vec::new();
+
+        for reason in reasons {
+            match reason {
+                BlockReason::DraftPr => {
+                    lines.push("- This PR is a draft.\n".to_owned());
+                }
+                BlockReason::ClosedPr => {
+                    lines.push("- This PR is closed.\n".to_owned());
+                }
+                BlockReason::InactivePr => {
+                    // Probably the bot was inactive for too long, don't report here.
+                }
+                BlockReason::MissingReviews => {
+                    lines.push(
+                        "- Still waiting for requested reviewers to review this.\n".to_owned(),
+                    );
+                }
+                BlockReason::ReviewerLostAccess { users } => {
+                    let users = users
+                        .iter()
+                        .map(|nick| format!("@{nick}"))
+                        .collect::<Vec<_>>();
+                    lines.push(format!(
+                        "- {} can't access this repo anymore, so please remove them from the \
+                         reviewers.\n",
+                        users.join(", ")
+                    ));
+                }
+                BlockReason::MissingReviewApproval { from_users } => {
+                    let mut line = "- There are some missing review approvals".to_owned();
+                    if self.config.comment_requests_change {
+                        line += " (and comments count as request-changes)";
+                    }
+                    if !from_users.is_empty() {
+                        let mut from_users = from_users
+                            .iter()
+                            .map(|nick| format!("@{nick}"))
+                            .collect::<Vec<_>>();
+                        from_users.sort();
+                        line += ". Missing approvals from: ";
+                        line += &from_users.join(", ");
+                    }
+                    line += ".\n";
+                    lines.push(line);
+                }
+                BlockReason::MissingMandatoryApproval { from_users } => {
+                    let mut from_users = from_users
+                        .iter()
+                        .map(|nick| format!("@{nick}"))
+                        .collect::<Vec<_>>();
+                    from_users.sort();
+                    lines.push(format!(
+                        "- Missing approvals from mandatory reviewers: {}.\n",
+                        from_users.join(", ")
+                    ));
+                }
+                BlockReason::CiNotPassing => {
+                    lines.push("- Github checks haven't passed yet.\n".to_owned());
+                }
+                BlockReason::RequiredCheckMissing { context } => {
+                    lines.push(format!(
+                        "- Required check '{context}' was never reported; was it started?\n"
+                    ));
+                }
+                BlockReason::StatusPendingTooLong { statuses } => {
+                    for status in statuses {
+                        lines.push(format!(
+                            "- Check '{status}' has been pending for too long.\n"
+                        ));
+                    }
+                }
+                BlockReason::MissingDescription => {
+                    lines.push("- This PR lacks a description.\n".to_owned());
+                }
+                BlockReason::BlockedByLabel => {
+                    lines.push(format!(
+                        "- This PR is blocked by the '{}' label.\n",
+                        self.config.block_merge_label.as_ref().unwrap()
+                    ));
+                }
+                BlockReason::UntrustedBotAuthor => {
+                    lines.push(format!(
+                        "- @{} is a bot whose PRs aren't merged automatically.\n",
+                        self.pr.author
+                    ));
+                }
+                BlockReason::MissingAutomergeLabel => {
+                    lines.push(format!(
+                        "- This PR needs the '{}' label to be merged.\n",
+                        self.config.automerge_label.as_ref().unwrap()
+                    ));
+                }
+                BlockReason::InsideGracePeriod => {
+                    lines.push("- In grace period; I'll retry in a bit.\n".to_owned());
+                }
+                BlockReason::DeploymentNotSuccessful { env } => {
+                    lines.push(format!(
+                        "- The deployment to '{env}' hasn't succeeded yet.\n"
+                    ));
+                }
+                BlockReason::ExternalGateNotSatisfied => {
+                    lines.push(
+                        "- The external approval service hasn't approved this PR.\n".to_owned(),
+                    );
+                }
+                BlockReason::TooLarge => {
+                    lines.push("- This PR is large; please merge it manually.\n".to_owned());
+                }
+                BlockReason::InsufficientReviewBreadth => {
+                    lines.push(format!(
+                        "- This PR needs reviews from at least {} people.\n",
+                        self.config.min_distinct_reviewers.unwrap_or_default()
+                    ));
+                }
+                BlockReason::ForbiddenStatus { statuses } => {
+                    lines.push(format!(
+                        "- These checks prevent merging: {}.\n",
+                        statuses.join(", ")
+                    ));
+                }
+                BlockReason::BaseBranchRed => {
+                    lines.push(format!(
+                        "- The checks of '{}' are failing; I'll wait until they're fixed.\n",
+                        self.pr.base_ref
+                    ));
+                }
+                BlockReason::SoftBlocked { label, until } => {
+                    lines.push(format!(
+                        "- This PR has the `{label}` label; it will be merged after {} unless \
+                         something else blocks it.\n",
+                        until.format("%Y-%m-%d %H:%M UTC")
+                    ));
+                }
+                BlockReason::MissingCrossTeamApproval => {
+                    lines.push(
+                        "- This PR needs an approval from someone outside of the author's teams.\n"
+                            .to_owned(),
+                    );
+                }
+                BlockReason::NoChecksRan => {
+                    lines.push("- No checks have run on this PR yet.\n".to_owned());
+                }
+                BlockReason::WaitingOnLinkedPr { prs } => {
+                    lines.push(format!(
+                        "- Waiting for these PRs to be merged first: {}.\n",
+                        prs.join(", ")
+                    ));
+                }
+                BlockReason::DisallowedBaseBranch => {
+                    lines.push(format!(
+                        "- Only PRs targeting the default branch are merged, not '{}'.\n",
+                        self.pr.base_ref
+                    ));
+                }
+                BlockReason::NotRebaseable => {
+                    lines.push(format!(
+                        "- This PR can't be rebased onto '{}'; please resolve the conflicts or \
+                         merge it manually.\n",
+                        self.pr.base_ref
+                    ));
+                }
+                BlockReason::MergeCooldown => {
+                    lines.push("- Merging failed recently; I'll retry in a bit.\n".to_owned());
+                }
+                BlockReason::BranchBehind => {
+                    lines.push(format!(
+                        "- The branch isn't up to date with '{}'.\n",
+                        self.pr.base_ref
+                    ));
+                }
+            }
+        }
+
+        if !lines.is_empty() {
+            for (status, user) in self.waivers.borrow().iter() {
+                lines.push(format!("- Check '{status}' was waived by @{user}.\n"));
+            }
+            for user in self.advisory_concerns.borrow().iter() {
+                lines.push(format!("- There are advisory concerns from @{user}.\n"));
+            }
+            for user in self.reviews_in_progress.borrow().iter() {
+                lines.push(format!("- @{user} is reviewing this PR.\n"));
+            }
+        }
+
+        // Keep the message stable across runs, so an up-to-date status comment isn't edited.
+        lines.sort();
+        lines.concat()
+    }
+
+    /// Whether the bot should answer a comment written by the given user.
+    async fn may_answer(&self, author: &str) -> Result<bool> {
+        match &self.config.respond_to_authors {
+            None => Ok(true),
+            Some(context::AuthorFilter::Users(users)) => Ok(users.iter().any(|u| u == author)),
+            Some(context::AuthorFilter::Collaborators(_)) => {
+                self.client
+                    .is_collaborator(self.config.name.as_str(), author)
+                    .await
+            }
+        }
+    }
+
+    /// Whether a collaborator asked for the PR to be merged as soon as it's green, skipping the
+    /// grace period.
+    async fn merge_when_green_requested(&self) -> Result<bool> {
+        let comments = self.get_pr_comments().await?;
+        let mut commands = comments
+            .iter()
+            .filter(|comment| is_merge_when_green(&comment.body))
+            .peekable();
+        if commands.peek().is_none() {
+            return Ok(false);
+        }
+        let bot_nick = self.client.get_bot_nick().await?;
+        for comment in commands {
+            // The bot never requests it, even if it quotes the command.
+            if comment.author == bot_nick {
+                continue;
+            }
+            if self
+                .client
+                .is_collaborator(self.config.name.as_str(), &comment.author)
+                .await?
+            {
+                log::info!("{} asked to merge once green", comment.author);
+                return Ok(true);
+            }
+            log::trace!(
+                "Ignoring a merge-when-green command from {}",
+                comment.author
+            );
+        }
+        Ok(false)
+    }
+
+    /// The required statuses collaborators waived for the head commit of the PR with the skip-check
+    /// command, with who waived them.
+    async fn status_waivers(&self) -> Result<HashMap<String, String>> {
+        let mut waivers = HashMap::new();
+        let comments = self.get_pr_comments().await?;
+        let mut commands = comments
+            .iter()
+            .map(|comment| (comment, skip_check_commands(&comment.body)))
+            .filter(|(_, statuses)| !statuses.is_empty())
+            .peekable();
+        if commands.peek().is_none() {
+            return Ok(waivers);
+        }
+        let bot_nick = self.client.get_bot_nick().await?;
+        let last_commit_at = self.get_pr_last_commit_at().await?;
+        for (comment, statuses) in commands {
+            // The bot never waives statuses, even if it quotes the command.
+            if comment.author == bot_nick {
+                continue;
+            }
+            // A waiver only holds for the head commit it was posted on, new commits need another.
+            if last_commit_at.is_some_and(|at| comment.created_at < at) {
+                log::trace!("Ignoring a skip-check command posted before the last commit");
+                continue;
+            }
+            if !self
+                .client
+                .is_collaborator(self.config.name.as_str(), &comment.author)
+                .await?
+            {
+                log::trace!("Ignoring a skip-check command from {}", comment.author);
+                continue;
+            }
+            for status in statuses {
+                waivers.insert(status.to_owned(), comment.author.clone());
+            }
         }
+        Ok(waivers)
     }
 
     async fn analyze_comments(
@@ -113,86 +832,193 @@ impl<'a> Analyzer<'a> {
         let user_id = self.client.get_bot_nick().await?;
         let bot_mention = format!("@{user_id}");
 
-        let pr_comments = self
-            .client
-            .get_pull_request_comments(self.config.name.as_str(), self.pr.number)
-            .await?;
-        let mut looking_for_response = false;
+        let pr_comments = self.get_pr_comments().await?;
+        let mut last_mention = None;
+        let mut status_comment = None;
 
-        for (author, body) in pr_comments.into_iter().filter_map(|comment| {
-            if let Some(body) = comment.body {
-                Some((comment.user.login, body))
-            } else {
-                None
-            }
-        }) {
-            if looking_for_response && author == user_id && body.starts_with(SIGIL) {
-                log::trace!("Found response to the user asking why the PR is blocked");
-                looking_for_response = false;
+        for comment in &pr_comments {
+            if comment.author == user_id && comment.body.starts_with(SIGIL) {
+                status_comment = Some(comment);
             }
-            if author != user_id && body.contains(&bot_mention) {
-                log::trace!("Found a comment asking mentioning the bot and asking why it's stuck");
-                looking_for_response = true;
+            if self.config.react_to_comments
+                && comment.author != user_id
+                && comment.body.contains(&bot_mention)
+            {
+                if self.may_answer(&comment.author).await? {
+                    log::trace!(
+                        "Found a comment asking mentioning the bot and asking why it's stuck"
+                    );
+                    last_mention = Some(comment.created_at);
+                } else {
+                    log::trace!("Ignoring a mention from {}", comment.author);
+                }
             }
         }
 
-        if looking_for_response {
-            let mut body = String::new();
+        // The status comment is edited rather than reposted, so it answers a mention if it has
+        // been updated after it.
+        let looking_for_response = match (last_mention, status_comment) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            (Some(asked_at), Some(status_comment)) => {
+                if status_comment.updated_at >= asked_at {
+                    log::trace!("Found response to the user asking why the PR is blocked");
+                    false
+                } else {
+                    true
+                }
+            }
+        };
 
-            for reason in reasons {
-                match reason {
-                    BlockReason::DraftPr => {
-                        body += "- This PR is a draft.\n";
-                    }
-                    BlockReason::ClosedPr => {
-                        body += "- This PR is closed.\n";
-                    }
-                    BlockReason::InactivePr => {
-                        // Probably the bot was inactive for too long, don't report here.
-                    }
-                    BlockReason::MissingReviews => {
-                        body += "- Still waiting for requested reviewers to review this.\n";
-                    }
-                    BlockReason::MissingReviewApproval { from_users } => {
-                        body += "- There are some missing review approvals";
-                        if self.config.comment_requests_change {
-                            body += " (and comments count as request-changes)";
-                        }
-                        if !from_users.is_empty() {
-                            body += ". Missing approvals from: ";
-                            body += &from_users
-                                .iter()
-                                .map(|nick| format!("@{nick}"))
-                                .collect::<Vec<_>>()
-                                .join(", ");
-                        }
-                        body += ".\n";
-                    }
-                    BlockReason::CiNotPassing => {
-                        body += "- Github checks haven't passed yet.\n";
-                    }
-                    BlockReason::MissingDescription => {
-                        body += "- This PR lacks a description.\n";
-                    }
-                    BlockReason::BlockedByLabel => {
-                        body += &format!(
-                            "- This PR is blocked by the '{}' label.\n",
-                            self.config.block_merge_label.as_ref().unwrap()
-                        );
-                    }
-                    BlockReason::InsideGracePeriod => {
-                        body += "- In grace period; I'll retry in a bit.\n";
-                    }
+        if self.config.proactive_status_comment {
+            if reasons.contains(&BlockReason::InactivePr) {
+                // Nothing changed since the status comment was last updated.
+                return Ok(());
+            }
+
+            let body = if reasons.is_empty() {
+                format!("{SIGIL}\n{ALL_GOOD}")
+            } else {
+                format!("{SIGIL}\n{}", self.block_reasons_message(reasons))
+            };
+
+            match status_comment {
+                Some(comment) if comment.body == body => {
+                    log::trace!("Status comment is up to date");
                 }
+                Some(comment) => {
+                    actions.update_comment(comment.id, body);
+                }
+                None if !reasons.is_empty() => {
+                    actions.post_comment(body);
+                }
+                None => {}
             }
 
+            // Anyone asking why the PR is stuck can read the status comment.
+            return Ok(());
+        }
+
+        if looking_for_response {
+            let mut body = self.block_reasons_message(reasons);
+
             if body.is_empty() {
                 body += "Sorry, I was taking a nice little nap; will get back to work now!\n";
             }
 
-            actions.post_comment(format!("{SIGIL}\n{body}"));
+            let body = format!("{SIGIL}\n{body}");
+            match status_comment {
+                Some(comment) => actions.update_comment(comment.id, body),
+                None => actions.post_comment(body),
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Skips the grace period of PRs that have been ready to merge for longer than it, since
+    /// their last commit. When they became ready is recorded in a comment, which is reset when
+    /// they stop being ready, or get new commits.
+    async fn analyze_ready_since(
+        &self,
+        reasons: &mut HashSet<BlockReason>,
+        actions: &mut Actions,
+    ) -> Result<()> {
+        if [
+            BlockReason::DraftPr,
+            BlockReason::ClosedPr,
+            BlockReason::InactivePr,
+        ]
+        .iter()
+        .any(|reason| reasons.contains(reason))
+        {
+            return Ok(());
+        }
+        // Ready PRs outside of the grace period are merged right away.
+        let ready = reasons.len() == 1 && reasons.contains(&BlockReason::InsideGracePeriod);
+        let bot_nick = self.client.get_bot_nick().await?;
+        let comments = self.get_pr_comments().await?;
+        let record = comments.iter().find(|comment| {
+            comment.author == bot_nick && comment.body.starts_with(READY_SINCE_SIGIL)
+        });
+        let since = record.and_then(|comment| ready_since(&comment.body, &self.pr.commit_sha));
+        let body = match (ready, since) {
+            (true, Some(since)) => {
+                if self.outside_grace_period_since(since) {
+                    log::info!("Ready to merge since {since}, skipping the grace period");
+                    reasons.remove(&BlockReason::InsideGracePeriod);
+                }
+                return Ok(());
+            }
+            (true, None) => ready_since_comment(&self.pr.commit_sha, Utc::now()),
+            (false, Some(_)) => format!("{READY_SINCE_SIGIL}\nThis PR isn't ready to merge.\n"),
+            (false, None) => return Ok(()),
+        };
+        match record {
+            Some(comment) => actions.update_comment(comment.id, body),
+            None => actions.post_comment(body),
+        };
+        Ok(())
+    }
+
+    /// Announces once that the PR is going to be merged, as soon as only the grace period is
+    /// left.
+    async fn analyze_ready_comment(
+        &self,
+        text: &str,
+        reasons: &HashSet<BlockReason>,
+        actions: &mut Actions,
+    ) -> Result<()> {
+        if reasons.len() != 1 || !reasons.contains(&BlockReason::InsideGracePeriod) {
+            return Ok(());
+        }
+        let bot_nick = self.client.get_bot_nick().await?;
+        let announced = self
+            .get_pr_comments()
+            .await?
+            .iter()
+            .any(|comment| comment.author == bot_nick && comment.body.starts_with(READY_SIGIL));
+        if !announced {
+            actions.post_comment(format!("{READY_SIGIL}\n{}\n", text.trim_end()));
         }
+        Ok(())
+    }
 
+    /// Requests changes with a review while the PR is blocked, and dismisses the review once it
+    /// isn't anymore.
+    async fn analyze_review(
+        &self,
+        reasons: &HashSet<BlockReason>,
+        actions: &mut Actions,
+    ) -> Result<()> {
+        let bot_nick = self.client.get_bot_nick().await?;
+        let active_reviews: Vec<_> = self
+            .get_pr_reviews()
+            .await?
+            .into_iter()
+            .filter(|review| {
+                review.user_name == bot_nick
+                    && review.state == models::pulls::ReviewState::ChangesRequested
+            })
+            .collect();
+
+        if reasons.is_empty() {
+            for review in active_reviews {
+                actions.dismiss_review(review.id);
+            }
+            return Ok(());
+        }
+        let body = self.block_reasons_message(reasons);
+        match active_reviews.last() {
+            None => {
+                actions.request_changes(body);
+            }
+            // The PR is still blocked, but not for the same reasons.
+            Some(review) if review.body != body => {
+                actions.update_review(review.id, body);
+            }
+            Some(_) => log::trace!("Already requesting changes"),
+        }
         Ok(())
     }
 
@@ -205,56 +1031,497 @@ impl<'a> Analyzer<'a> {
         if pr.state == Some(IssueState::Closed) {
             reasons.insert(BlockReason::ClosedPr);
         }
-        if pr.updated_at < Utc::now() - Duration::minutes(60) {
+        // Soft blocked PRs must be merged once the soft block ends, however quiet they are.
+        if self.last_activity.get() < Utc::now() - Duration::minutes(60)
+            && self.soft_block_label().is_none()
+        {
             reasons.insert(BlockReason::InactivePr);
         }
         let block_on_reviews = self.requires_reviews();
-        if block_on_reviews && pr.requested_reviewers_remaining != 0 {
+        // Team requests aren't always cleared when a member of the team approves, so they're
+        // checked against the actual reviews later on.
+        if block_on_reviews && pr.requested_reviewers_remaining > pr.requested_teams.len() {
             reasons.insert(BlockReason::MissingReviews);
         }
-        if self.merge_blocked_by_label() {
-            reasons.insert(BlockReason::BlockedByLabel);
+        match self.resolve_merge_eligibility(&pr.labels) {
+            Eligibility::Blocked => {
+                reasons.insert(BlockReason::BlockedByLabel);
+            }
+            Eligibility::NotOptedIn => {
+                reasons.insert(BlockReason::MissingAutomergeLabel);
+            }
+            Eligibility::OptedIn | Eligibility::Default => {}
+        }
+        if let Some(trusted) = &self.config.trusted_authors {
+            if pr.author_is_bot && !trusted.contains(&pr.author) {
+                reasons.insert(BlockReason::UntrustedBotAuthor);
+            }
         }
         if self.config.needs_description_label.is_some() && !self.pr.has_description {
             reasons.insert(BlockReason::MissingDescription);
         }
-        if !self.outside_grace_period() {
+        if self.config.grace_anchor == context::GraceAnchor::LastUpdate
+            && !self.outside_grace_period()
+        {
             reasons.insert(BlockReason::InsideGracePeriod);
         }
         reasons
     }
 
+    /// Requests reviews from members of the configured team, when nobody was asked to review the
+    /// PR and nobody reviewed it yet. The bot only does so once per PR, which its review requests
+    /// on the timeline tell, even if they were removed since.
+    async fn analyze_auto_assign(
+        &self,
+        auto_assign: &context::AutoAssignReviewers,
+        actions: &mut Actions,
+    ) -> Result<()> {
+        if self.pr.requested_reviewers_remaining > 0 {
+            return Ok(());
+        }
+        let bot_nick = self.client.get_bot_nick().await?;
+        let reviewed = self
+            .get_pr_reviews()
+            .await?
+            .iter()
+            .any(|review| review.user_name != bot_nick);
+        if reviewed {
+            return Ok(());
+        }
+        let assigned = self.get_pr_timeline().await?.iter().any(|event| {
+            event.event == models::Event::ReviewRequested
+                && event.actor.as_deref() == Some(bot_nick.as_str())
+        });
+        if assigned {
+            log::debug!("Reviewers were already assigned");
+            return Ok(());
+        }
+        let members = self.client.get_team_members(&auto_assign.from_team).await?;
+        let reviewers =
+            pick_reviewers(&members, &self.pr.author, auto_assign.count, self.pr.number);
+        if !reviewers.is_empty() {
+            log::info!("Requesting reviews from {}", reviewers.join(", "));
+            actions.request_reviewers(reviewers);
+        }
+        Ok(())
+    }
+
+    /// Requested reviewers who can't access the repo anymore will never review, so they're not
+    /// waited on. They should be removed from the reviewers instead.
+    async fn analyze_requested_reviewers(&self, reasons: &mut HashSet<BlockReason>) -> Result<()> {
+        let mut lost = Vec::new();
+        for user in &self.pr.requested_users {
+            if !self.client.is_collaborator(&self.config.name, user).await? {
+                lost.push(user.clone());
+            }
+        }
+        if lost.is_empty() {
+            return Ok(());
+        }
+        let waiting_on = self
+            .pr
+            .requested_reviewers_remaining
+            .saturating_sub(self.pr.requested_teams.len());
+        if waiting_on == lost.len() {
+            reasons.remove(&BlockReason::MissingReviews);
+        }
+        reasons.insert(BlockReason::ReviewerLostAccess { users: lost });
+        Ok(())
+    }
+
+    /// The first soft block label of the PR, if any.
+    fn soft_block_label(&self) -> Option<&String> {
+        self.config
+            .soft_block_labels
+            .iter()
+            .find(|label| has_label(&self.pr.labels, label).is_some())
+    }
+
+    /// Defers the merge of PRs with a soft block label, counting from when the label was added,
+    /// and warns about it once.
+    async fn analyze_soft_block(
+        &self,
+        reasons: &mut HashSet<BlockReason>,
+        actions: &mut Actions,
+    ) -> Result<()> {
+        let Some(label) = self.soft_block_label() else {
+            return Ok(());
+        };
+        let since = self
+            .get_pr_labeled_at()
+            .await?
+            .unwrap_or(self.pr.created_at);
+        let period = self
+            .config
+            .soft_block_period_secs
+            .unwrap_or(DEFAULT_SOFT_BLOCK_PERIOD_SECS);
+        let until = since + Duration::seconds(period as i64);
+        if Utc::now() >= until {
+            log::info!("Soft block by `{label}` ended at {until}");
+            return Ok(());
+        }
+        reasons.insert(BlockReason::SoftBlocked {
+            label: label.clone(),
+            until,
+        });
+
+        let bot_nick = self.client.get_bot_nick().await?;
+        let warned = self.get_pr_comments().await?.iter().any(|comment| {
+            comment.author == bot_nick && comment.body.starts_with(SOFT_BLOCK_SIGIL)
+        });
+        if !warned {
+            actions.post_comment(soft_block_comment(label, until));
+        }
+        Ok(())
+    }
+
+    /// A PR that was just marked as ready for review is likely to get updated right after, e.g.
+    /// when its labels are reconciled. If that's the case, measure the grace period from the
+    /// moment it became ready instead.
+    async fn analyze_ready_for_review(&self, reasons: &mut HashSet<BlockReason>) -> Result<()> {
+        let (Some(grace_period), Some(ready_at)) = (
+            self.grace_period(),
+            self.get_pr_ready_for_review_at().await?,
+        ) else {
+            return Ok(());
+        };
+        if self.last_activity.get() - ready_at < grace_period
+            && self.outside_grace_period_since(ready_at)
+        {
+            log::info!("Grace period elapsed since the PR became ready for review");
+            reasons.remove(&BlockReason::InsideGracePeriod);
+        }
+        Ok(())
+    }
+
     async fn analyze_extended_checks(
         &self,
         reasons: &mut HashSet<BlockReason>,
     ) -> anyhow::Result<()> {
-        let statuses_passed = self.pr_statuses_passed().await?;
-        if !statuses_passed {
-            reasons.insert(BlockReason::CiNotPassing);
+        let statuses = self.get_pr_statuses().await?;
+        // Waivers are only looked up when needed, as it costs API calls.
+        let all_passed = self
+            .required_statuses()
+            .iter()
+            .chain(self.config.required_status_groups.iter().flatten())
+            .all(|required| {
+                self.tolerated(required)
+                    || statuses.get(required).map(|s| &s.state) == Some(&StatusState::Success)
+            });
+        if !all_passed {
+            *self.waivers.borrow_mut() = self.status_waivers().await?;
+        }
+        match self.pr_statuses_passed(&statuses) {
+            CiStatus::Passed => {
+                if self.config.grace_anchor == context::GraceAnchor::CiCompletion {
+                    let completed_at = self.ci_completed_at(&statuses);
+                    if !self.outside_grace_period_since(completed_at) {
+                        reasons.insert(BlockReason::InsideGracePeriod);
+                    }
+                }
+            }
+            CiStatus::NotPassed {
+                reported_failing,
+                pending_too_long,
+                missing,
+            } => {
+                if reported_failing {
+                    reasons.insert(BlockReason::CiNotPassing);
+                }
+                for context in missing {
+                    reasons.insert(BlockReason::RequiredCheckMissing { context });
+                }
+                if !pending_too_long.is_empty() {
+                    reasons.insert(BlockReason::StatusPendingTooLong {
+                        statuses: pending_too_long,
+                    });
+                }
+            }
+        }
+        if self.config.require_at_least_one_check
+            && statuses.keys().all(|name| name == context::CHECK_RUN_NAME)
+        {
+            reasons.insert(BlockReason::NoChecksRan);
+        }
+        if self.config.grace_anchor == context::GraceAnchor::LastCommitOrReview {
+            let anchor = self.last_commit_or_review_at().await?;
+            if !self.outside_grace_period_since(anchor) {
+                reasons.insert(BlockReason::InsideGracePeriod);
+            }
+        }
+        let forbidden = self.forbidden_statuses(&statuses);
+        if !forbidden.is_empty() {
+            reasons.insert(BlockReason::ForbiddenStatus {
+                statuses: forbidden,
+            });
         }
-        let pr_approved = self.pr_approved(self.requires_reviews()).await?;
-        if let PrApprovalStatus::MissingReview { from_users } = pr_approved {
-            reasons.insert(BlockReason::MissingReviewApproval { from_users });
+        let block_on_reviews = self.requires_reviews();
+        let mut reviews = self.get_pr_reviews().await?;
+        log::debug!(reviews = ?reviews, "Got PR reviews");
+        // The bot's own reviews, e.g. requesting changes with `block_via_review`, don't count.
+        let bot_nick = self.client.get_bot_nick().await?;
+        reviews.retain(|review| review.user_name != bot_nick);
+        if let Some(min) = self.config.min_distinct_reviewers {
+            let reviewers = self.distinct_reviewers(&reviews);
+            if reviewers < min {
+                log::info!("Reviewed by {reviewers} people, expected at least {min}");
+                reasons.insert(BlockReason::InsufficientReviewBreadth);
+            }
+        }
+        let min_approvals =
+            if self.config.sensitive_paths.is_empty() && self.config.size_approvals.is_empty() {
+                None
+            } else {
+                let files = self.get_pr_changed_files().await?;
+                let paths: Vec<_> = files.iter().map(|file| file.path.clone()).collect();
+                let changes = files
+                    .iter()
+                    .map(|file| file.additions + file.deletions)
+                    .sum();
+                path_labels::required_approvals(&self.config.sensitive_paths, &paths).max(
+                    size_required_approvals(&self.config.size_approvals, changes),
+                )
+            };
+        // Only looked up when needed, as it costs API calls.
+        let requested_reviewers = if self.comments_only_from_requested_reviewers() {
+            self.get_pr_requested_reviewers().await?
+        } else {
+            Vec::new()
+        };
+        match self.pr_approved(
+            reviews,
+            requested_reviewers,
+            block_on_reviews,
+            min_approvals,
+        ) {
+            PrApprovalStatus::Approved => {
+                if block_on_reviews && !self.pr.requested_teams.is_empty() {
+                    let waiting_on = self.teams_without_approval().await?;
+                    if waiting_on.is_empty() {
+                        log::info!(
+                            "Ignoring the review requests for teams {}, since members approved",
+                            self.pr.requested_teams.join(", ")
+                        );
+                    } else {
+                        log::info!(
+                            "No member of the teams {} approved the PR",
+                            waiting_on.join(", ")
+                        );
+                        reasons.insert(BlockReason::MissingReviews);
+                    }
+                }
+            }
+            PrApprovalStatus::MissingReview { from_users } => {
+                if block_on_reviews && !self.pr.requested_teams.is_empty() {
+                    reasons.insert(BlockReason::MissingReviews);
+                }
+                reasons.insert(BlockReason::MissingReviewApproval { from_users });
+            }
+            PrApprovalStatus::MissingMandatoryApproval { from_users } => {
+                reasons.insert(BlockReason::MissingMandatoryApproval { from_users });
+            }
+        }
+        if self.config.require_cross_team_approval && !self.approved_across_teams().await? {
+            reasons.insert(BlockReason::MissingCrossTeamApproval);
+        }
+        if !self.config.required_deployments.is_empty() {
+            let deployments = self.get_pr_deployments().await?;
+            for env in &self.config.required_deployments {
+                let state = deployments.get(env);
+                if state != Some(&context::DeploymentState::Success) {
+                    log::info!(
+                        "Required deployment to `{env}` has not succeeded. State is {state:?}"
+                    );
+                    reasons.insert(BlockReason::DeploymentNotSuccessful { env: env.clone() });
+                }
+            }
+        }
+        if self.config.default_branch_only {
+            let default_branch = self.client.get_default_branch(&self.config.name).await?;
+            if self.pr.base_ref != default_branch {
+                reasons.insert(BlockReason::DisallowedBaseBranch);
+            }
+        }
+        if self.config.require_base_green {
+            let state = self
+                .client
+                .get_branch_status(&self.config.name, &self.pr.base_ref)
+                .await?;
+            if matches!(state, StatusState::Failure | StatusState::Error) {
+                reasons.insert(BlockReason::BaseBranchRed);
+            }
+        }
+        if let Some(marker) = &self.config.linked_pr_marker {
+            let prs = self.unmerged_linked_prs(marker).await?;
+            if !prs.is_empty() {
+                reasons.insert(BlockReason::WaitingOnLinkedPr { prs });
+            }
+        }
+        if self.config.require_up_to_date && self.get_pr_commits_behind().await? > 0 {
+            reasons.insert(BlockReason::BranchBehind);
+        }
+        if self.may_rebase() && !self.get_pr_rebaseable().await? {
+            reasons.insert(BlockReason::NotRebaseable);
+        }
+        if (self.config.max_changed_files.is_some() || self.config.max_total_changes.is_some())
+            && self.too_large().await?
+        {
+            reasons.insert(BlockReason::TooLarge);
+        }
+        if self.in_merge_cooldown().await? {
+            reasons.insert(BlockReason::MergeCooldown);
+        }
+        if let Some(gate) = &self.config.external_gate {
+            if !external_gate::approved(gate, &self.client.owner, &self.config.name, self.pr).await
+            {
+                reasons.insert(BlockReason::ExternalGateNotSatisfied);
+            }
         }
         Ok(())
     }
 
+    /// Whether the PR is a draft to mark as ready for review once it's approved and its CI passes.
+    fn opted_in_to_mark_ready(&self) -> bool {
+        self.pr.draft
+            && self.config.mark_ready_when_approved
+            && self
+                .config
+                .mark_ready_label
+                .as_ref()
+                .is_some_and(|label| has_label(&self.pr.labels, label).is_some())
+    }
+
     /// Analyze a PR to determine what actions need to be undertaken.
     pub async fn required_actions(&self) -> Result<Actions> {
+        Ok(self.analyze().await?.actions)
+    }
+
+    /// Analyze a PR to determine what actions need to be undertaken, and why.
+    pub async fn analyze(&self) -> Result<Analysis> {
         let mut actions = Actions::noop();
 
+        // Older updates make the PR inactive anyway, so the timeline isn't fetched for them.
+        if self.config.ignore_bot_label_activity
+            && self.pr.last_activity() >= Utc::now() - Duration::minutes(60)
+        {
+            let bot_nick = self.client.get_bot_nick().await?;
+            let activity = self.get_pr_timeline().await?;
+            self.last_activity
+                .set(meaningful_activity(self.pr, &activity, &bot_nick));
+        }
+
         let mut block_reasons = self.analyze_basic_checks();
-        if self.config.react_to_comments || block_reasons.is_empty() {
+        if block_reasons.contains(&BlockReason::InsideGracePeriod) {
+            self.analyze_ready_for_review(&mut block_reasons).await?;
+        }
+        if let Some(auto_assign) = &self.config.auto_assign_reviewers {
+            if ![
+                BlockReason::DraftPr,
+                BlockReason::ClosedPr,
+                BlockReason::InactivePr,
+            ]
+            .iter()
+            .any(|reason| block_reasons.contains(reason))
+            {
+                self.analyze_auto_assign(auto_assign, &mut actions).await?;
+            }
+        }
+        if block_reasons.contains(&BlockReason::MissingReviews) {
+            self.analyze_requested_reviewers(&mut block_reasons).await?;
+        }
+        if ![BlockReason::DraftPr, BlockReason::ClosedPr]
+            .iter()
+            .any(|reason| block_reasons.contains(reason))
+        {
+            self.analyze_soft_block(&mut block_reasons, &mut actions)
+                .await?;
+        }
+        // Only looked up when there's a grace period to skip, as it costs API calls.
+        let mut merge_when_green = None;
+        if block_reasons.contains(&BlockReason::InsideGracePeriod) {
+            let requested = self.merge_when_green_requested().await?;
+            if requested {
+                block_reasons.remove(&BlockReason::InsideGracePeriod);
+            }
+            merge_when_green = Some(requested);
+        }
+        let analyze_comments =
+            self.config.react_to_comments || self.config.proactive_status_comment;
+        if analyze_comments
+            || self.config.publish_check_run
+            || self.config.use_reactions
+            || self.config.block_via_review
+            || (self.config.evaluate_drafts && self.pr.draft)
+            || self.opted_in_to_mark_ready()
+            || ((self.config.ready_comment.is_some() || self.config.grace_period_once_per_commit)
+                && block_reasons
+                    .iter()
+                    .all(|reason| *reason == BlockReason::InsideGracePeriod))
+            || block_reasons.is_empty()
+        {
             // Now that the basic checks have been passed we can gather information
             // from the GitHub API in order to do the full check. We do this second
             // so that we use the GitHub API as little as possible, we don't want to
             // hit the rate limit.
             self.analyze_extended_checks(&mut block_reasons).await?;
         }
+        // The grace period may also start once CI completes.
+        if block_reasons.contains(&BlockReason::InsideGracePeriod) {
+            let requested = match merge_when_green {
+                Some(requested) => requested,
+                None => self.merge_when_green_requested().await?,
+            };
+            if requested {
+                block_reasons.remove(&BlockReason::InsideGracePeriod);
+            }
+        }
 
-        if self.config.react_to_comments {
+        if self.config.grace_period_once_per_commit {
+            self.analyze_ready_since(&mut block_reasons, &mut actions)
+                .await?;
+        }
+        if analyze_comments {
             self.analyze_comments(&block_reasons, &mut actions).await?;
         }
+        if let Some(text) = &self.config.ready_comment {
+            self.analyze_ready_comment(text, &block_reasons, &mut actions)
+                .await?;
+        }
+        if self.config.block_via_review
+            && ![
+                BlockReason::DraftPr,
+                BlockReason::ClosedPr,
+                BlockReason::InactivePr,
+            ]
+            .iter()
+            .any(|reason| block_reasons.contains(reason))
+        {
+            self.analyze_review(&block_reasons, &mut actions).await?;
+        }
+
+        let mut reason_names: Vec<_> = block_reasons
+            .iter()
+            .map(|reason| format!("{reason:?}"))
+            .collect();
+        reason_names.sort_unstable();
+        let noop = |actions| Analysis {
+            actions,
+            decision: Decision::Noop,
+            block_reasons: reason_names.clone(),
+        };
+
+        // Drafts aren't merged, but can be marked as ready to be merged on the next run.
+        if self.opted_in_to_mark_ready()
+            && block_reasons.iter().all(|reason| {
+                matches!(
+                    reason,
+                    BlockReason::DraftPr | BlockReason::InsideGracePeriod
+                )
+            })
+        {
+            log::info!("Draft is approved and its CI passes, marking it as ready for review");
+            actions.set_mark_ready(true);
+        }
 
         let mut missing_review = false;
         let mut statuses_passed = true;
@@ -262,37 +1529,117 @@ impl<'a> Analyzer<'a> {
         for reason in &block_reasons {
             match reason {
                 BlockReason::DraftPr => {
-                    log::info!("Draft, nothing to do");
-                    return Ok(actions);
+                    if !self.config.evaluate_drafts {
+                        log::info!("Draft, nothing to do");
+                        return Ok(noop(actions));
+                    }
+                    log::info!("Draft, only updating labels and comments");
                 }
                 BlockReason::ClosedPr => {
                     log::info!("Closed, nothing to do");
-                    return Ok(actions);
+                    return Ok(noop(actions));
+                }
+                BlockReason::InactivePr => {
+                    log::info!("Inactive for over 60 minutes, nothing to do");
+                    return Ok(noop(actions));
+                }
+                BlockReason::MissingReviews => {
+                    log::info!("Waiting on reviewers, nothing to do");
+                    missing_review = true;
+                }
+                BlockReason::ReviewerLostAccess { users } => {
+                    log::info!(
+                        "Requested reviewers can't access the repo anymore: {}",
+                        users.join(", ")
+                    );
+                    missing_review = true;
+                }
+                BlockReason::MissingReviewApproval { .. } => {
+                    log::info!("Still waiting for a review approval");
+                    missing_review = true;
+                }
+                BlockReason::MissingMandatoryApproval { from_users } => {
+                    log::info!(
+                        "Still waiting for approvals from mandatory reviewers: {}",
+                        from_users.join(", ")
+                    );
+                    missing_review = true;
+                }
+                BlockReason::CiNotPassing => {
+                    log::info!("CI not passing yet");
+                    statuses_passed = false;
+                }
+                BlockReason::RequiredCheckMissing { context } => {
+                    log::info!("Required check {context} was never reported");
+                    statuses_passed = false;
+                }
+                BlockReason::StatusPendingTooLong { statuses } => {
+                    log::warn!("Checks pending for too long: {}", statuses.join(", "));
+                    statuses_passed = false;
+                }
+                BlockReason::MissingDescription => {
+                    log::info!("Missing description");
+                }
+                BlockReason::BlockedByLabel => {
+                    log::info!("Blocked by a block-merge label.");
+                }
+                BlockReason::MissingAutomergeLabel => {
+                    log::info!("Missing the automerge label");
+                }
+                BlockReason::UntrustedBotAuthor => {
+                    log::info!("Opened by the untrusted bot {}", self.pr.author);
+                }
+                BlockReason::InsideGracePeriod => {
+                    log::info!("Still inside the grace period");
+                }
+                BlockReason::ExternalGateNotSatisfied => {
+                    log::info!("Not approved by the external gate");
+                }
+                BlockReason::DeploymentNotSuccessful { env } => {
+                    log::info!("Not successfully deployed to {env} yet");
+                }
+                BlockReason::TooLarge => {
+                    log::info!("Too large to be merged automatically");
+                }
+                BlockReason::InsufficientReviewBreadth => {
+                    log::info!("Not reviewed by enough people");
+                    missing_review = true;
+                }
+                BlockReason::ForbiddenStatus { statuses } => {
+                    log::info!("Forbidden checks reported: {}", statuses.join(", "));
+                    statuses_passed = false;
+                }
+                BlockReason::NoChecksRan => {
+                    log::info!("No checks have run");
+                    statuses_passed = false;
                 }
-                BlockReason::InactivePr => {
-                    log::info!("Inactive for over 60 minutes, nothing to do");
-                    return Ok(actions);
+                BlockReason::SoftBlocked { label, until } => {
+                    log::info!("Soft blocked by `{label}` until {until}");
                 }
-                BlockReason::MissingReviews => {
-                    log::info!("Waiting on reviewers, nothing to do");
+                BlockReason::MissingCrossTeamApproval => {
+                    log::info!("Not approved by anyone outside of the author's teams");
                     missing_review = true;
                 }
-                BlockReason::MissingReviewApproval { .. } => {
-                    log::info!("Still waiting for a review approval");
-                    missing_review = true;
+                BlockReason::BaseBranchRed => {
+                    log::info!("The checks of {} are failing", self.pr.base_ref);
                 }
-                BlockReason::CiNotPassing => {
-                    log::info!("CI not passing yet");
-                    statuses_passed = false;
+                BlockReason::WaitingOnLinkedPr { prs } => {
+                    log::info!("Waiting for linked PRs to be merged: {}", prs.join(", "));
                 }
-                BlockReason::MissingDescription => {
-                    log::info!("Missing description");
+                BlockReason::DisallowedBaseBranch => {
+                    log::info!("Not targeting a branch PRs can be merged into");
                 }
-                BlockReason::BlockedByLabel => {
-                    log::info!("Blocked by a block-merge label.");
+                BlockReason::NotRebaseable => {
+                    log::info!("Can't be rebased onto {}", self.pr.base_ref);
                 }
-                BlockReason::InsideGracePeriod => {
-                    log::info!("Still inside the grace period");
+                BlockReason::MergeCooldown => {
+                    log::info!("Merging failed recently, waiting before retrying");
+                }
+                BlockReason::BranchBehind => {
+                    log::info!("Branch is behind {}", self.pr.base_ref);
+                    if self.config.auto_update_branch {
+                        actions.set_update_branch(true);
+                    }
                 }
             }
         }
@@ -303,82 +1650,395 @@ impl<'a> Analyzer<'a> {
             actions.set_label(label, Presence::should_be_present(reviewed));
         }
         if let Some(label) = &self.config.ci_passed_label {
-            actions.set_label(label, Presence::should_be_present(statuses_passed));
+            // Without required statuses, CI trivially passes.
+            if !self.config.ci_label_requires_statuses || self.config.requires_statuses() {
+                actions.set_label(label, Presence::should_be_present(statuses_passed));
+            }
         }
         if let Some(label) = &self.config.needs_description_label {
             actions.set_label(label, Presence::should_be_present(!self.pr.has_description));
         }
+        if !self.config.path_labels.is_empty() {
+            let paths: Vec<_> = self
+                .get_pr_changed_files()
+                .await?
+                .into_iter()
+                .map(|file| file.path)
+                .collect();
+            for label in path_labels::labels(&self.config.path_labels, &paths) {
+                actions.set_label(label, Presence::Present);
+            }
+        }
 
         // Conclude.
+        if self.config.publish_check_run {
+            let (conclusion, summary) = self.check_run(&block_reasons);
+            actions.set_check_run(conclusion, summary);
+        }
+        if self.config.use_reactions {
+            actions.set_reaction(Reaction::of(&block_reasons));
+        }
         actions.set_merge(block_reasons.is_empty());
+        if actions.merge && self.config.approved_by_trailers {
+            actions.set_approvers(self.approvers.borrow().clone());
+        }
 
-        Ok(actions)
+        let decision = if actions.merge {
+            Decision::Ready
+        } else {
+            Decision::Blocked
+        };
+        Ok(Analysis {
+            actions,
+            decision,
+            block_reasons: reason_names,
+        })
     }
 
-    async fn pr_approved(&self, review_required: bool) -> Result<PrApprovalStatus> {
-        let reviews = self.get_pr_reviews().await?;
-        log::debug!(reviews = ?reviews, "Got PR reviews");
+    /// The conclusion and summary of the check run reporting whether the PR can be merged.
+    fn check_run(&self, reasons: &HashSet<BlockReason>) -> (CheckConclusion, String) {
+        if reasons.is_empty() {
+            return (CheckConclusion::Success, ALL_GOOD.to_owned());
+        }
+        let conclusion = if needs_action(reasons) {
+            CheckConclusion::ActionRequired
+        } else {
+            CheckConclusion::InProgress
+        };
+        (conclusion, self.block_reasons_message(reasons))
+    }
+
+    /// The PRs linked in the body with the marker that haven't been merged yet.
+    async fn unmerged_linked_prs(&self, marker: &str) -> Result<Vec<String>> {
+        let mut unmerged = Vec::new();
+        for linked in linked_prs(&self.pr.body, marker) {
+            let pr = self
+                .client
+                .get_pull_request_from(&linked.owner, &linked.repo, linked.number)
+                .await?;
+            if pr.merged_at.is_none() {
+                unmerged.push(linked.to_string());
+            }
+        }
+        Ok(unmerged)
+    }
+
+    /// Whether merging the PR's current commit failed within the cooldown. New commits can be
+    /// merged right away.
+    async fn in_merge_cooldown(&self) -> Result<bool> {
+        let Some(cooldown) = self.config.merge_retry_cooldown_secs else {
+            return Ok(false);
+        };
+        let bot_nick = self.client.get_bot_nick().await?;
+        let comments = self.get_pr_comments().await?;
+        Ok(match find_merge_failure_comment(&comments, &bot_nick) {
+            Some(comment) => {
+                comment.body.contains(&self.pr.commit_sha)
+                    && comment.updated_at > Utc::now() - Duration::seconds(cooldown as i64)
+            }
+            None => false,
+        })
+    }
+
+    /// How many people other than the author reviewed the PR, in any way.
+    fn distinct_reviewers(&self, reviews: &[Review]) -> usize {
+        reviews
+            .iter()
+            .map(|review| &review.user_name)
+            .filter(|user| **user != self.pr.author)
+            .collect::<HashSet<_>>()
+            .len()
+    }
+
+    /// The requested teams none of whose members approved the PR.
+    async fn teams_without_approval(&self) -> Result<Vec<String>> {
+        let mut waiting_on = Vec::new();
+        for team in &self.pr.requested_teams {
+            let members = self.client.get_team_members(team).await?;
+            if !self
+                .approvers
+                .borrow()
+                .iter()
+                .any(|approver| members.contains(approver))
+            {
+                waiting_on.push(team.clone());
+            }
+        }
+        Ok(waiting_on)
+    }
+
+    /// Whether someone outside of all the author's teams approved the PR. Authors who aren't in
+    /// any of the configured teams can be approved by anyone.
+    async fn approved_across_teams(&self) -> Result<bool> {
+        let mut author_teams = Vec::new();
+        for team in &self.config.teams {
+            let members = self.client.get_team_members(team).await?;
+            if members.contains(&self.pr.author) {
+                author_teams.push(members);
+            }
+        }
+        let approved = self.approvers.borrow().iter().any(|approver| {
+            author_teams
+                .iter()
+                .all(|members| !members.contains(approver))
+        });
+        Ok(approved)
+    }
+
+    /// Whether the bot may merge the PR by rebasing it, depending on its number of commits.
+    fn may_rebase(&self) -> bool {
+        self.config.merge_strategy == context::MergeStrategy::Direct
+            && [None, Some(1)].iter().any(|commits| {
+                merge::merge_method(self.config, *commits) == context::MergeMethod::Rebase
+            })
+    }
 
+    fn comments_only_from_requested_reviewers(&self) -> bool {
+        self.config.comment_requests_change && self.config.comments_only_from_requested_reviewers
+    }
+
+    fn pr_approved(
+        &self,
+        reviews: Vec<Review>,
+        requested_reviewers: Vec<String>,
+        review_required: bool,
+        min_approvals: Option<usize>,
+    ) -> PrApprovalStatus {
         let review_required = if review_required {
             Approval::Required
         } else {
             Approval::Optional
         };
-        let comment_effect = if self.config.comment_requests_change {
+        let comment_effect = if self.comments_only_from_requested_reviewers() {
+            CommentEffect::RequestsChangeFromRequestedReviewers
+        } else if self.config.comment_requests_change {
             CommentEffect::RequestsChange
         } else {
             CommentEffect::Ignore
         };
 
-        let reviews = Reviews::new(self.pr.author.clone(), comment_effect).record_reviews(reviews);
+        let mut aggregated = Reviews::new(self.pr.author.clone(), comment_effect);
+        if let Some(days) = self.config.approval_max_age_days {
+            aggregated = aggregated.with_approval_max_age(Duration::days(days as i64));
+        }
+        if let Some(approvals) = self.config.approvals_per_change_request {
+            aggregated = aggregated.with_approvals_per_change_request(approvals);
+        }
+        let reviews = aggregated
+            .with_advisory_reviewers(self.config.advisory_reviewers.iter().cloned())
+            .with_requested_reviewers(requested_reviewers)
+            .record_reviews(reviews);
+        *self.advisory_concerns.borrow_mut() = reviews.advisory_concerns_from_users();
+        *self.approvers.borrow_mut() = reviews.approvers();
+        *self.reviews_in_progress.borrow_mut() =
+            reviews.reviews_in_progress_from(&self.pr.requested_users);
 
-        if reviews.approved(review_required) {
-            Ok(PrApprovalStatus::Approved)
+        let approvals = reviews.approvals();
+        if let Some(min) = min_approvals.filter(|min| approvals < *min) {
+            log::info!("Approved by {approvals} people, the changes need {min}");
+            let from_users = reviews.missing_approvals_from_users();
+            PrApprovalStatus::MissingReview { from_users }
+        } else if reviews.approved(review_required) {
+            // Mandatory reviewers must approve in any case, but can't approve their own PRs.
+            let from_users = reviews.missing_approvals_from(
+                self.config
+                    .mandatory_reviewers_for(&self.pr.base_ref)
+                    .iter()
+                    .filter(|user| **user != self.pr.author),
+            );
+            if from_users.is_empty() {
+                PrApprovalStatus::Approved
+            } else {
+                PrApprovalStatus::MissingMandatoryApproval { from_users }
+            }
         } else {
             let from_users = reviews.missing_approvals_from_users();
             log::info!("Not yet approved by review");
             if !from_users.is_empty() {
                 log::info!("\tWaiting for reviews from: {}", from_users.join(", "));
             }
-            Ok(PrApprovalStatus::MissingReview { from_users })
+            PrApprovalStatus::MissingReview { from_users }
         }
     }
 
-    async fn pr_statuses_passed(&self) -> Result<bool> {
-        let statuses = self.get_pr_statuses().await?;
+    /// Whether the status matches one of the `tolerated_status_patterns`, so it never blocks.
+    fn tolerated(&self, status: &str) -> bool {
+        self.config
+            .tolerated_status_patterns
+            .iter()
+            .any(|pattern| path_labels::glob_matches(pattern.as_bytes(), status.as_bytes()))
+    }
+
+    /// The statuses required for the PR, given its base branch and labels, except the tolerated
+    /// ones.
+    fn required_statuses(&self) -> Vec<String> {
+        let mut statuses = self.config.required_statuses_for(&self.pr.base_ref);
+        let extra = self
+            .config
+            .conditional_required_statuses
+            .iter()
+            .filter(|conditional| has_label(&self.pr.labels, &conditional.label).is_some())
+            .flat_map(|conditional| &conditional.statuses);
+        for status in extra {
+            if !statuses.contains(status) {
+                statuses.push(status.clone());
+            }
+        }
+        statuses.retain(|status| !self.tolerated(status));
+        statuses
+    }
+
+    fn pr_statuses_passed(&self, statuses: &HashMap<String, CommitStatus>) -> CiStatus {
         log::debug!(statuses = ?statuses, "Got PR statuses");
-        for required in &self.config.required_statuses {
-            let status = statuses.get(required);
-            if status != Some(&StatusState::Success) {
+        let mut reported_failing = false;
+        let mut pending_too_long = Vec::new();
+        let mut missing = Vec::new();
+        let waivers = self.waivers.borrow();
+        for required in &self.required_statuses() {
+            if let Some(user) = waivers.get(required) {
+                log::info!("Required status `{required}` was waived by {user}");
+                continue;
+            }
+            let Some(status) = statuses.get(required) else {
+                log::info!("Required status `{required}` was never reported");
+                missing.push(required.clone());
+                continue;
+            };
+            if status.state != StatusState::Success {
                 log::info!(
                     "Required status `{}` has not passed. Status is {:?}",
                     required,
                     status
                 );
-                return Ok(false);
+                reported_failing = true;
+                if self.pending_for_too_long(status) {
+                    pending_too_long.push(required.clone());
+                }
+            }
+        }
+        let groups = &self.config.required_status_groups;
+        let passed_group = groups.iter().find(|group| {
+            group.iter().all(|required| {
+                waivers.contains_key(required)
+                    || self.tolerated(required)
+                    || statuses.get(required).map(|s| &s.state) == Some(&StatusState::Success)
+            })
+        });
+        match passed_group {
+            Some(group) => log::debug!("Required statuses {} have passed", group.join(", ")),
+            None if !groups.is_empty() => {
+                log::info!("None of the required status groups has passed");
+                reported_failing = true;
+            }
+            None => {}
+        }
+        if !reported_failing && missing.is_empty() {
+            CiStatus::Passed
+        } else {
+            CiStatus::NotPassed {
+                reported_failing,
+                pending_too_long,
+                missing,
             }
         }
-        Ok(true)
     }
 
-    fn merge_blocked_by_label(&self) -> bool {
+    /// The forbidden statuses reported on the PR, unless they're successful (if allowed).
+    fn forbidden_statuses(&self, statuses: &HashMap<String, CommitStatus>) -> Vec<String> {
         self.config
-            .block_merge_label
-            .as_ref()
-            .map_or(false, |label| {
-                if self.pr.labels.contains(label) {
-                    log::info!("Merge blocked by label");
-                    true
-                } else {
-                    false
-                }
+            .forbidden_statuses
+            .iter()
+            .filter(|forbidden| !self.tolerated(forbidden))
+            .filter(|forbidden| {
+                statuses.get(*forbidden).is_some_and(|status| {
+                    self.config.forbid_successful_statuses || status.state != StatusState::Success
+                })
             })
+            .cloned()
+            .collect()
+    }
+
+    /// When the last required status was updated, falling back to the last update of the PR
+    /// if the statuses don't tell.
+    fn ci_completed_at(&self, statuses: &HashMap<String, CommitStatus>) -> DateTime<Utc> {
+        self.required_statuses()
+            .iter()
+            .chain(self.config.required_status_groups.iter().flatten())
+            .filter_map(|required| statuses.get(required)?.updated_at)
+            .max()
+            .unwrap_or(self.last_activity.get())
+    }
+
+    /// When the last commit was made or the last review was submitted, unlike the last update
+    /// which is also bumped by metadata changes, e.g. to the labels or requested reviewers.
+    async fn last_commit_or_review_at(&self) -> Result<DateTime<Utc>> {
+        let last_commit = self.get_pr_last_commit_at().await?;
+        let last_review = self
+            .get_pr_reviews()
+            .await?
+            .iter()
+            .filter_map(|review| review.submitted_at)
+            .max();
+        Ok(last_commit.max(last_review).unwrap_or(self.pr.created_at))
+    }
+
+    async fn too_large(&self) -> Result<bool> {
+        let files = self.get_pr_changed_files().await?;
+        if let Some(max) = self.config.max_changed_files {
+            if files.len() > max {
+                log::info!("{} files changed, more than {max}", files.len());
+                return Ok(true);
+            }
+        }
+        if let Some(max) = self.config.max_total_changes {
+            let changes: u64 = files
+                .iter()
+                .map(|file| file.additions + file.deletions)
+                .sum();
+            if changes > max as u64 {
+                log::info!("{changes} lines changed, more than {max}");
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn pending_for_too_long(&self, status: &CommitStatus) -> bool {
+        match (self.config.pending_status_timeout_secs, status.updated_at) {
+            (Some(timeout), Some(updated_at)) => {
+                status.state == StatusState::Pending
+                    && updated_at < Utc::now() - Duration::seconds(timeout as i64)
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the labels let the PR be merged: the block merge label wins over the automerge
+    /// label, without which PRs aren't merged if it's configured.
+    pub fn resolve_merge_eligibility(&self, labels: &HashSet<String>) -> Eligibility {
+        let has = |label: &Option<String>| {
+            label
+                .as_ref()
+                .map(|label| has_label(labels, label).is_some())
+        };
+        match (
+            has(&self.config.block_merge_label),
+            has(&self.config.automerge_label),
+        ) {
+            (Some(true), _) => {
+                log::info!("Merge blocked by label");
+                Eligibility::Blocked
+            }
+            (_, Some(true)) => Eligibility::OptedIn,
+            (_, Some(false)) => Eligibility::NotOptedIn,
+            (_, None) => Eligibility::Default,
+        }
     }
 
     fn requires_reviews(&self) -> bool {
         // Either there's a trivial label, and the PR contains it, so reviews are optional.
         if let Some(ref trivial_label) = self.config.skip_review_label {
-            if self.pr.labels.contains(trivial_label) {
+            if has_label(&self.pr.labels, trivial_label).is_some() {
                 log::info!("Not blocking on reviews because of trivial review label");
                 return false;
             }
@@ -389,10 +2049,24 @@ impl<'a> Analyzer<'a> {
     }
 
     fn outside_grace_period(&self) -> bool {
-        match &self.config.automerge_grace_period {
+        self.outside_grace_period_since(self.last_activity.get())
+    }
+
+    /// The grace period of the PR, including its jitter.
+    fn grace_period(&self) -> Option<Duration> {
+        let grace_period = self.config.automerge_grace_period?;
+        let jitter = self
+            .config
+            .grace_period_jitter_secs
+            .map_or(0, |max| grace_period_jitter(self.pr.number, max));
+        Some(Duration::seconds((grace_period + jitter) as i64))
+    }
+
+    fn outside_grace_period_since(&self, anchor: DateTime<Utc>) -> bool {
+        match self.grace_period() {
             None => true,
             Some(grace_period) => {
-                if Utc::now() - Duration::seconds(*grace_period as i64) > self.pr.updated_at {
+                if Utc::now() - grace_period > anchor {
                     true
                 } else {
                     log::info!("Within grace period, not merging");
@@ -403,30 +2077,160 @@ impl<'a> Analyzer<'a> {
     }
 
     async fn get_pr_reviews(&self) -> Result<Vec<Review>> {
-        match &self.reviews {
-            RemoteData::Local(reviews) => Ok(reviews.clone()),
-            RemoteData::Remote => Ok(self
+        if let RemoteData::Local(reviews) = &*self.reviews.borrow() {
+            return Ok(reviews.clone());
+        }
+        let reviews: Vec<_> = self
+            .client
+            .get_pull_request_reviews(self.config.name.as_str(), self.pr.number)
+            .await?
+            .iter()
+            .flat_map(Review::from_octocrab_review)
+            .collect();
+        *self.reviews.borrow_mut() = RemoteData::Local(reviews.clone());
+        Ok(reviews)
+    }
+
+    async fn get_pr_comments(&self) -> Result<Vec<Comment>> {
+        if let RemoteData::Local(comments) = &*self.comments.borrow() {
+            return Ok(comments.clone());
+        }
+        let comments: Vec<_> = self
+            .client
+            .get_pull_request_comments(self.config.name.as_str(), self.pr.number)
+            .await?
+            .into_iter()
+            .flat_map(Comment::from_octocrab_comment)
+            .collect();
+        *self.comments.borrow_mut() = RemoteData::Local(comments.clone());
+        Ok(comments)
+    }
+
+    async fn get_pr_deployments(&self) -> Result<HashMap<String, context::DeploymentState>> {
+        match &self.deployments {
+            RemoteData::Local(deployments) => Ok(deployments.clone()),
+            RemoteData::Remote => {
+                self.client
+                    .get_deployment_statuses(&self.config.name, &self.pr.commit_sha)
+                    .await
+            }
+        }
+    }
+
+    async fn get_pr_ready_for_review_at(&self) -> Result<Option<DateTime<Utc>>> {
+        Ok(ready_for_review_at(&self.get_pr_timeline().await?))
+    }
+
+    async fn get_pr_requested_reviewers(&self) -> Result<Vec<String>> {
+        Ok(requested_reviewers(&self.get_pr_timeline().await?))
+    }
+
+    async fn get_pr_labeled_at(&self) -> Result<Option<DateTime<Utc>>> {
+        Ok(labeled_at(
+            &self.get_pr_timeline().await?,
+            &self.config.soft_block_labels,
+        ))
+    }
+
+    /// The commit statuses, along with the check runs counting as statuses named after them.
+    async fn get_pr_statuses(&self) -> Result<HashMap<String, CommitStatus>> {
+        let mut statuses: HashMap<_, _> = match &self.statuses {
+            RemoteData::Local(statuses) => statuses.clone(),
+            RemoteData::Remote => self
                 .client
-                .get_pull_request_reviews(self.config.name.as_str(), self.pr.number)
+                .get_pull_request_statuses(&self.config.name, self.pr)
                 .await?
-                .iter()
-                .flat_map(Review::from_octocrab_review)
-                .collect()),
+                .into_iter()
+                .flat_map(CommitStatus::from_octocrab_status)
+                .collect(),
+        };
+        for check_run in latest_check_runs(self.get_pr_check_runs().await?) {
+            if let Some(status) = check_run.status(&self.config.conclusion_map) {
+                statuses.entry(check_run.name).or_insert(status);
+            }
         }
+        Ok(statuses)
     }
 
-    async fn get_pr_statuses(&self) -> Result<HashMap<String, StatusState>> {
-        match &self.statuses {
-            RemoteData::Local(statuses) => Ok(statuses.clone()),
+    async fn get_pr_check_runs(&self) -> Result<Vec<CheckRun>> {
+        match &self.check_runs {
+            RemoteData::Local(check_runs) => Ok(check_runs.clone()),
             RemoteData::Remote => Ok(self
                 .client
-                .get_pull_request_statuses(&self.config.name, self.pr)
+                .get_check_runs(&self.config.name, &self.pr.commit_sha)
                 .await?
                 .into_iter()
-                .flat_map(|status| Some((status.context?, status.state)))
+                .map(CheckRun::from_octocrab_check_run)
                 .collect()),
         }
     }
+
+    async fn get_pr_last_commit_at(&self) -> Result<Option<DateTime<Utc>>> {
+        match &self.last_commit_at {
+            RemoteData::Local(last_commit_at) => Ok(*last_commit_at),
+            RemoteData::Remote => {
+                self.client
+                    .get_last_commit_at(&self.config.name, self.pr.number)
+                    .await
+            }
+        }
+    }
+
+    async fn get_pr_commits_behind(&self) -> Result<u64> {
+        match &self.commits_behind {
+            RemoteData::Local(behind) => Ok(*behind),
+            RemoteData::Remote => {
+                self.client
+                    .get_commits_behind(&self.config.name, &self.pr.base_ref, &self.pr.commit_sha)
+                    .await
+            }
+        }
+    }
+
+    /// Whether the PR can be merged, unless it would be rebased and Github says it can't. Github only
+    /// tells when fetching the PR alone, and an unknown state doesn't block.
+    async fn get_pr_rebaseable(&self) -> Result<bool> {
+        match &self.rebaseable {
+            RemoteData::Local(rebaseable) => Ok(*rebaseable),
+            RemoteData::Remote => {
+                let pr = self
+                    .client
+                    .get_pull_request(&self.config.name, self.pr.number)
+                    .await?;
+                Ok(
+                    merge::merge_method(self.config, pr.commits) != context::MergeMethod::Rebase
+                        || pr.rebaseable != Some(false),
+                )
+            }
+        }
+    }
+
+    async fn get_pr_timeline(&self) -> Result<Vec<context::TimelineEvent>> {
+        if let RemoteData::Local(timeline) = &*self.timeline.borrow() {
+            return Ok(timeline.clone());
+        }
+        let timeline = self
+            .client
+            .get_timeline(&self.config.name, self.pr.number)
+            .await?;
+        *self.timeline.borrow_mut() = RemoteData::Local(timeline.clone());
+        Ok(timeline)
+    }
+
+    async fn get_pr_changed_files(&self) -> Result<Vec<ChangedFile>> {
+        if let RemoteData::Local(files) = &*self.changed_files.borrow() {
+            return Ok(files.clone());
+        }
+        let files: Vec<_> = self
+            .client
+            .get_pull_request_files(&self.config.name, self.pr.number)
+            .await?
+            .into_iter()
+            .map(ChangedFile::from_octocrab_diff_entry)
+            .collect();
+        *self.changed_files.borrow_mut() = RemoteData::Local(files.clone());
+        Ok(files)
+    }
 }
 
 pub enum RemoteData<T> {
@@ -450,12 +2254,286 @@ impl Presence {
     }
 }
 
+/// What was decided for a PR.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    /// The PR can be merged, which the bot does in full mode, unless Github refuses to.
+    Ready,
+    /// The bot merged the PR.
+    Merged,
+    /// The PR can't be merged yet.
+    Blocked,
+    /// The PR isn't worth looking at, e.g. it's a draft.
+    Noop,
+}
+
+impl Decision {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Ready => "ready",
+            Self::Merged => "merged",
+            Self::Blocked => "blocked",
+            Self::Noop => "noop",
+        }
+    }
+}
+
+/// The outcome of analyzing a PR.
+#[derive(Debug)]
+pub struct Analysis {
+    pub actions: Actions,
+    pub decision: Decision,
+    /// Why the PR can't be merged, e.g. `CiNotPassing`.
+    pub block_reasons: Vec<String>,
+}
+
+/// Whether a human must act for the PR to be merged, e.g. by reviewing it.
+fn needs_action(reasons: &HashSet<BlockReason>) -> bool {
+    reasons.iter().any(|reason| {
+        matches!(
+            reason,
+            BlockReason::MissingReviews
+                | BlockReason::ReviewerLostAccess { .. }
+                | BlockReason::MissingReviewApproval { .. }
+                | BlockReason::MissingMandatoryApproval { .. }
+                | BlockReason::InsufficientReviewBreadth
+                | BlockReason::MissingCrossTeamApproval
+                | BlockReason::MissingDescription
+                | BlockReason::BlockedByLabel
+                | BlockReason::MissingAutomergeLabel
+                | BlockReason::UntrustedBotAuthor
+                | BlockReason::ExternalGateNotSatisfied
+                | BlockReason::TooLarge
+                | BlockReason::ForbiddenStatus { .. }
+                | BlockReason::DisallowedBaseBranch
+                | BlockReason::NotRebaseable
+                | BlockReason::NoChecksRan
+        )
+    })
+}
+
+/// The comment recording that the PR has been ready to merge since the given time, at the given
+/// commit.
+fn ready_since_comment(commit_sha: &str, since: DateTime<Utc>) -> String {
+    format!(
+        "{READY_SINCE_SIGIL}\nThis PR is ready to merge; updates that don't push commits won't \
+         restart the grace period.\n<!-- ready-since: {commit_sha} {} -->\n",
+        since.to_rfc3339()
+    )
+}
+
+/// Since when the PR has been ready to merge according to the comment, unless it wasn't, or it
+/// got new commits since.
+fn ready_since(body: &str, commit_sha: &str) -> Option<DateTime<Utc>> {
+    let record = body.lines().find_map(|line| {
+        line.strip_prefix("<!-- ready-since: ")?
+            .strip_suffix(" -->")
+    })?;
+    let (sha, since) = record.split_once(' ')?;
+    if sha != commit_sha {
+        return None;
+    }
+    DateTime::parse_from_rfc3339(since)
+        .ok()
+        .map(|since| since.with_timezone(&Utc))
+}
+
+/// Between 0 and `max` seconds, always the same for a PR but scattered across PR numbers.
+fn grace_period_jitter(pr_number: u64, max: u64) -> u64 {
+    // The finalizer of SplitMix64, so consecutive PR numbers get unrelated jitters.
+    let mut hash = pr_number.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^= hash >> 31;
+    hash % (max + 1)
+}
+
+/// The last time the PR was marked as ready for review, if it ever was a draft.
+fn ready_for_review_at(timeline: &[context::TimelineEvent]) -> Option<DateTime<Utc>> {
+    timeline
+        .iter()
+        .filter(|event| event.event == models::Event::ReadyForReview)
+        .map(|event| event.at)
+        .max()
+}
+
+/// The users whose review of the PR was requested at some point, unless the request was removed.
+/// Unlike the PR's requested reviewers, it includes those who already reviewed.
+fn requested_reviewers(timeline: &[context::TimelineEvent]) -> Vec<String> {
+    let mut requested = Vec::new();
+    for event in timeline {
+        let Some(reviewer) = &event.requested_reviewer else {
+            continue;
+        };
+        match event.event {
+            models::Event::ReviewRequested if !requested.contains(reviewer) => {
+                requested.push(reviewer.clone());
+            }
+            models::Event::ReviewRequestRemoved => {
+                requested.retain(|login| login != reviewer);
+            }
+            _ => {}
+        }
+    }
+    requested
+}
+
+/// The last time one of the labels was added to the PR, if it ever was.
+fn labeled_at(timeline: &[context::TimelineEvent], labels: &[String]) -> Option<DateTime<Utc>> {
+    timeline
+        .iter()
+        .filter(|event| {
+            event.event == models::Event::Labeled
+                && event
+                    .label
+                    .as_ref()
+                    .is_some_and(|label| has_label(labels, label).is_some())
+        })
+        .map(|event| event.at)
+        .max()
+}
+
+/// When the PR was last active, unless its last update is the bot changing its labels: then when
+/// anything else last happened on its timeline.
+fn meaningful_activity(
+    pr: &Pr,
+    activity: &[context::TimelineEvent],
+    bot_nick: &str,
+) -> DateTime<Utc> {
+    let by_bot = |event: &&context::TimelineEvent| {
+        event.is_label_change() && event.actor.as_deref() == Some(bot_nick)
+    };
+    let last_update = pr.last_activity();
+    let Some(bot_labeled_at) = activity.iter().filter(by_bot).map(|event| event.at).max() else {
+        return last_update;
+    };
+    // Something else happened after, e.g. the description was edited.
+    if last_update > bot_labeled_at + Duration::seconds(1) {
+        return last_update;
+    }
+    activity
+        .iter()
+        .filter(|event| !by_bot(event))
+        .map(|event| event.at)
+        .chain(Some(pr.created_at))
+        .max()
+        .unwrap_or(pr.created_at)
+        .min(last_update)
+}
+
+/// Picks up to `count` of the team members other than the author, in turn: each PR number starts
+/// with a different member.
+fn pick_reviewers(members: &[String], author: &str, count: usize, pr_number: u64) -> Vec<String> {
+    let mut candidates: Vec<_> = members.iter().filter(|user| *user != author).collect();
+    if candidates.is_empty() {
+        return Vec::new();
+    }
+    candidates.sort();
+    let start = (pr_number % candidates.len() as u64) as usize;
+    candidates
+        .iter()
+        .cycle()
+        .skip(start)
+        .take(count.min(candidates.len()))
+        .map(|user| (*user).clone())
+        .collect()
+}
+
+/// The most approvals required for PRs with as many added and deleted lines, from the buckets
+/// whose `min_changes` they reach.
+fn size_required_approvals(buckets: &[context::SizeApprovals], changes: u64) -> Option<usize> {
+    buckets
+        .iter()
+        .filter(|bucket| changes >= bucket.min_changes as u64)
+        .map(|bucket| bucket.approvals)
+        .max()
+}
+
+/// How the bot reacts to PRs, to tell at a glance whether they're going to be merged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reaction {
+    /// The PR is going to be merged.
+    Ready,
+    /// The PR is waiting on something else than a human, e.g. CI.
+    Waiting,
+    /// The PR is waiting on a human, e.g. for a review.
+    Blocked,
+}
+
+impl Reaction {
+    pub const ALL: [Reaction; 3] = [Reaction::Ready, Reaction::Waiting, Reaction::Blocked];
+
+    pub fn content(self) -> models::reactions::ReactionContent {
+        use models::reactions::ReactionContent;
+        match self {
+            Reaction::Ready => ReactionContent::Rocket,
+            Reaction::Waiting => ReactionContent::Eyes,
+            Reaction::Blocked => ReactionContent::Confused,
+        }
+    }
+
+    fn of(reasons: &HashSet<BlockReason>) -> Self {
+        if reasons.is_empty() {
+            Reaction::Ready
+        } else if needs_action(reasons) {
+            Reaction::Blocked
+        } else {
+            Reaction::Waiting
+        }
+    }
+}
+
+/// The conclusion of the octobors check run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckConclusion {
+    /// The PR is going to be merged.
+    Success,
+    /// The PR is waiting on something that will eventually happen on its own, e.g. CI. The check
+    /// run stays in progress rather than concluding, since branch protection counts neutral check
+    /// runs as passing.
+    InProgress,
+    /// The PR is waiting on someone, e.g. a reviewer.
+    ActionRequired,
+}
+
+impl CheckConclusion {
+    /// The conclusion of the check run, unless it's still in progress.
+    pub fn conclusion(self) -> Option<octocrab::params::checks::CheckRunConclusion> {
+        use octocrab::params::checks::CheckRunConclusion;
+        match self {
+            Self::Success => Some(CheckRunConclusion::Success),
+            Self::InProgress => None,
+            Self::ActionRequired => Some(CheckRunConclusion::ActionRequired),
+        }
+    }
+}
+
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct Actions {
     pub merge: bool,
     pub add_labels: HashSet<String>,
     pub remove_labels: HashSet<String>,
     pub post_comment: Vec<String>,
+    /// Existing comments to edit, as (comment id, new body) pairs.
+    pub update_comment: Vec<(u64, String)>,
+    /// The check run to publish, as (conclusion, summary).
+    pub check_run: Option<(CheckConclusion, String)>,
+    /// Whether to merge the base branch into the PR branch.
+    pub update_branch: bool,
+    /// The body of a review requesting changes to submit.
+    pub request_changes: Option<String>,
+    /// An existing review of the bot requesting changes to edit, as (review id, new body).
+    pub update_review: Option<(u64, String)>,
+    /// Reviews of the bot to dismiss, by id.
+    pub dismiss_reviews: Vec<u64>,
+    /// Whether to mark the draft PR as ready for review.
+    pub mark_ready: bool,
+    /// The reaction of the bot to the PR, replacing its previous one.
+    pub reaction: Option<Reaction>,
+    /// The users to request reviews from.
+    pub request_reviewers: Vec<String>,
+    /// The reviewers who approved the PR, credited in the merge commit if configured.
+    pub approvers: Vec<String>,
 }
 
 impl Actions {
@@ -480,11 +2558,99 @@ impl Actions {
         self.post_comment.push(comment);
         self
     }
+
+    pub fn update_comment(&mut self, comment_id: u64, body: String) -> &mut Self {
+        self.update_comment.push((comment_id, body));
+        self
+    }
+
+    /// Only keeps the actions giving feedback, e.g. comments, reviews and check runs, dropping
+    /// the ones changing the PR or the repo.
+    pub fn audit(self) -> Self {
+        let Self {
+            merge: _,
+            add_labels: _,
+            remove_labels: _,
+            update_branch: _,
+            mark_ready: _,
+            request_reviewers: _,
+            approvers: _,
+            post_comment,
+            update_comment,
+            check_run,
+            request_changes,
+            update_review,
+            dismiss_reviews,
+            reaction,
+        } = self;
+        Self {
+            post_comment,
+            update_comment,
+            check_run,
+            request_changes,
+            update_review,
+            dismiss_reviews,
+            reaction,
+            ..Self::noop()
+        }
+    }
+
+    pub fn set_reaction(&mut self, reaction: Reaction) -> &mut Self {
+        self.reaction = Some(reaction);
+        self
+    }
+
+    pub fn set_mark_ready(&mut self, mark_ready: bool) -> &mut Self {
+        self.mark_ready = mark_ready;
+        self
+    }
+
+    pub fn set_update_branch(&mut self, update: bool) -> &mut Self {
+        self.update_branch = update;
+        self
+    }
+
+    pub fn request_changes(&mut self, body: String) -> &mut Self {
+        self.request_changes = Some(body);
+        self
+    }
+
+    pub fn update_review(&mut self, review_id: u64, body: String) -> &mut Self {
+        self.update_review = Some((review_id, body));
+        self
+    }
+
+    pub fn request_reviewers(&mut self, users: Vec<String>) -> &mut Self {
+        self.request_reviewers = users;
+        self
+    }
+
+    pub fn set_approvers(&mut self, approvers: Vec<String>) -> &mut Self {
+        self.approvers = approvers;
+        self
+    }
+
+    pub fn dismiss_review(&mut self, review_id: u64) -> &mut Self {
+        self.dismiss_reviews.push(review_id);
+        self
+    }
+
+    pub fn set_check_run(&mut self, conclusion: CheckConclusion, summary: String) -> &mut Self {
+        self.check_run = Some((conclusion, summary));
+        self
+    }
 }
 
+/// Finds a label by name. Names are compared once normalized, since the same characters can be
+/// composed differently in the configuration and in Github's API, e.g. "é" and "e\u{301}".
 #[inline]
-fn has_label(labels: &[String], name: &str) -> Option<usize> {
-    labels.iter().position(|label| label == name)
+pub(crate) fn has_label<'a>(
+    labels: impl IntoIterator<Item = &'a String>,
+    name: &str,
+) -> Option<usize> {
+    labels
+        .into_iter()
+        .position(|label| label == name || label.nfc().eq(name.nfc()))
 }
 
 /// Adds one or more labels to the PR. Only adds labels that aren't already present.
@@ -498,7 +2664,7 @@ pub async fn add_labels(
     // Only add the label(s) that are not actually present
     let to_add: Vec<_> = to_add
         .into_iter()
-        .filter_map(|new_label| match has_label(labels, new_label.as_ref()) {
+        .filter_map(|new_label| match has_label(&*labels, new_label.as_ref()) {
             None => Some(new_label.as_ref().to_owned()),
             Some(_) => None,
         })
@@ -536,10 +2702,8 @@ pub async fn remove_labels(
     let to_remove: Vec<_> = to_remove
         .into_iter()
         .filter_map(|old_label| {
-            has_label(labels, old_label.as_ref()).map(|i| {
-                labels.remove(i);
-                old_label
-            })
+            // Remove the label as it's named on the PR.
+            has_label(&*labels, old_label.as_ref()).map(|i| labels.remove(i))
         })
         .collect();
 
@@ -552,8 +2716,8 @@ pub async fn remove_labels(
     let ih = client.inner.issues(&client.owner, repo);
 
     for old_label in to_remove {
-        if let Err(e) = ih.remove_label(pr_number, old_label.as_ref()).await {
-            log::debug!("Error removing label '{}': {:#}", old_label.as_ref(), e);
+        if let Err(e) = ih.remove_label(pr_number, &old_label).await {
+            log::debug!("Error removing label '{}': {:#}", old_label, e);
         }
     }
 
@@ -3,9 +3,11 @@ use models::IssueState;
 use octocrab::models::pulls::ReviewState;
 
 use super::*;
+use crate::test_utils::{self, MockServer};
 
 fn make_context() -> (Pr, context::Client, context::RepoConfig) {
     let client = context::Client::new("token".to_string(), "org".to_string(), None, &[]).unwrap();
+    *client.bot_nick.borrow_mut() = Some("bot".to_owned());
 
     let config = context::RepoConfig {
         name: "the-project".to_string(),
@@ -15,23 +17,17 @@ fn make_context() -> (Pr, context::Client, context::RepoConfig) {
         reviewed_label: Some("reviewed".to_string()),
         block_merge_label: Some("block-merge".to_string()),
         automerge_grace_period: Some(10),
-        skip_review_label: None,
         merge_method: context::MergeMethod::Rebase,
-        comment_requests_change: false,
-        react_to_comments: false,
+        ..Default::default()
     };
 
     let pr = Pr {
         id: 13482,
-        author: "author".to_owned(),
-        number: 1,
-        commit_sha: "somesha".to_string(),
-        draft: false,
+        node_id: "PR_13482".to_owned(),
         state: Some(models::IssueState::Open),
-        updated_at: Utc::now() - Duration::seconds(50),
-        labels: HashSet::new(),
-        has_description: true,
-        requested_reviewers_remaining: 0,
+        created_at: Utc::now() - Duration::minutes(30),
+        updated_at: Some(Utc::now() - Duration::seconds(50)),
+        ..test_utils::pr(1)
     };
 
     (pr, client, config)
@@ -43,15 +39,21 @@ fn make_analyzer<'a>(
     config: &'a context::RepoConfig,
 ) -> Analyzer<'a> {
     let mut analyzer = Analyzer::new(pr, client, config);
-    analyzer.reviews = RemoteData::Local(vec![
+    analyzer.last_commit_at = RemoteData::Local(None);
+    analyzer.rebaseable = RemoteData::Local(true);
+    analyzer.timeline = RefCell::new(RemoteData::Local(Vec::new()));
+    analyzer.changed_files = RefCell::new(RemoteData::Local(Vec::new()));
+    analyzer.comments = RefCell::new(RemoteData::Local(Vec::new()));
+    analyzer.check_runs = RemoteData::Local(Vec::new());
+    analyzer.reviews = RefCell::new(RemoteData::Local(vec![
         review("1", ReviewState::Commented),
         review("2", ReviewState::Approved),
         review("3", ReviewState::Commented),
-    ]);
+    ]));
     analyzer.statuses = RemoteData::Local(
         vec![
-            ("status1".to_string(), StatusState::Success),
-            ("status2".to_string(), StatusState::Failure),
+            ("status1".to_string(), status(StatusState::Success)),
+            ("status2".to_string(), status(StatusState::Failure)),
         ]
         .into_iter()
         .collect(),
@@ -89,6 +91,315 @@ async fn merge_blocked_by_label() {
     );
 }
 
+#[tokio::test]
+async fn untrusted_bot_authors() {
+    let (mut pr, client, mut config) = make_context();
+    macro_rules! analyze {
+        () => {
+            make_analyzer(&pr, &client, &config)
+                .analyze()
+                .await
+                .unwrap()
+        };
+    }
+
+    pr.author = "helper[bot]".to_owned();
+    pr.author_is_bot = true;
+    // Bots are trusted unless configured otherwise.
+    assert!(analyze!().actions.merge);
+
+    config.trusted_authors = Some(vec!["dependabot[bot]".to_owned()]);
+    let analysis = analyze!();
+    assert!(!analysis.actions.merge);
+    assert_eq!(analysis.block_reasons, vec!["UntrustedBotAuthor"]);
+
+    config.trusted_authors = Some(vec!["helper[bot]".to_owned()]);
+    assert!(analyze!().actions.merge);
+
+    // Users aren't affected.
+    config.trusted_authors = Some(Vec::new());
+    pr.author_is_bot = false;
+    assert!(analyze!().actions.merge);
+}
+
+#[tokio::test]
+async fn merge_eligibility() {
+    let (mut pr, client, mut config) = make_context();
+    let labels = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+    let none = labels(&[]);
+    let block = labels(&["block-merge"]);
+    let automerge = labels(&["automerge"]);
+    let both = labels(&["block-merge", "automerge"]);
+
+    let analyzer = make_analyzer(&pr, &client, &config);
+    assert_eq!(
+        analyzer.resolve_merge_eligibility(&none),
+        Eligibility::Default
+    );
+    assert_eq!(
+        analyzer.resolve_merge_eligibility(&block),
+        Eligibility::Blocked
+    );
+    assert_eq!(
+        analyzer.resolve_merge_eligibility(&automerge),
+        Eligibility::Default
+    );
+
+    config.automerge_label = Some("automerge".to_string());
+    let analyzer = make_analyzer(&pr, &client, &config);
+    assert_eq!(
+        analyzer.resolve_merge_eligibility(&none),
+        Eligibility::NotOptedIn
+    );
+    assert_eq!(
+        analyzer.resolve_merge_eligibility(&block),
+        Eligibility::Blocked
+    );
+    assert_eq!(
+        analyzer.resolve_merge_eligibility(&automerge),
+        Eligibility::OptedIn
+    );
+    assert_eq!(
+        analyzer.resolve_merge_eligibility(&both),
+        Eligibility::Blocked
+    );
+
+    config.block_merge_label = None;
+    let analyzer = make_analyzer(&pr, &client, &config);
+    assert_eq!(
+        analyzer.resolve_merge_eligibility(&block),
+        Eligibility::NotOptedIn
+    );
+    assert_eq!(
+        analyzer.resolve_merge_eligibility(&both),
+        Eligibility::OptedIn
+    );
+
+    let analysis = make_analyzer(&pr, &client, &config)
+        .analyze()
+        .await
+        .unwrap();
+    assert!(!analysis.actions.merge);
+    assert_eq!(analysis.block_reasons, vec!["MissingAutomergeLabel"]);
+    pr.labels.insert("automerge".to_string());
+    let analysis = make_analyzer(&pr, &client, &config)
+        .analyze()
+        .await
+        .unwrap();
+    assert!(analysis.actions.merge);
+}
+
+#[test]
+fn description_template() {
+    let template = "## What\n<!-- Describe the change -->\n\n## Why\n<!-- And its reason -->\n";
+
+    // Without a template, anything goes.
+    assert!(is_description("## What\n", None));
+    assert!(!is_description("", None));
+
+    // Genuinely empty.
+    assert!(!is_description("", Some(template)));
+    assert!(!is_description("\n  \n", Some(template)));
+
+    // Unedited, or with parts of the template removed.
+    assert!(!is_description(template, Some(template)));
+    assert!(!is_description("## What\r\n\r\n## Why", Some(template)));
+    assert!(!is_description("## Why\n", Some(template)));
+
+    // Edited.
+    assert!(is_description(
+        "## What\nFix the thing\n\n## Why\n<!-- And its reason -->\n",
+        Some(template)
+    ));
+}
+
+#[tokio::test]
+async fn team_review_request() {
+    let (mut pr, client, config) = make_context();
+
+    client
+        .team_members
+        .borrow_mut()
+        .insert("the-team".to_owned(), vec!["1".to_owned()]);
+
+    // A team was requested, and one of its members approved.
+    pr.requested_reviewers_remaining = 1;
+    pr.requested_teams = vec!["the-team".to_string()];
+    let mut analyzer = make_analyzer(&pr, &client, &config);
+    analyzer.reviews = RefCell::new(RemoteData::Local(vec![review("1", ReviewState::Approved)]));
+    assert!(analyzer.required_actions().await.unwrap().merge);
+
+    // Someone outside of the team approved.
+    let mut analyzer = make_analyzer(&pr, &client, &config);
+    analyzer.reviews = RefCell::new(RemoteData::Local(vec![review("2", ReviewState::Approved)]));
+    let mut reasons = HashSet::new();
+    analyzer
+        .analyze_extended_checks(&mut reasons)
+        .await
+        .unwrap();
+    assert!(reasons.contains(&BlockReason::MissingReviews));
+
+    // Nobody approved yet.
+    analyzer.reviews = RefCell::new(RemoteData::Local(vec![review("1", ReviewState::Commented)]));
+    let mut reasons = HashSet::new();
+    analyzer
+        .analyze_extended_checks(&mut reasons)
+        .await
+        .unwrap();
+    assert!(reasons.contains(&BlockReason::MissingReviews));
+    assert!(!analyzer.required_actions().await.unwrap().merge);
+
+    // An individual reviewer is still requested besides the team.
+    pr.requested_reviewers_remaining = 2;
+    let mut analyzer = make_analyzer(&pr, &client, &config);
+    analyzer.reviews = RefCell::new(RemoteData::Local(vec![review("1", ReviewState::Approved)]));
+    assert!(analyzer
+        .analyze_basic_checks()
+        .contains(&BlockReason::MissingReviews));
+}
+
+#[tokio::test]
+async fn requested_reviewers_without_access() {
+    let (mut pr, client, config) = make_context();
+    for (user, has_access) in [("gone", false), ("present", true)].iter() {
+        client
+            .collaborators
+            .borrow_mut()
+            .insert(("the-project".to_owned(), user.to_string()), *has_access);
+    }
+
+    // Reviewers who lost access aren't waited on...
+    pr.requested_reviewers_remaining = 1;
+    pr.requested_users = vec!["gone".to_owned()];
+    let analysis = make_analyzer(&pr, &client, &config)
+        .analyze()
+        .await
+        .unwrap();
+    assert!(!analysis.actions.merge);
+    assert_eq!(
+        analysis.block_reasons,
+        vec![r#"ReviewerLostAccess { users: ["gone"] }"#]
+    );
+
+    // ...unlike the ones who still have access, so one of each is still waited on.
+    pr.requested_reviewers_remaining = 2;
+    pr.requested_users = vec!["gone".to_owned(), "present".to_owned()];
+    let analysis = make_analyzer(&pr, &client, &config)
+        .analyze()
+        .await
+        .unwrap();
+    assert!(!analysis.actions.merge);
+    assert_eq!(
+        analysis.block_reasons,
+        vec![
+            "MissingReviews",
+            r#"ReviewerLostAccess { users: ["gone"] }"#
+        ]
+    );
+
+    pr.requested_reviewers_remaining = 1;
+    pr.requested_users = vec!["present".to_owned()];
+    let analysis = make_analyzer(&pr, &client, &config)
+        .analyze()
+        .await
+        .unwrap();
+    assert_eq!(analysis.block_reasons, vec!["MissingReviews"]);
+}
+
+#[tokio::test]
+async fn mandatory_reviewers() {
+    macro_rules! assert_reasons {
+        ($author:expr, $reviews:expr, $expected:expr) => {{
+            let (mut pr, client, mut config) = make_context();
+            pr.author = $author.to_string();
+            config.mandatory_reviewers = vec!["boss".to_string()];
+            let mut analyzer = make_analyzer(&pr, &client, &config);
+            analyzer.reviews = RefCell::new(RemoteData::Local($reviews));
+            let mut reasons = HashSet::new();
+            analyzer
+                .analyze_extended_checks(&mut reasons)
+                .await
+                .unwrap();
+            reasons.remove(&BlockReason::CiNotPassing);
+            let expected: HashSet<BlockReason> = $expected.into_iter().collect();
+            assert!(reasons == expected);
+        }};
+    }
+
+    // The mandatory reviewer approved.
+    assert_reasons!(
+        "author",
+        vec![
+            review("someone", ReviewState::Approved),
+            review("boss", ReviewState::Approved)
+        ],
+        vec![]
+    );
+
+    // Only someone else approved.
+    assert_reasons!(
+        "author",
+        vec![review("someone", ReviewState::Approved)],
+        vec![BlockReason::MissingMandatoryApproval {
+            from_users: vec!["boss".to_string()]
+        }]
+    );
+
+    // Nobody approved.
+    assert_reasons!(
+        "author",
+        vec![review("boss", ReviewState::Commented)],
+        vec![BlockReason::MissingReviewApproval { from_users: vec![] }]
+    );
+
+    // The mandatory reviewer is the author.
+    assert_reasons!(
+        "boss",
+        vec![review("someone", ReviewState::Approved)],
+        vec![]
+    );
+}
+
+#[tokio::test]
+async fn min_distinct_reviewers() {
+    macro_rules! assert_merge {
+        ($reviews:expr, $merge:expr) => {{
+            let (pr, client, mut config) = make_context();
+            config.min_distinct_reviewers = Some(2);
+            let mut analyzer = make_analyzer(&pr, &client, &config);
+            analyzer.reviews = RefCell::new(RemoteData::Local($reviews));
+            assert_eq!(analyzer.required_actions().await.unwrap().merge, $merge);
+        }};
+    }
+
+    // One reviewer, even with several reviews.
+    assert_merge!(
+        vec![
+            review("1", ReviewState::Commented),
+            review("1", ReviewState::Approved)
+        ],
+        false
+    );
+
+    // The author doesn't count.
+    assert_merge!(
+        vec![
+            review("author", ReviewState::Commented),
+            review("1", ReviewState::Approved)
+        ],
+        false
+    );
+
+    // Two distinct reviewers, in any capacity.
+    assert_merge!(
+        vec![
+            review("1", ReviewState::Commented),
+            review("2", ReviewState::Approved)
+        ],
+        true
+    );
+}
+
 #[tokio::test]
 async fn trivial_merge_not_blocked_on_pending_reviews() {
     let (mut pr, client, mut config) = make_context();
@@ -126,7 +437,10 @@ async fn trivial_merge_blocked_on_requested_changes() {
     let mut analyzer = make_analyzer(&pr, &client, &config);
 
     // But one reviewer was like meh
-    analyzer.reviews = RemoteData::Local(vec![review("1", ReviewState::ChangesRequested)]);
+    analyzer.reviews = RefCell::new(RemoteData::Local(vec![review(
+        "1",
+        ReviewState::ChangesRequested,
+    )]));
 
     assert_eq!(
         analyzer.required_actions().await.unwrap(),
@@ -152,7 +466,7 @@ async fn trivial_merge_with_approval() {
     let mut analyzer = make_analyzer(&pr, &client, &config);
 
     // But one reviewer was satisfied
-    analyzer.reviews = RemoteData::Local(vec![review("1", ReviewState::Approved)]);
+    analyzer.reviews = RefCell::new(RemoteData::Local(vec![review("1", ReviewState::Approved)]));
 
     assert_eq!(
         analyzer.required_actions().await.unwrap(),
@@ -172,6 +486,99 @@ async fn draft_pr_actions() {
     assert_eq!(analyzer.required_actions().await.unwrap(), Actions::noop());
 }
 
+#[tokio::test]
+async fn evaluated_draft_pr_actions() {
+    let (mut pr, client, mut config) = make_context();
+    pr.draft = true;
+    config.evaluate_drafts = true;
+    let analyzer = make_analyzer(&pr, &client, &config);
+    assert_eq!(
+        analyzer.required_actions().await.unwrap(),
+        *Actions::noop()
+            .set_merge(false)
+            .set_label("reviewed", Presence::Present)
+            .set_label("ci-passed", Presence::Present)
+            .set_label("needs-description", Presence::Absent)
+    );
+
+    // Failing CI is reported too.
+    let mut analyzer = make_analyzer(&pr, &client, &config);
+    analyzer.statuses = RemoteData::Local(
+        vec![("status1".to_string(), status(StatusState::Failure))]
+            .into_iter()
+            .collect(),
+    );
+    let analysis = analyzer.analyze().await.unwrap();
+    assert!(!analysis.actions.merge);
+    assert!(analysis.actions.remove_labels.contains("ci-passed"));
+    assert_eq!(analysis.decision, Decision::Blocked);
+}
+
+#[tokio::test]
+async fn mark_ready_when_approved() {
+    macro_rules! assert_mark_ready {
+        ($draft:expr, $labeled:expr, $ci_status:expr, $mark_ready:expr) => {{
+            let (mut pr, client, mut config) = make_context();
+            pr.draft = $draft;
+            if $labeled {
+                pr.labels.insert("merge-when-ready".to_owned());
+            }
+            config.mark_ready_when_approved = true;
+            config.mark_ready_label = Some("merge-when-ready".to_owned());
+            let mut analyzer = make_analyzer(&pr, &client, &config);
+            analyzer.statuses = RemoteData::Local(
+                vec![("status1".to_string(), status($ci_status))]
+                    .into_iter()
+                    .collect(),
+            );
+            let actions = analyzer.required_actions().await.unwrap();
+            assert_eq!(actions.mark_ready, $mark_ready);
+            assert_eq!(actions.merge, !$draft && $ci_status == StatusState::Success);
+        }};
+    }
+
+    // An opted in, approved draft with passing CI.
+    assert_mark_ready!(true, true, StatusState::Success, true);
+
+    // Not opted in, not ready yet, or not a draft.
+    assert_mark_ready!(true, false, StatusState::Success, false);
+    assert_mark_ready!(true, true, StatusState::Pending, false);
+    assert_mark_ready!(false, true, StatusState::Success, false);
+}
+
+#[tokio::test]
+async fn reactions() {
+    macro_rules! assert_reaction {
+        ($has_description:expr, $ci_status:expr, $reaction:expr) => {{
+            let (mut pr, client, mut config) = make_context();
+            pr.has_description = $has_description;
+            config.use_reactions = true;
+            let mut analyzer = make_analyzer(&pr, &client, &config);
+            analyzer.statuses = RemoteData::Local(
+                vec![("status1".to_string(), status($ci_status))]
+                    .into_iter()
+                    .collect(),
+            );
+            let actions = analyzer.required_actions().await.unwrap();
+            assert_eq!(actions.reaction, $reaction);
+            // Reactions are feedback.
+            assert_eq!(actions.audit().reaction, $reaction);
+        }};
+    }
+
+    assert_reaction!(true, StatusState::Success, Some(Reaction::Ready));
+    assert_reaction!(true, StatusState::Pending, Some(Reaction::Waiting));
+    assert_reaction!(false, StatusState::Success, Some(Reaction::Blocked));
+    assert_reaction!(false, StatusState::Pending, Some(Reaction::Blocked));
+
+    // Nothing changes for drafts.
+    let (mut pr, client, mut config) = make_context();
+    pr.draft = true;
+    config.use_reactions = true;
+    let analyzer = make_analyzer(&pr, &client, &config);
+    assert_eq!(analyzer.required_actions().await.unwrap().reaction, None);
+}
+
 #[tokio::test]
 async fn closed_pr_actions() {
     let (mut pr, client, config) = make_context();
@@ -183,9 +590,53 @@ async fn closed_pr_actions() {
 #[tokio::test]
 async fn stale_pr_actions() {
     let (mut pr, client, config) = make_context();
-    pr.updated_at = Utc::now() - Duration::minutes(61);
+    pr.updated_at = Some(Utc::now() - Duration::minutes(61));
+    let analyzer = make_analyzer(&pr, &client, &config);
+    assert_eq!(analyzer.required_actions().await.unwrap(), Actions::noop());
+}
+
+#[tokio::test]
+async fn never_updated_pr() {
+    let server = MockServer::new(vec![(
+        "200 OK",
+        r#"{
+            "url": "https://api.github.com/repos/org/the-project/pulls/1",
+            "id": 1,
+            "number": 1,
+            "state": "open",
+            "created_at": "2024-01-01T00:00:00Z",
+            "updated_at": null,
+            "head": { "ref": "feature", "sha": "somesha" },
+            "base": { "ref": "main", "sha": "othersha" }
+        }"#,
+    )]);
+    let remote = test_utils::client(&server)
+        .get_pull_request("the-project", 1)
+        .await
+        .unwrap();
+    let fetched = Pr::from_octocrab_pull_request(remote, None);
+    assert_eq!(fetched.updated_at, None);
+    assert_eq!(
+        fetched.last_activity().to_rfc3339(),
+        "2024-01-01T00:00:00+00:00"
+    );
+
+    // Time-based checks go by the creation of PRs that were never updated.
+    let (mut pr, client, config) = make_context();
+    pr.updated_at = None;
+    let analyzer = make_analyzer(&pr, &client, &config);
+    assert!(analyzer.required_actions().await.unwrap().merge);
+
+    pr.created_at = Utc::now() - Duration::minutes(61);
     let analyzer = make_analyzer(&pr, &client, &config);
     assert_eq!(analyzer.required_actions().await.unwrap(), Actions::noop());
+
+    pr.created_at = Utc::now() - Duration::seconds(5);
+    let analysis = make_analyzer(&pr, &client, &config)
+        .analyze()
+        .await
+        .unwrap();
+    assert_eq!(analysis.block_reasons, vec!["InsideGracePeriod"]);
 }
 
 #[tokio::test]
@@ -227,7 +678,7 @@ async fn review_not_required_if_label_not_configured() {
         let (pr, client, mut config) = make_context();
         config.reviewed_label = None;
         let mut analyzer = make_analyzer(&pr, &client, &config);
-        analyzer.reviews = RemoteData::Local(cases);
+        analyzer.reviews = RefCell::new(RemoteData::Local(cases));
         assert_eq!(
             analyzer.required_actions().await.unwrap(),
             *Actions::noop()
@@ -248,10 +699,13 @@ async fn changes_requested_still_blocks_if_label_not_configured() {
     let (pr, client, mut config) = make_context();
     config.reviewed_label = None;
     let mut analyzer = make_analyzer(&pr, &client, &config);
-    analyzer.reviews = RemoteData::Local(vec![Review {
+    analyzer.reviews = RefCell::new(RemoteData::Local(vec![Review {
+        id: 0,
         user_name: "me".to_string(),
         state: ReviewState::ChangesRequested,
-    }]);
+        submitted_at: None,
+        body: String::new(),
+    }]));
     assert_eq!(
         analyzer.required_actions().await.unwrap(),
         *Actions::noop()
@@ -276,66 +730,310 @@ async fn no_ci_passed_label() {
 }
 
 #[tokio::test]
-async fn required_ci_not_passed_pr_actions() {
-    macro_rules! assert_ci_failed_actions {
-        ($cases:expr) => {{
-            let (pr, client, mut config) = make_context();
-            config.required_statuses = vec!["required1".to_string(), "required2".to_string()];
+async fn base_branch_mandatory_reviewers() {
+    macro_rules! assert_reasons {
+        ($base_ref:expr, $reviews:expr, $expected:expr) => {{
+            let (mut pr, client, mut config) = make_context();
+            pr.base_ref = $base_ref.to_owned();
+            config.base_branch_mandatory_reviewers = vec![context::BaseBranchReviewers {
+                base_glob: "release/*".to_owned(),
+                reviewers: vec!["release-manager".to_owned()],
+            }];
+            let mut analyzer = make_analyzer(&pr, &client, &config);
+            analyzer.reviews = RefCell::new(RemoteData::Local($reviews));
+            let analysis = analyzer.analyze().await.unwrap();
+            let expected: Vec<&str> = $expected;
+            assert_eq!(analysis.block_reasons, expected);
+        }};
+    }
+
+    // Approved by someone, which is enough for main.
+    assert_reasons!(
+        "main",
+        vec![review("someone", ReviewState::Approved)],
+        vec![]
+    );
+    assert_reasons!(
+        "release/1.0",
+        vec![review("someone", ReviewState::Approved)],
+        vec!["MissingMandatoryApproval { from_users: [\"release-manager\"] }"]
+    );
+    assert_reasons!(
+        "release/1.0",
+        vec![
+            review("someone", ReviewState::Approved),
+            review("release-manager", ReviewState::Approved)
+        ],
+        vec![]
+    );
+}
+
+#[tokio::test]
+async fn base_branch_statuses() {
+    macro_rules! assert_merge {
+        ($base_ref:expr, $statuses:expr, $merge:expr) => {{
+            let (mut pr, client, mut config) = make_context();
+            pr.base_ref = $base_ref.to_owned();
+            config.base_branch_statuses = vec![context::BaseBranchStatuses {
+                base_glob: "release/*".to_owned(),
+                statuses: vec!["status1".to_owned(), "integration".to_owned()],
+            }];
             let mut analyzer = make_analyzer(&pr, &client, &config);
+            let statuses: Vec<(&str, StatusState)> = $statuses;
             analyzer.statuses = RemoteData::Local(
-                $cases
+                statuses
                     .into_iter()
-                    .map(|(a, b): (&str, StatusState)| (a.to_string(), b))
+                    .map(|(name, state)| (name.to_string(), status(state)))
                     .collect(),
             );
-            assert_eq!(
-                analyzer.required_actions().await.unwrap(),
-                *Actions::noop()
-                    .set_merge(false)
-                    .set_label("reviewed", Presence::Present)
-                    .set_label("ci-passed", Presence::Absent)
-                    .set_label("needs-description", Presence::Absent)
-            );
+            assert_eq!(analyzer.required_actions().await.unwrap().merge, $merge);
         }};
     }
+    use StatusState::{Pending, Success};
 
-    // No passes
-    assert_ci_failed_actions!(vec![]);
-
-    // One pass, other missing
-    assert_ci_failed_actions!(vec![("required1", StatusState::Success)]);
-    assert_ci_failed_actions!(vec![("required2", StatusState::Success)]);
+    // Only the `required_statuses` are needed on main.
+    assert_merge!("main", vec![("status1", Success)], true);
+    assert_merge!("release/1.0", vec![("status1", Success)], false);
+    assert_merge!(
+        "release/1.0",
+        vec![("status1", Success), ("integration", Pending)],
+        false
+    );
+    assert_merge!(
+        "release/1.0",
+        vec![("status1", Success), ("integration", Success)],
+        true
+    );
+}
 
-    // One passed, other failed
+#[tokio::test]
+async fn conditional_required_statuses() {
+    macro_rules! assert_merge {
+        ($labels:expr, $statuses:expr, $merge:expr) => {{
+            let (mut pr, client, mut config) = make_context();
+            let labels: &[&str] = $labels;
+            pr.labels = labels.iter().map(|label| label.to_string()).collect();
+            config.conditional_required_statuses = vec![context::LabelStatuses {
+                label: "needs-e2e".to_owned(),
+                statuses: vec!["e2e".to_owned()],
+            }];
+            let mut analyzer = make_analyzer(&pr, &client, &config);
+            let statuses: Vec<(&str, StatusState)> = $statuses;
+            analyzer.statuses = RemoteData::Local(
+                statuses
+                    .into_iter()
+                    .map(|(name, state)| (name.to_string(), status(state)))
+                    .collect(),
+            );
+            assert_eq!(analyzer.required_actions().await.unwrap().merge, $merge);
+        }};
+    }
+    use StatusState::{Failure, Success};
 
-    assert_ci_failed_actions!(vec![
-        ("required2", StatusState::Success),
-        ("required1", StatusState::Error)
-    ]);
-    assert_ci_failed_actions!(vec![
-        ("required2", StatusState::Success),
-        ("required1", StatusState::Failure)
-    ]);
-    assert_ci_failed_actions!(vec![
-        ("required2", StatusState::Success),
-        ("required1", StatusState::Pending)
-    ]);
-    assert_ci_failed_actions!(vec![
-        ("required1", StatusState::Success),
-        ("required2", StatusState::Error)
-    ]);
-    assert_ci_failed_actions!(vec![
-        ("required1", StatusState::Success),
-        ("required2", StatusState::Failure)
-    ]);
-    assert_ci_failed_actions!(vec![
-        ("required1", StatusState::Success),
-        ("required2", StatusState::Pending)
-    ]);
+    assert_merge!(&[], vec![("status1", Success)], true);
+    assert_merge!(&[], vec![("status1", Success), ("e2e", Failure)], true);
+    assert_merge!(&["needs-e2e"], vec![("status1", Success)], false);
+    assert_merge!(
+        &["needs-e2e"],
+        vec![("status1", Success), ("e2e", Failure)],
+        false
+    );
+    assert_merge!(
+        &["needs-e2e"],
+        vec![("status1", Success), ("e2e", Success)],
+        true
+    );
+}
 
-    // Failing statuses with a non-required pass
+#[tokio::test]
+async fn tolerated_status_patterns() {
+    macro_rules! assert_merge {
+        ($statuses:expr, $merge:expr) => {{
+            let (pr, client, mut config) = make_context();
+            config.required_statuses = vec!["test (ubuntu)".to_owned(), "test (macos)".to_owned()];
+            config.required_status_groups = vec![vec!["lint (macos)".to_owned()]];
+            config.forbidden_statuses = vec!["flaky (macos)".to_owned()];
+            config.tolerated_status_patterns = vec!["* (macos)".to_owned()];
+            let mut analyzer = make_analyzer(&pr, &client, &config);
+            let statuses: Vec<(&str, StatusState)> = $statuses;
+            analyzer.statuses = RemoteData::Local(
+                statuses
+                    .into_iter()
+                    .map(|(name, state)| (name.to_string(), status(state)))
+                    .collect(),
+            );
+            assert_eq!(analyzer.required_actions().await.unwrap().merge, $merge);
+        }};
+    }
+    use StatusState::{Failure, Success};
 
-    assert_ci_failed_actions!(vec![("not-required", StatusState::Success)]);
+    assert_merge!(vec![("test (ubuntu)", Success)], true);
+    assert_merge!(
+        vec![
+            ("test (ubuntu)", Success),
+            ("test (macos)", Failure),
+            ("lint (macos)", Failure),
+            ("flaky (macos)", Failure)
+        ],
+        true
+    );
+    assert_merge!(
+        vec![("test (ubuntu)", Failure), ("test (macos)", Success)],
+        false
+    );
+}
+
+#[tokio::test]
+async fn tolerated_patterns_match_whole_components() {
+    macro_rules! assert_merge {
+        ($required:expr, $merge:expr) => {{
+            let (pr, client, mut config) = make_context();
+            config.required_statuses = vec![$required.to_owned()];
+            config.tolerated_status_patterns = vec!["ci/**/optional".to_owned()];
+            let mut analyzer = make_analyzer(&pr, &client, &config);
+            analyzer.statuses = RemoteData::Local(
+                vec![($required.to_string(), status(StatusState::Failure))]
+                    .into_iter()
+                    .collect(),
+            );
+            assert_eq!(analyzer.required_actions().await.unwrap().merge, $merge);
+        }};
+    }
+
+    assert_merge!("ci/optional", true);
+    assert_merge!("ci/linux/optional", true);
+    // Only shares a suffix with the pattern, so the failing required status still blocks.
+    assert_merge!("ci/nonoptional", false);
+}
+
+#[tokio::test]
+async fn required_status_groups() {
+    macro_rules! assert_merge {
+        ($required:expr, $statuses:expr, $merge:expr) => {{
+            let (pr, client, mut config) = make_context();
+            let required: Vec<&str> = $required;
+            config.required_statuses = required.into_iter().map(String::from).collect();
+            config.required_status_groups = vec![
+                vec!["fast".to_owned()],
+                vec!["full-linux".to_owned(), "full-windows".to_owned()],
+            ];
+            let mut analyzer = make_analyzer(&pr, &client, &config);
+            let statuses: Vec<(&str, StatusState)> = $statuses;
+            analyzer.statuses = RemoteData::Local(
+                statuses
+                    .into_iter()
+                    .map(|(name, state)| (name.to_string(), status(state)))
+                    .collect(),
+            );
+            assert_eq!(analyzer.required_actions().await.unwrap().merge, $merge);
+        }};
+    }
+    use StatusState::{Failure, Pending, Success};
+
+    // No group passed.
+    assert_merge!(vec![], vec![], false);
+    assert_merge!(
+        vec![],
+        vec![("fast", Pending), ("full-linux", Success)],
+        false
+    );
+
+    // The fast group passed, while the full one is pending.
+    assert_merge!(
+        vec![],
+        vec![
+            ("fast", Success),
+            ("full-linux", Pending),
+            ("full-windows", Failure)
+        ],
+        true
+    );
+
+    // The full group passed.
+    assert_merge!(
+        vec![],
+        vec![
+            ("fast", Failure),
+            ("full-linux", Success),
+            ("full-windows", Success)
+        ],
+        true
+    );
+
+    // The flat list must pass too.
+    assert_merge!(
+        vec!["lint"],
+        vec![("fast", Success), ("lint", Pending)],
+        false
+    );
+    assert_merge!(
+        vec!["lint"],
+        vec![("fast", Success), ("lint", Success)],
+        true
+    );
+}
+
+#[tokio::test]
+async fn required_ci_not_passed_pr_actions() {
+    macro_rules! assert_ci_failed_actions {
+        ($cases:expr) => {{
+            let (pr, client, mut config) = make_context();
+            config.required_statuses = vec!["required1".to_string(), "required2".to_string()];
+            let mut analyzer = make_analyzer(&pr, &client, &config);
+            analyzer.statuses = RemoteData::Local(
+                $cases
+                    .into_iter()
+                    .map(|(a, b): (&str, StatusState)| (a.to_string(), status(b)))
+                    .collect(),
+            );
+            assert_eq!(
+                analyzer.required_actions().await.unwrap(),
+                *Actions::noop()
+                    .set_merge(false)
+                    .set_label("reviewed", Presence::Present)
+                    .set_label("ci-passed", Presence::Absent)
+                    .set_label("needs-description", Presence::Absent)
+            );
+        }};
+    }
+
+    // No passes
+    assert_ci_failed_actions!(vec![]);
+
+    // One pass, other missing
+    assert_ci_failed_actions!(vec![("required1", StatusState::Success)]);
+    assert_ci_failed_actions!(vec![("required2", StatusState::Success)]);
+
+    // One passed, other failed
+
+    assert_ci_failed_actions!(vec![
+        ("required2", StatusState::Success),
+        ("required1", StatusState::Error)
+    ]);
+    assert_ci_failed_actions!(vec![
+        ("required2", StatusState::Success),
+        ("required1", StatusState::Failure)
+    ]);
+    assert_ci_failed_actions!(vec![
+        ("required2", StatusState::Success),
+        ("required1", StatusState::Pending)
+    ]);
+    assert_ci_failed_actions!(vec![
+        ("required1", StatusState::Success),
+        ("required2", StatusState::Error)
+    ]);
+    assert_ci_failed_actions!(vec![
+        ("required1", StatusState::Success),
+        ("required2", StatusState::Failure)
+    ]);
+    assert_ci_failed_actions!(vec![
+        ("required1", StatusState::Success),
+        ("required2", StatusState::Pending)
+    ]);
+
+    // Failing statuses with a non-required pass
+
+    assert_ci_failed_actions!(vec![("not-required", StatusState::Success)]);
 
     assert_ci_failed_actions!(vec![
         ("not-required", StatusState::Success),
@@ -363,7 +1061,7 @@ async fn review_approval_pr_actions() {
         ($approved:expr, $cases:expr) => {{
             let (pr, client, config) = make_context();
             let mut analyzer = make_analyzer(&pr, &client, &config);
-            analyzer.reviews = RemoteData::Local($cases);
+            analyzer.reviews = RefCell::new(RemoteData::Local($cases));
             assert_eq!(
                 analyzer.required_actions().await.unwrap(),
                 *Actions::noop()
@@ -410,10 +1108,65 @@ async fn review_approval_pr_actions() {
     assert_approved!(true, vec![review("1", Approved), review("1", Commented)]);
 }
 
+#[tokio::test]
+async fn approvers_are_passed_to_the_merge() {
+    macro_rules! approvers {
+        ($approved_by_trailers:expr, $reviews:expr) => {{
+            let (pr, client, mut config) = make_context();
+            config.approved_by_trailers = $approved_by_trailers;
+            let mut analyzer = make_analyzer(&pr, &client, &config);
+            analyzer.reviews = RefCell::new(RemoteData::Local($reviews));
+            analyzer.required_actions().await.unwrap().approvers
+        }};
+    }
+
+    assert_eq!(
+        approvers!(
+            true,
+            vec![
+                review("1", ReviewState::Approved),
+                review("2", ReviewState::Approved),
+                review("3", ReviewState::Commented),
+            ]
+        ),
+        vec!["1", "2"]
+    );
+    assert!(approvers!(false, vec![review("1", ReviewState::Approved)]).is_empty());
+    // Blocked PRs aren't merged.
+    assert!(approvers!(true, vec![review("1", ReviewState::ChangesRequested)]).is_empty());
+}
+
+fn status(state: StatusState) -> CommitStatus {
+    CommitStatus {
+        state,
+        updated_at: Some(Utc::now()),
+    }
+}
+
+fn timeline_event(event: models::Event, seconds_ago: i64) -> context::TimelineEvent {
+    context::TimelineEvent {
+        event,
+        at: Utc::now() - Duration::seconds(seconds_ago),
+        actor: Some("author".to_owned()),
+        label: None,
+        requested_reviewer: None,
+    }
+}
+
+fn review_requested(reviewer: &str) -> context::TimelineEvent {
+    context::TimelineEvent {
+        requested_reviewer: Some(reviewer.to_owned()),
+        ..timeline_event(models::Event::ReviewRequested, 3600)
+    }
+}
+
 fn review(user_name: &str, state: ReviewState) -> Review {
     Review {
+        id: 0,
         user_name: user_name.to_string(),
         state,
+        submitted_at: None,
+        body: String::new(),
     }
 }
 
@@ -423,7 +1176,7 @@ async fn grace_period_prevents_merge() {
         ($grace_period:expr, $updated_seconds_ago:expr, $merge:expr) => {{
             let (mut pr, client, mut config) = make_context();
             config.automerge_grace_period = $grace_period;
-            pr.updated_at = Utc::now() - Duration::seconds($updated_seconds_ago);
+            pr.updated_at = Some(Utc::now() - Duration::seconds($updated_seconds_ago));
             let analyzer = make_analyzer(&pr, &client, &config);
             assert_eq!(
                 analyzer.required_actions().await.unwrap(),
@@ -466,3 +1219,2029 @@ async fn requested_reviews() {
     assert_merge!(2, false);
     assert_merge!(3, false);
 }
+
+fn comment(id: u64, author: &str, body: &str) -> Comment {
+    // Later comments have a higher id.
+    let created_at = Utc::now() - Duration::minutes(100) + Duration::minutes(id as i64);
+    Comment {
+        id,
+        author: author.to_owned(),
+        body: body.to_owned(),
+        created_at,
+        updated_at: created_at,
+    }
+}
+
+#[tokio::test]
+async fn proactive_status_comment() {
+    macro_rules! assert_comments {
+        ($has_description:expr, $comments:expr, $post:expr, $update:expr) => {{
+            let (mut pr, client, mut config) = make_context();
+            *client.bot_nick.borrow_mut() = Some("bot".to_owned());
+            config.proactive_status_comment = true;
+            pr.has_description = $has_description;
+            let mut analyzer = make_analyzer(&pr, &client, &config);
+            analyzer.comments = RefCell::new(RemoteData::Local($comments));
+            let actions = analyzer.required_actions().await.unwrap();
+            let post: Vec<&str> = $post;
+            let update: Vec<(u64, &str)> = $update;
+            assert_eq!(actions.post_comment, post);
+            assert_eq!(
+                actions.update_comment,
+                update
+                    .into_iter()
+                    .map(|(id, body)| (id, body.to_owned()))
+                    .collect::<Vec<_>>()
+            );
+        }};
+    }
+
+    const BLOCKED: &str = "### Merge status\n- This PR lacks a description.\n";
+    const MERGEABLE: &str = "### Merge status\nAll good, this PR is going to be merged.\n";
+
+    // Blocked without a status comment: create one.
+    assert_comments!(false, vec![], vec![BLOCKED], vec![]);
+    assert_comments!(
+        false,
+        vec![comment(1, "someone", BLOCKED)],
+        vec![BLOCKED],
+        vec![]
+    );
+
+    // Blocked with an outdated status comment: update it.
+    assert_comments!(
+        false,
+        vec![comment(
+            1,
+            "bot",
+            "### Merge status\n- This PR is a draft.\n"
+        )],
+        vec![],
+        vec![(1, BLOCKED)]
+    );
+
+    // Only the latest status comment is updated.
+    assert_comments!(
+        false,
+        vec![
+            comment(1, "bot", "### Merge status\n- This PR is a draft.\n"),
+            comment(2, "bot", "### Merge status\n- This PR is a draft.\n"),
+        ],
+        vec![],
+        vec![(2, BLOCKED)]
+    );
+
+    // Blocked with an up-to-date status comment: nothing to do.
+    assert_comments!(false, vec![comment(1, "bot", BLOCKED)], vec![], vec![]);
+
+    // Mergeable: neutralize the status comment, if any.
+    assert_comments!(true, vec![], vec![], vec![]);
+    assert_comments!(
+        true,
+        vec![comment(1, "bot", BLOCKED)],
+        vec![],
+        vec![(1, MERGEABLE)]
+    );
+    assert_comments!(true, vec![comment(1, "bot", MERGEABLE)], vec![], vec![]);
+}
+
+#[tokio::test]
+async fn mention_updates_existing_status_comment() {
+    macro_rules! assert_comments {
+        ($comments:expr, $post:expr, $update:expr) => {{
+            let (mut pr, client, mut config) = make_context();
+            *client.bot_nick.borrow_mut() = Some("bot".to_owned());
+            config.react_to_comments = true;
+            pr.has_description = false;
+            let mut analyzer = make_analyzer(&pr, &client, &config);
+            analyzer.comments = RefCell::new(RemoteData::Local($comments));
+            let actions = analyzer.required_actions().await.unwrap();
+            let post: Vec<&str> = $post;
+            let update: Vec<(u64, &str)> = $update;
+            assert_eq!(actions.post_comment, post);
+            assert_eq!(
+                actions.update_comment,
+                update
+                    .into_iter()
+                    .map(|(id, body)| (id, body.to_owned()))
+                    .collect::<Vec<_>>()
+            );
+        }};
+    }
+
+    const BLOCKED: &str = "### Merge status\n- This PR lacks a description.\n";
+    const OUTDATED: &str = "### Merge status\n- This PR is a draft.\n";
+
+    // No mention, nothing to answer.
+    assert_comments!(vec![comment(1, "bot", OUTDATED)], vec![], vec![]);
+
+    // First mention: post a new comment.
+    assert_comments!(
+        vec![comment(1, "someone", "@bot why?")],
+        vec![BLOCKED],
+        vec![]
+    );
+
+    // Mention after a previous answer: edit it rather than posting again.
+    assert_comments!(
+        vec![
+            comment(1, "someone", "@bot why?"),
+            comment(2, "bot", OUTDATED),
+            comment(3, "someone", "@bot and now?"),
+        ],
+        vec![],
+        vec![(2, BLOCKED)]
+    );
+
+    // The answer was edited after the last mention: already answered.
+    let mut answer = comment(2, "bot", BLOCKED);
+    answer.updated_at = Utc::now();
+    assert_comments!(
+        vec![
+            comment(1, "someone", "@bot why?"),
+            answer,
+            comment(3, "someone", "@bot and now?"),
+        ],
+        vec![],
+        vec![]
+    );
+}
+
+#[tokio::test]
+async fn status_pending_too_long() {
+    macro_rules! assert_reasons {
+        ($timeout:expr, $pending_seconds:expr, $expected:expr) => {{
+            let (pr, client, mut config) = make_context();
+            config.pending_status_timeout_secs = $timeout;
+            let mut analyzer = make_analyzer(&pr, &client, &config);
+            analyzer.statuses = RemoteData::Local(
+                vec![(
+                    "status1".to_string(),
+                    CommitStatus {
+                        state: StatusState::Pending,
+                        updated_at: Some(Utc::now() - Duration::seconds($pending_seconds)),
+                    },
+                )]
+                .into_iter()
+                .collect(),
+            );
+            let mut reasons = HashSet::new();
+            analyzer
+                .analyze_extended_checks(&mut reasons)
+                .await
+                .unwrap();
+            let expected: HashSet<BlockReason> = $expected.into_iter().collect();
+            assert!(reasons == expected);
+        }};
+    }
+
+    // No timeout configured.
+    assert_reasons!(None, 3600, vec![BlockReason::CiNotPassing]);
+
+    // Recently pending.
+    assert_reasons!(Some(600), 60, vec![BlockReason::CiNotPassing]);
+
+    // Pending for too long.
+    assert_reasons!(
+        Some(600),
+        3600,
+        vec![
+            BlockReason::CiNotPassing,
+            BlockReason::StatusPendingTooLong {
+                statuses: vec!["status1".to_string()]
+            }
+        ]
+    );
+}
+
+#[tokio::test]
+async fn ci_label_requires_statuses() {
+    macro_rules! assert_ci_label {
+        ($requires_statuses:expr, $present:expr, $absent:expr) => {{
+            let (pr, client, mut config) = make_context();
+            config.required_statuses = Vec::new();
+            config.ci_label_requires_statuses = $requires_statuses;
+            let analyzer = make_analyzer(&pr, &client, &config);
+            let actions = analyzer.required_actions().await.unwrap();
+            assert_eq!(actions.add_labels.contains("ci-passed"), $present);
+            assert_eq!(actions.remove_labels.contains("ci-passed"), $absent);
+        }};
+    }
+
+    assert_ci_label!(false, true, false);
+    assert_ci_label!(true, false, false);
+}
+
+#[tokio::test]
+async fn forbidden_statuses() {
+    macro_rules! assert_merge {
+        ($forbid_successful:expr, $state:expr, $merge:expr) => {{
+            let (pr, client, mut config) = make_context();
+            config.forbidden_statuses = vec!["do-not-merge".to_string()];
+            config.forbid_successful_statuses = $forbid_successful;
+            let mut analyzer = make_analyzer(&pr, &client, &config);
+            let state: Option<StatusState> = $state;
+            if let (Some(state), RemoteData::Local(statuses)) = (state, &mut analyzer.statuses) {
+                statuses.insert("do-not-merge".to_string(), status(state));
+            }
+            let actions = analyzer.required_actions().await.unwrap();
+            assert_eq!(actions.merge, $merge);
+        }};
+    }
+
+    // Absent.
+    assert_merge!(false, None, true);
+    assert_merge!(true, None, true);
+
+    // Failing.
+    assert_merge!(false, Some(StatusState::Failure), false);
+    assert_merge!(false, Some(StatusState::Pending), false);
+
+    // Successful.
+    assert_merge!(false, Some(StatusState::Success), true);
+    assert_merge!(true, Some(StatusState::Success), false);
+}
+
+#[tokio::test]
+async fn grace_period_since_ready_for_review() {
+    macro_rules! assert_merge {
+        ($updated_seconds_ago:expr, $ready_seconds_ago:expr, $merge:expr) => {{
+            let (mut pr, client, mut config) = make_context();
+            config.automerge_grace_period = Some(30);
+            pr.updated_at = Some(Utc::now() - Duration::seconds($updated_seconds_ago));
+            let mut analyzer = make_analyzer(&pr, &client, &config);
+            let ready_at: Option<i64> = $ready_seconds_ago;
+            analyzer.timeline = RefCell::new(RemoteData::Local(
+                ready_at
+                    .map(|ago| timeline_event(models::Event::ReadyForReview, ago))
+                    .into_iter()
+                    .collect(),
+            ));
+            assert_eq!(analyzer.required_actions().await.unwrap().merge, $merge);
+        }};
+    }
+
+    // Never was a draft.
+    assert_merge!(20, None, false);
+
+    // Updated right after becoming ready, a while ago.
+    assert_merge!(20, Some(40), true);
+
+    // Became ready recently.
+    assert_merge!(20, Some(25), false);
+
+    // Became ready long before the last update.
+    assert_merge!(20, Some(3600), false);
+}
+
+#[tokio::test]
+async fn grace_anchor() {
+    macro_rules! assert_merge {
+        ($anchor:expr, $updated_seconds_ago:expr, $ci_seconds_ago:expr, $merge:expr) => {{
+            let (mut pr, client, mut config) = make_context();
+            config.automerge_grace_period = Some(30);
+            config.grace_anchor = $anchor;
+            pr.updated_at = Some(Utc::now() - Duration::seconds($updated_seconds_ago));
+            let mut analyzer = make_analyzer(&pr, &client, &config);
+            analyzer.statuses = RemoteData::Local(
+                ["status1", "status2"]
+                    .iter()
+                    .map(|name| {
+                        let status = CommitStatus {
+                            state: StatusState::Success,
+                            updated_at: Some(Utc::now() - Duration::seconds($ci_seconds_ago)),
+                        };
+                        (name.to_string(), status)
+                    })
+                    .collect(),
+            );
+            assert_eq!(analyzer.required_actions().await.unwrap().merge, $merge);
+        }};
+    }
+
+    use context::GraceAnchor::{CiCompletion, LastUpdate};
+
+    // CI completed after the last update.
+    assert_merge!(LastUpdate, 40, 20, true);
+    assert_merge!(CiCompletion, 40, 20, false);
+
+    // CI completed before the last update.
+    assert_merge!(LastUpdate, 20, 40, false);
+    assert_merge!(CiCompletion, 20, 40, true);
+}
+
+#[tokio::test]
+async fn grace_period_from_last_commit_or_review() {
+    let (mut pr, client, mut config) = make_context();
+    config.automerge_grace_period = Some(30);
+    config.grace_anchor = context::GraceAnchor::LastCommitOrReview;
+    // Only the labels changed recently.
+    pr.updated_at = Some(Utc::now() - Duration::seconds(5));
+    let seconds_ago = |seconds| Some(Utc::now() - Duration::seconds(seconds));
+    let merges = |commit_seconds_ago, review_seconds_ago| {
+        let mut analyzer = make_analyzer(&pr, &client, &config);
+        analyzer.last_commit_at = RemoteData::Local(seconds_ago(commit_seconds_ago));
+        let mut approval = review("2", ReviewState::Approved);
+        approval.submitted_at = seconds_ago(review_seconds_ago);
+        analyzer.reviews = RefCell::new(RemoteData::Local(vec![approval]));
+        async move { analyzer.required_actions().await.unwrap().merge }
+    };
+
+    assert!(merges(60, 40).await);
+    // A new commit or review restarts the grace period.
+    assert!(!merges(10, 40).await);
+    assert!(!merges(60, 10).await);
+
+    // The metadata changes restart it when measured from the last update.
+    config.grace_anchor = context::GraceAnchor::LastUpdate;
+    let analyzer = make_analyzer(&pr, &client, &config);
+    assert!(!analyzer.required_actions().await.unwrap().merge);
+}
+
+#[test]
+fn merge_when_green_command() {
+    assert!(is_merge_when_green("/octobors merge-when-green"));
+    assert!(is_merge_when_green(
+        "LGTM!\r\n  /octobors merge-when-green  \n"
+    ));
+    assert!(!is_merge_when_green("/octobors merge-when-green please"));
+    assert!(!is_merge_when_green(
+        "Should I use `/octobors merge-when-green`?"
+    ));
+    assert!(!is_merge_when_green("/octobors merge"));
+}
+
+#[test]
+fn skip_check_command() {
+    assert_eq!(
+        skip_check_commands("/octobors skip-check ci/flaky"),
+        vec!["ci/flaky"]
+    );
+    assert_eq!(
+        skip_check_commands(
+            "It's broken on main.\n  /octobors skip-check build (windows)  \n/octobors skip-check lint"
+        ),
+        vec!["build (windows)", "lint"]
+    );
+    assert!(skip_check_commands("/octobors skip-check").is_empty());
+    assert!(skip_check_commands("/octobors skip-checks lint").is_empty());
+    assert!(skip_check_commands("Please /octobors skip-check lint").is_empty());
+}
+
+#[tokio::test]
+async fn skip_check_waivers() {
+    macro_rules! analyze {
+        ($has_description:expr, $comments:expr) => {
+            analyze!($has_description, $comments, None)
+        };
+        ($has_description:expr, $comments:expr, $last_commit_at:expr) => {{
+            let (mut pr, client, mut config) = make_context();
+            pr.has_description = $has_description;
+            config.proactive_status_comment = true;
+            *client.bot_nick.borrow_mut() = Some("bot".to_owned());
+            client
+                .collaborators
+                .borrow_mut()
+                .insert(("the-project".to_owned(), "maintainer".to_owned()), true);
+            client
+                .collaborators
+                .borrow_mut()
+                .insert(("the-project".to_owned(), "outsider".to_owned()), false);
+            let mut analyzer = make_analyzer(&pr, &client, &config);
+            analyzer.statuses = RemoteData::Local(
+                vec![("status1".to_string(), status(StatusState::Failure))]
+                    .into_iter()
+                    .collect(),
+            );
+            analyzer.comments = RefCell::new(RemoteData::Local($comments));
+            analyzer.last_commit_at = RemoteData::Local($last_commit_at);
+            analyzer.analyze().await.unwrap()
+        }};
+    }
+
+    assert!(!analyze!(true, vec![]).actions.merge);
+
+    // Only collaborators other than the bot can waive checks.
+    let waiver = "/octobors skip-check status1";
+    assert!(
+        !analyze!(true, vec![comment(1, "outsider", waiver)])
+            .actions
+            .merge
+    );
+    assert!(
+        !analyze!(true, vec![comment(1, "bot", waiver)])
+            .actions
+            .merge
+    );
+    let analysis = analyze!(true, vec![comment(1, "maintainer", waiver)]);
+    assert!(analysis.actions.merge);
+    assert!(analysis.actions.add_labels.contains("ci-passed"));
+
+    // Waivers don't carry over to commits pushed after them.
+    let commit_minutes_ago = |minutes| Some(Utc::now() - Duration::minutes(minutes));
+    let waived = vec![comment(1, "maintainer", waiver)];
+    assert!(
+        analyze!(true, waived.clone(), commit_minutes_ago(100))
+            .actions
+            .merge
+    );
+    assert!(!analyze!(true, waived, commit_minutes_ago(10)).actions.merge);
+
+    // Waivers are listed in the status comment of blocked PRs.
+    let analysis = analyze!(false, vec![comment(1, "maintainer", waiver)]);
+    assert!(!analysis.actions.merge);
+    assert_eq!(
+        analysis.actions.post_comment,
+        vec![
+            "### Merge status\n- Check 'status1' was waived by @maintainer.\n- This PR lacks a \
+             description.\n"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn comments_from_requested_reviewers() {
+    let (pr, client, mut config) = make_context();
+    config.comment_requests_change = true;
+    config.comments_only_from_requested_reviewers = true;
+    let mut analyzer = make_analyzer(&pr, &client, &config);
+
+    // "1" and "3" commented, but only "3" was asked for a review.
+    analyzer.timeline = RefCell::new(RemoteData::Local(vec![review_requested("3")]));
+    let mut reasons = HashSet::new();
+    analyzer
+        .analyze_extended_checks(&mut reasons)
+        .await
+        .unwrap();
+    assert_eq!(
+        reasons,
+        vec![BlockReason::MissingReviewApproval {
+            from_users: vec!["3".to_owned()]
+        }]
+        .into_iter()
+        .collect()
+    );
+
+    analyzer.timeline = RefCell::new(RemoteData::Local(vec![review_requested("4")]));
+    let mut reasons = HashSet::new();
+    analyzer
+        .analyze_extended_checks(&mut reasons)
+        .await
+        .unwrap();
+    assert!(reasons.is_empty(), "{:?}", reasons);
+}
+
+#[tokio::test]
+async fn reviews_in_progress() {
+    macro_rules! analyze {
+        ($requested_users:expr) => {{
+            let (mut pr, client, mut config) = make_context();
+            pr.requested_reviewers_remaining = 1;
+            pr.requested_users = $requested_users;
+            config.proactive_status_comment = true;
+            *client.bot_nick.borrow_mut() = Some("bot".to_owned());
+            client
+                .collaborators
+                .borrow_mut()
+                .insert((config.name.clone(), "4".to_owned()), true);
+            let mut analyzer = make_analyzer(&pr, &client, &config);
+            analyzer.reviews = RefCell::new(RemoteData::Local(vec![
+                review("2", ReviewState::Approved),
+                review("4", ReviewState::Pending),
+            ]));
+            analyzer.analyze().await.unwrap()
+        }};
+    }
+
+    let analysis = analyze!(vec!["4".to_owned()]);
+    assert_eq!(analysis.block_reasons, vec!["MissingReviews"]);
+    assert_eq!(
+        analysis.actions.post_comment,
+        vec![
+            "### Merge status\n- @4 is reviewing this PR.\n- Still waiting for requested \
+             reviewers to review this.\n"
+        ]
+    );
+
+    // Pending reviews from users whose review wasn't requested aren't reported.
+    let analysis = analyze!(Vec::new());
+    assert_eq!(analysis.block_reasons, vec!["MissingReviews"]);
+    assert_eq!(
+        analysis.actions.post_comment,
+        vec!["### Merge status\n- Still waiting for requested reviewers to review this.\n"]
+    );
+}
+
+#[tokio::test]
+async fn advisory_reviewers() {
+    macro_rules! analyze {
+        ($has_description:expr, $reviewer:expr) => {{
+            let (mut pr, client, mut config) = make_context();
+            pr.has_description = $has_description;
+            config.proactive_status_comment = true;
+            config.advisory_reviewers = vec!["style-bot".to_owned()];
+            *client.bot_nick.borrow_mut() = Some("bot".to_owned());
+            let mut analyzer = make_analyzer(&pr, &client, &config);
+            analyzer.reviews = RefCell::new(RemoteData::Local(vec![
+                review("2", ReviewState::Approved),
+                review($reviewer, ReviewState::ChangesRequested),
+            ]));
+            analyzer.analyze().await.unwrap()
+        }};
+    }
+
+    assert!(analyze!(true, "style-bot").actions.merge);
+    let analysis = analyze!(true, "3");
+    assert!(!analysis.actions.merge);
+    assert_eq!(
+        analysis.block_reasons,
+        vec![r#"MissingReviewApproval { from_users: ["3"] }"#]
+    );
+
+    // Advisory concerns are listed in the status comment of blocked PRs.
+    let analysis = analyze!(false, "style-bot");
+    assert_eq!(
+        analysis.actions.post_comment,
+        vec![
+            "### Merge status\n- There are advisory concerns from @style-bot.\n- This PR lacks a \
+             description.\n"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn ready_comment() {
+    macro_rules! analyze {
+        ($updated_secs_ago:expr, $statuses:expr, $comments:expr) => {{
+            let (mut pr, client, mut config) = make_context();
+            pr.updated_at = Some(Utc::now() - Duration::seconds($updated_secs_ago));
+            config.automerge_grace_period = Some(60);
+            config.ready_comment = Some("Merging shortly!".to_owned());
+            *client.bot_nick.borrow_mut() = Some("bot".to_owned());
+            let mut analyzer = make_analyzer(&pr, &client, &config);
+            analyzer.statuses = RemoteData::Local(
+                $statuses
+                    .iter()
+                    .map(|(name, state): &(&str, StatusState)| (name.to_string(), status(*state)))
+                    .collect(),
+            );
+            analyzer.comments = RefCell::new(RemoteData::Local($comments));
+            analyzer.analyze().await.unwrap()
+        }};
+    }
+    let passing = [("status1", StatusState::Success)];
+    let failing = [("status1", StatusState::Failure)];
+
+    // Only the grace period is left.
+    let analysis = analyze!(5, passing, Vec::new());
+    assert_eq!(analysis.block_reasons, vec!["InsideGracePeriod"]);
+    assert_eq!(
+        analysis.actions.post_comment,
+        vec!["### Ready to merge\nMerging shortly!\n"]
+    );
+
+    // The comment is only posted once.
+    let ready = comment(1, "bot", "### Ready to merge\nMerging shortly!\n");
+    let analysis = analyze!(5, passing, vec![ready]);
+    assert!(analysis.actions.post_comment.is_empty());
+
+    // Other reasons are left.
+    let analysis = analyze!(5, failing, Vec::new());
+    assert_eq!(
+        analysis.block_reasons,
+        vec!["CiNotPassing", "InsideGracePeriod"]
+    );
+    assert!(analysis.actions.post_comment.is_empty());
+
+    // Nothing is left.
+    let analysis = analyze!(120, passing, Vec::new());
+    assert!(analysis.actions.merge);
+    assert!(analysis.actions.post_comment.is_empty());
+}
+
+#[tokio::test]
+async fn soft_block_labels() {
+    macro_rules! analyze {
+        ($labeled_hours_ago:expr, $comments:expr) => {{
+            let (mut pr, client, mut config) = make_context();
+            pr.labels.insert("risky".to_owned());
+            pr.updated_at = Some(Utc::now() - Duration::hours(3));
+            config.soft_block_labels = vec!["risky".to_owned()];
+            *client.bot_nick.borrow_mut() = Some("bot".to_owned());
+            let mut analyzer = make_analyzer(&pr, &client, &config);
+            analyzer.timeline = RefCell::new(RemoteData::Local(vec![context::TimelineEvent {
+                label: Some("risky".to_owned()),
+                ..timeline_event(models::Event::Labeled, $labeled_hours_ago * 3600)
+            }]));
+            analyzer.comments = RefCell::new(RemoteData::Local($comments));
+            analyzer.analyze().await.unwrap()
+        }};
+    }
+
+    // Within the window, the merge is deferred with a warning.
+    let analysis = analyze!(1, Vec::new());
+    assert!(!analysis.actions.merge);
+    assert_eq!(analysis.block_reasons.len(), 1);
+    assert!(
+        analysis.block_reasons[0].starts_with(r#"SoftBlocked { label: "risky""#),
+        "{:?}",
+        analysis.block_reasons
+    );
+    assert_eq!(analysis.actions.post_comment.len(), 1);
+    assert!(analysis.actions.post_comment[0]
+        .starts_with("### Merge deferred\nThis PR has the `risky` label, so I'll wait until "));
+
+    // The warning is only posted once.
+    let warning = comment(
+        1,
+        "bot",
+        "### Merge deferred\nThis PR has the `risky` label",
+    );
+    let analysis = analyze!(1, vec![warning.clone()]);
+    assert!(!analysis.actions.merge);
+    assert!(analysis.actions.post_comment.is_empty());
+
+    // After the window, the PR is merged, even though it has been quiet.
+    let analysis = analyze!(25, vec![warning]);
+    assert!(analysis.actions.merge);
+    assert!(analysis.block_reasons.is_empty());
+}
+
+#[tokio::test]
+async fn merge_when_green() {
+    macro_rules! assert_merge {
+        ($comments:expr, $merge:expr) => {{
+            let (mut pr, client, config) = make_context();
+            pr.updated_at = Some(Utc::now() - Duration::seconds(5));
+            *client.bot_nick.borrow_mut() = Some("bot".to_owned());
+            client
+                .collaborators
+                .borrow_mut()
+                .insert(("the-project".to_owned(), "maintainer".to_owned()), true);
+            client
+                .collaborators
+                .borrow_mut()
+                .insert(("the-project".to_owned(), "bot".to_owned()), true);
+            client
+                .collaborators
+                .borrow_mut()
+                .insert(("the-project".to_owned(), "outsider".to_owned()), false);
+            let mut analyzer = make_analyzer(&pr, &client, &config);
+            analyzer.comments = RefCell::new(RemoteData::Local($comments));
+            assert_eq!(analyzer.required_actions().await.unwrap().merge, $merge);
+        }};
+    }
+
+    // Inside the grace period.
+    assert_merge!(vec![], false);
+    assert_merge!(vec![comment(1, "maintainer", "LGTM")], false);
+
+    // Pre-authorized by someone with write access.
+    assert_merge!(
+        vec![comment(1, "maintainer", "/octobors merge-when-green")],
+        true
+    );
+    assert_merge!(
+        vec![comment(1, "outsider", "/octobors merge-when-green")],
+        false
+    );
+
+    // The bot quoting the command, e.g. in its help, doesn't count.
+    assert_merge!(vec![comment(1, "bot", "/octobors merge-when-green")], false);
+}
+
+#[tokio::test]
+async fn path_labels() {
+    let (pr, client, mut config) = make_context();
+    config.path_labels = vec![
+        context::PathLabel {
+            glob: "src/api/**".to_string(),
+            label: "area/api".to_string(),
+        },
+        context::PathLabel {
+            glob: "src/ui/**".to_string(),
+            label: "area/ui".to_string(),
+        },
+    ];
+    let mut analyzer = make_analyzer(&pr, &client, &config);
+    analyzer.changed_files = RefCell::new(RemoteData::Local(vec![
+        changed_file("src/api/users.rs", 10, 2),
+        changed_file("README.md", 1, 1),
+    ]));
+    let actions = analyzer.required_actions().await.unwrap();
+    assert!(actions.add_labels.contains("area/api"));
+    assert!(!actions.add_labels.contains("area/ui"));
+    assert!(!actions.remove_labels.contains("area/ui"));
+}
+
+#[tokio::test]
+async fn sensitive_paths() {
+    let (pr, client, mut config) = make_context();
+    config.sensitive_paths = vec![context::SensitivePath {
+        glob: "infra/**".to_string(),
+        extra_approvals: 2,
+    }];
+    let merges = |files: Vec<ChangedFile>, reviews: Vec<Review>| {
+        let mut analyzer = make_analyzer(&pr, &client, &config);
+        analyzer.changed_files = RefCell::new(RemoteData::Local(files));
+        analyzer.reviews = RefCell::new(RemoteData::Local(reviews));
+        async move { analyzer.analyze().await.unwrap() }
+    };
+
+    let one_approval = || vec![review("1", ReviewState::Approved)];
+    let analysis = merges(vec![changed_file("src/lib.rs", 1, 1)], one_approval()).await;
+    assert!(analysis.actions.merge);
+
+    let analysis = merges(vec![changed_file("infra/main.tf", 1, 1)], one_approval()).await;
+    assert!(!analysis.actions.merge);
+    assert_eq!(
+        analysis.block_reasons,
+        vec!["MissingReviewApproval { from_users: [] }"]
+    );
+
+    let two_approvals = vec![
+        review("1", ReviewState::Approved),
+        review("2", ReviewState::Approved),
+    ];
+    let analysis = merges(vec![changed_file("infra/main.tf", 1, 1)], two_approvals).await;
+    assert!(analysis.actions.merge);
+}
+
+#[tokio::test]
+async fn size_approvals() {
+    let (pr, client, mut config) = make_context();
+    config.size_approvals = vec![
+        context::SizeApprovals {
+            min_changes: 100,
+            approvals: 2,
+        },
+        context::SizeApprovals {
+            min_changes: 500,
+            approvals: 3,
+        },
+    ];
+    async fn merges(
+        (pr, client): (&Pr, &context::Client),
+        config: &context::RepoConfig,
+        changes: u64,
+        approvals: usize,
+    ) -> bool {
+        let mut analyzer = make_analyzer(pr, client, config);
+        let files = vec![changed_file("src/lib.rs", changes, 0)];
+        analyzer.changed_files = RefCell::new(RemoteData::Local(files));
+        let reviews = (0..approvals)
+            .map(|i| review(&i.to_string(), ReviewState::Approved))
+            .collect();
+        analyzer.reviews = RefCell::new(RemoteData::Local(reviews));
+        analyzer.analyze().await.unwrap().actions.merge
+    }
+    let ctx = (&pr, &client);
+
+    assert!(merges(ctx, &config, 99, 1).await);
+    assert!(!merges(ctx, &config, 100, 1).await);
+    assert!(merges(ctx, &config, 100, 2).await);
+    assert!(merges(ctx, &config, 499, 2).await);
+    assert!(!merges(ctx, &config, 500, 2).await);
+    assert!(merges(ctx, &config, 500, 3).await);
+
+    // The highest requirement wins when sensitive paths are changed too.
+    config.sensitive_paths = vec![context::SensitivePath {
+        glob: "src/**".to_string(),
+        extra_approvals: 4,
+    }];
+    assert!(!merges(ctx, &config, 501, 3).await);
+    assert!(merges(ctx, &config, 501, 4).await);
+}
+
+#[test]
+fn unicode_label_names() {
+    let composed = "caf\u{e9}".to_owned();
+    let decomposed = "cafe\u{301}".to_owned();
+    assert_ne!(composed, decomposed);
+
+    let labels = vec!["trivial :)".to_owned(), composed.clone()];
+    assert_eq!(has_label(&labels, "trivial :)"), Some(0));
+    assert_eq!(has_label(&labels, &composed), Some(1));
+    assert_eq!(has_label(&labels, &decomposed), Some(1));
+    assert_eq!(has_label(&[decomposed], &composed), Some(0));
+    assert_eq!(has_label(&labels, "cafe"), None);
+    assert_eq!(has_label(&labels, "\u{1f680} ship it"), None);
+}
+
+#[tokio::test]
+async fn unicode_label_operations() {
+    let server = MockServer::new(vec![("200 OK", "[]")]);
+    let client = test_utils::client(&server);
+    let mut labels = vec!["caf\u{e9}".to_owned(), "\u{1f680}".to_owned()];
+
+    // Already present, with a different composition.
+    add_labels(&client, "repo", 1, &mut labels, vec!["cafe\u{301}"])
+        .await
+        .unwrap();
+    assert_eq!(labels.len(), 2);
+
+    // Removed as named on the PR.
+    remove_labels(&client, "repo", 1, &mut labels, vec!["cafe\u{301}"])
+        .await
+        .unwrap();
+    assert_eq!(labels, vec!["\u{1f680}"]);
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(
+        requests[0].line,
+        "DELETE /repos/org/repo/issues/1/labels/caf%C3%A9"
+    );
+}
+
+#[tokio::test]
+async fn unicode_pr_labels() {
+    let (mut pr, client, mut config) = make_context();
+    let composed = |name: &str| format!("{name} caf\u{e9}");
+    pr.labels = ["block", "trivial", "e2e", "soft"]
+        .iter()
+        .map(|name| format!("{name} cafe\u{301}"))
+        .collect();
+    config.block_merge_label = Some(composed("block"));
+    config.skip_review_label = Some(composed("trivial"));
+    config.conditional_required_statuses = vec![context::LabelStatuses {
+        label: composed("e2e"),
+        statuses: vec!["e2e".to_owned()],
+    }];
+    config.soft_block_labels = vec![composed("soft")];
+
+    let analyzer = make_analyzer(&pr, &client, &config);
+    assert_eq!(
+        analyzer.resolve_merge_eligibility(&pr.labels),
+        Eligibility::Blocked
+    );
+    assert!(!analyzer.requires_reviews());
+    assert!(analyzer.required_statuses().contains(&"e2e".to_owned()));
+    assert_eq!(analyzer.soft_block_label(), Some(&composed("soft")));
+}
+
+#[tokio::test]
+async fn superseded_check_runs() {
+    let (pr, client, config) = make_context();
+    let check_run = |conclusion: &str, minutes_ago| CheckRun {
+        name: "status1".to_owned(),
+        conclusion: Some(conclusion.to_owned()),
+        started_at: Some(Utc::now() - Duration::minutes(minutes_ago)),
+        completed_at: None,
+    };
+    let merges = |check_runs: Vec<CheckRun>| {
+        let mut analyzer = make_analyzer(&pr, &client, &config);
+        analyzer.statuses = RemoteData::Local(HashMap::new());
+        analyzer.check_runs = RemoteData::Local(check_runs);
+        async move { analyzer.required_actions().await.unwrap().merge }
+    };
+
+    // The re-run wins, whichever order the check runs are listed in.
+    assert!(merges(vec![check_run("failure", 20), check_run("success", 5)]).await);
+    assert!(merges(vec![check_run("success", 5), check_run("failure", 20)]).await);
+    assert!(!merges(vec![check_run("success", 20), check_run("failure", 5)]).await);
+}
+
+#[tokio::test]
+async fn require_at_least_one_check() {
+    let (pr, client, mut config) = make_context();
+    config.required_statuses = Vec::new();
+    config.require_at_least_one_check = true;
+    let analyze = |statuses: Vec<&str>, check_runs: Vec<&str>| {
+        let mut analyzer = make_analyzer(&pr, &client, &config);
+        analyzer.statuses = RemoteData::Local(
+            statuses
+                .into_iter()
+                .map(|name| (name.to_owned(), status(StatusState::Success)))
+                .collect(),
+        );
+        analyzer.check_runs = RemoteData::Local(
+            check_runs
+                .into_iter()
+                .map(|name| CheckRun {
+                    name: name.to_owned(),
+                    conclusion: Some("success".to_owned()),
+                    started_at: None,
+                    completed_at: None,
+                })
+                .collect(),
+        );
+        async move { analyzer.analyze().await.unwrap() }
+    };
+
+    let analysis = analyze(vec![], vec![]).await;
+    assert!(!analysis.actions.merge);
+    assert_eq!(analysis.block_reasons, vec!["NoChecksRan"]);
+
+    // The bot's own check run doesn't count.
+    let analysis = analyze(vec![], vec![context::CHECK_RUN_NAME]).await;
+    assert_eq!(analysis.block_reasons, vec!["NoChecksRan"]);
+
+    assert!(analyze(vec!["lint"], vec![]).await.actions.merge);
+    assert!(analyze(vec![], vec!["build"]).await.actions.merge);
+}
+
+#[tokio::test]
+async fn require_base_green() {
+    let server = MockServer::new(vec![
+        ("200 OK", r#"{"state": "success", "total_count": 2}"#),
+        ("200 OK", r#"{"state": "failure", "total_count": 2}"#),
+    ]);
+    let client = test_utils::client(&server);
+    let (mut pr, _, mut config) = make_context();
+    config.require_base_green = true;
+
+    let analyzer = make_analyzer(&pr, &client, &config);
+    assert!(analyzer.required_actions().await.unwrap().merge);
+
+    pr.base_ref = "release/1.0".to_owned();
+    let analysis = make_analyzer(&pr, &client, &config)
+        .analyze()
+        .await
+        .unwrap();
+    assert!(!analysis.actions.merge);
+    assert_eq!(analysis.block_reasons, vec!["BaseBranchRed"]);
+
+    // The status of each branch was cached.
+    let analysis = make_analyzer(&pr, &client, &config)
+        .analyze()
+        .await
+        .unwrap();
+    assert_eq!(analysis.block_reasons, vec!["BaseBranchRed"]);
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(
+        requests[0].line,
+        "GET /repos/org/the-project/commits/main/status"
+    );
+    assert_eq!(
+        requests[1].line,
+        "GET /repos/org/the-project/commits/release/1.0/status"
+    );
+}
+
+#[tokio::test]
+async fn require_cross_team_approval() {
+    let server = MockServer::new(vec![
+        ("200 OK", r#"[{"login": "author"}, {"login": "2"}]"#),
+        ("200 OK", r#"[{"login": "3"}]"#),
+    ]);
+    let client = test_utils::client(&server);
+    let (mut pr, _, mut config) = make_context();
+    config.require_cross_team_approval = true;
+    config.teams = vec!["engine".to_owned(), "tools".to_owned()];
+
+    // Only a teammate of the author approved.
+    let analysis = make_analyzer(&pr, &client, &config)
+        .analyze()
+        .await
+        .unwrap();
+    assert!(!analysis.actions.merge);
+    assert_eq!(analysis.block_reasons, vec!["MissingCrossTeamApproval"]);
+
+    let mut analyzer = make_analyzer(&pr, &client, &config);
+    analyzer.reviews = RefCell::new(RemoteData::Local(vec![
+        review("2", ReviewState::Approved),
+        review("3", ReviewState::Approved),
+    ]));
+    assert!(analyzer.required_actions().await.unwrap().merge);
+
+    // Authors outside of the teams can be approved by anyone.
+    pr.author = "newcomer".to_owned();
+    let analyzer = make_analyzer(&pr, &client, &config);
+    assert!(analyzer.required_actions().await.unwrap().merge);
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(
+        requests[0].line,
+        "GET /orgs/org/teams/engine/members?per_page=100"
+    );
+    assert_eq!(
+        requests[1].line,
+        "GET /orgs/org/teams/tools/members?per_page=100"
+    );
+}
+
+#[test]
+fn linked_pr_references() {
+    let linked = |owner: &str, repo: &str, number: u64| LinkedPr {
+        owner: owner.to_owned(),
+        repo: repo.to_owned(),
+        number,
+    };
+    let body = "Needs the new API.\n\
+                octobors-wait-for: org/api#12\n  \
+                octobors-wait-for:other/client#3  \n\
+                octobors-wait-for: #4\n\
+                octobors-wait-for: org/api#next";
+    assert_eq!(
+        linked_prs(body, "octobors-wait-for:"),
+        vec![linked("org", "api", 12), linked("other", "client", 3)]
+    );
+    assert_eq!(linked("org", "api", 12).to_string(), "org/api#12");
+    assert!(linked_prs(body, "depends-on:").is_empty());
+}
+
+#[tokio::test]
+async fn linked_prs_block_merge() {
+    let server = MockServer::new(vec![
+        (
+            "200 OK",
+            r#"{
+                "url": "https://api.github.com/repos/other/api/pulls/12",
+                "id": 1,
+                "number": 12,
+                "state": "open",
+                "head": { "ref": "feature", "sha": "somesha" },
+                "base": { "ref": "main", "sha": "othersha" }
+            }"#,
+        ),
+        (
+            "200 OK",
+            r#"{
+                "url": "https://api.github.com/repos/other/api/pulls/12",
+                "id": 1,
+                "number": 12,
+                "state": "closed",
+                "merged_at": "2024-01-01T00:00:00Z",
+                "head": { "ref": "feature", "sha": "somesha" },
+                "base": { "ref": "main", "sha": "othersha" }
+            }"#,
+        ),
+    ]);
+    let client = test_utils::client(&server);
+    let (mut pr, _, mut config) = make_context();
+    config.linked_pr_marker = Some("octobors-wait-for:".to_owned());
+    pr.body = "Together with the API change.\noctobors-wait-for: other/api#12".to_owned();
+
+    // The linked PR is still open.
+    let analysis = make_analyzer(&pr, &client, &config)
+        .analyze()
+        .await
+        .unwrap();
+    assert!(!analysis.actions.merge);
+    assert_eq!(
+        analysis.block_reasons,
+        vec![r#"WaitingOnLinkedPr { prs: ["other/api#12"] }"#]
+    );
+
+    // The linked PR was merged.
+    let analysis = make_analyzer(&pr, &client, &config)
+        .analyze()
+        .await
+        .unwrap();
+    assert!(analysis.actions.merge);
+
+    let requests = server.requests();
+    assert_eq!(requests[0].line, "GET /repos/other/api/pulls/12");
+}
+
+#[tokio::test]
+async fn default_branch_only() {
+    let server = MockServer::new(vec![(
+        "200 OK",
+        r#"{
+            "id": 1,
+            "name": "the-project",
+            "url": "https://api.github.com/repos/org/the-project",
+            "default_branch": "main"
+        }"#,
+    )]);
+    let client = test_utils::client(&server);
+    let (mut pr, _, mut config) = make_context();
+    config.default_branch_only = true;
+
+    let analyzer = make_analyzer(&pr, &client, &config);
+    assert!(analyzer.required_actions().await.unwrap().merge);
+
+    // The default branch was cached.
+    pr.base_ref = "develop".to_owned();
+    let analyzer = make_analyzer(&pr, &client, &config);
+    let analysis = analyzer.analyze().await.unwrap();
+    assert!(!analysis.actions.merge);
+    assert_eq!(analysis.block_reasons, vec!["DisallowedBaseBranch"]);
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].line, "GET /repos/org/the-project");
+}
+
+#[test]
+fn check_run_verdicts() {
+    let check_run = |conclusion: Option<&str>| CheckRun {
+        name: "build".to_owned(),
+        conclusion: conclusion.map(str::to_owned),
+        started_at: None,
+        completed_at: None,
+    };
+    let state = |conclusion, map: &HashMap<String, context::Verdict>| {
+        check_run(conclusion).status(map).map(|status| status.state)
+    };
+
+    let defaults = HashMap::new();
+    assert_eq!(state(None, &defaults), Some(StatusState::Pending));
+    assert_eq!(
+        state(Some("success"), &defaults),
+        Some(StatusState::Success)
+    );
+    assert_eq!(
+        state(Some("skipped"), &defaults),
+        Some(StatusState::Success)
+    );
+    assert_eq!(state(Some("stale"), &defaults), Some(StatusState::Pending));
+    assert_eq!(
+        state(Some("cancelled"), &defaults),
+        Some(StatusState::Failure)
+    );
+    assert_eq!(
+        state(Some("timed_out"), &defaults),
+        Some(StatusState::Failure)
+    );
+
+    let custom: HashMap<_, _> = vec![
+        ("cancelled".to_owned(), context::Verdict::Pending),
+        ("skipped".to_owned(), context::Verdict::Ignore),
+    ]
+    .into_iter()
+    .collect();
+    assert_eq!(
+        state(Some("cancelled"), &custom),
+        Some(StatusState::Pending)
+    );
+    assert_eq!(state(Some("skipped"), &custom), None);
+    assert_eq!(
+        state(Some("timed_out"), &custom),
+        Some(StatusState::Failure)
+    );
+}
+
+#[tokio::test]
+async fn conclusion_map() {
+    macro_rules! assert_merge {
+        ($conclusion_map:expr, $conclusion:expr, $merge:expr) => {{
+            let (pr, client, mut config) = make_context();
+            config.conclusion_map = $conclusion_map.into_iter().collect();
+            let mut analyzer = make_analyzer(&pr, &client, &config);
+            analyzer.statuses = RemoteData::Local(HashMap::new());
+            analyzer.check_runs = RemoteData::Local(vec![CheckRun {
+                name: "status1".to_owned(),
+                conclusion: Some($conclusion.to_owned()),
+                started_at: None,
+                completed_at: None,
+            }]);
+            assert_eq!(analyzer.required_actions().await.unwrap().merge, $merge);
+        }};
+    }
+
+    // Check runs count as required statuses.
+    assert_merge!(vec![], "success", true);
+    assert_merge!(vec![], "failure", false);
+
+    // Cancelled runs fail by default, but can be considered differently.
+    assert_merge!(vec![], "cancelled", false);
+    assert_merge!(
+        vec![("cancelled".to_owned(), context::Verdict::Pass)],
+        "cancelled",
+        true
+    );
+    assert_merge!(
+        vec![("success".to_owned(), context::Verdict::Ignore)],
+        "success",
+        false
+    );
+}
+
+#[tokio::test]
+async fn merge_cooldown() {
+    macro_rules! assert_merge {
+        ($comments:expr, $merge:expr) => {{
+            let (pr, client, mut config) = make_context();
+            *client.bot_nick.borrow_mut() = Some("bot".to_owned());
+            config.merge_retry_cooldown_secs = Some(600);
+            let mut analyzer = make_analyzer(&pr, &client, &config);
+            analyzer.comments = RefCell::new(RemoteData::Local($comments));
+            assert_eq!(analyzer.required_actions().await.unwrap().merge, $merge);
+        }};
+    }
+    let failure = |author: &str, sha: &str, minutes_ago: i64| {
+        let mut comment = comment(1, author, &merge_failure_comment(sha, "conflicts"));
+        comment.updated_at = Utc::now() - Duration::minutes(minutes_ago);
+        comment
+    };
+
+    assert_merge!(vec![], true);
+
+    // Failed recently.
+    assert_merge!(vec![failure("bot", "somesha", 5)], false);
+
+    // Failed a while ago, or for a previous commit.
+    assert_merge!(vec![failure("bot", "somesha", 15)], true);
+    assert_merge!(vec![failure("bot", "othersha", 5)], true);
+
+    // Only the bot records failures.
+    assert_merge!(vec![failure("someone", "somesha", 5)], true);
+}
+
+#[test]
+fn merge_forbidden_comments() {
+    let body = merge_forbidden_comment("Resource not accessible by integration");
+    assert!(body.contains("someone will have to do it"));
+    assert!(body.contains("Resource not accessible by integration"));
+
+    assert!(!has_merge_forbidden_comment(&[], "bot"));
+    assert!(has_merge_forbidden_comment(
+        &[comment(1, "bot", &body)],
+        "bot"
+    ));
+    assert!(!has_merge_forbidden_comment(
+        &[comment(1, "someone", &body)],
+        "bot"
+    ));
+    assert!(!has_merge_forbidden_comment(
+        &[comment(
+            1,
+            "bot",
+            &merge_failure_comment("sha", "conflicts")
+        )],
+        "bot"
+    ));
+}
+
+#[tokio::test]
+async fn block_via_review() {
+    macro_rules! assert_review {
+        ($has_description:expr, $bot_reviews:expr, $request_changes:expr, $update:expr, $dismiss:expr) => {{
+            let (mut pr, client, mut config) = make_context();
+            *client.bot_nick.borrow_mut() = Some("bot".to_owned());
+            config.block_via_review = true;
+            pr.has_description = $has_description;
+            let mut analyzer = make_analyzer(&pr, &client, &config);
+            let bot_reviews: Vec<(u64, ReviewState, &str)> = $bot_reviews;
+            if let RemoteData::Local(reviews) = analyzer.reviews.get_mut() {
+                for (id, state, body) in bot_reviews {
+                    reviews.push(Review {
+                        id,
+                        body: body.to_owned(),
+                        ..review("bot", state)
+                    });
+                }
+            }
+            let actions = analyzer.required_actions().await.unwrap();
+            let request_changes: Option<&str> = $request_changes;
+            assert_eq!(actions.request_changes.as_deref(), request_changes);
+            let update: Option<(u64, &str)> = $update;
+            assert_eq!(
+                actions
+                    .update_review
+                    .as_ref()
+                    .map(|(id, body)| (*id, body.as_str())),
+                update
+            );
+            let dismiss: Vec<u64> = $dismiss;
+            assert_eq!(actions.dismiss_reviews, dismiss);
+            assert_eq!(actions.merge, $has_description);
+        }};
+    }
+
+    const BLOCKED: &str = "- This PR lacks a description.\n";
+
+    // Blocked: request changes, once.
+    assert_review!(false, vec![], Some(BLOCKED), None, vec![]);
+    assert_review!(
+        false,
+        vec![(7, ReviewState::ChangesRequested, BLOCKED)],
+        None,
+        None,
+        vec![]
+    );
+    assert_review!(
+        false,
+        vec![(7, ReviewState::Dismissed, BLOCKED)],
+        Some(BLOCKED),
+        None,
+        vec![]
+    );
+
+    // Still blocked, but for other reasons: the review is updated.
+    assert_review!(
+        false,
+        vec![(7, ReviewState::ChangesRequested, "- CI is red.\n")],
+        None,
+        Some((7, BLOCKED)),
+        vec![]
+    );
+
+    // Mergeable, despite the bot's own review: dismiss it.
+    assert_review!(true, vec![], None, None, vec![]);
+    assert_review!(
+        true,
+        vec![(7, ReviewState::ChangesRequested, BLOCKED)],
+        None,
+        None,
+        vec![7]
+    );
+    assert_review!(
+        true,
+        vec![(7, ReviewState::Dismissed, BLOCKED)],
+        None,
+        None,
+        vec![]
+    );
+}
+
+#[tokio::test]
+async fn bot_reviews_do_not_count() {
+    let (pr, client, config) = make_context();
+    *client.bot_nick.borrow_mut() = Some("bot".to_owned());
+    let mut analyzer = make_analyzer(&pr, &client, &config);
+    analyzer.reviews = RefCell::new(RemoteData::Local(vec![review(
+        "bot",
+        ReviewState::Approved,
+    )]));
+    let analysis = analyzer.analyze().await.unwrap();
+    assert_eq!(
+        analysis.block_reasons,
+        vec!["MissingReviewApproval { from_users: [] }"]
+    );
+}
+
+#[test]
+fn audit_actions() {
+    let mut actions = Actions::noop();
+    actions
+        .set_merge(true)
+        .set_update_branch(true)
+        .set_label("reviewed", Presence::Present)
+        .set_label("needs-description", Presence::Absent)
+        .set_mark_ready(true)
+        .post_comment("Hello".to_string())
+        .update_comment(1, "Hi".to_string())
+        .set_check_run(CheckConclusion::Success, ALL_GOOD.to_string())
+        .request_changes("Blocked".to_string())
+        .update_review(8, "Still blocked".to_string())
+        .dismiss_review(7)
+        .set_reaction(Reaction::Blocked);
+    actions.request_reviewers.push("alice".to_string());
+
+    // Every feedback action is kept.
+    let mut expected = Actions::noop();
+    expected
+        .post_comment("Hello".to_string())
+        .update_comment(1, "Hi".to_string())
+        .set_check_run(CheckConclusion::Success, ALL_GOOD.to_string())
+        .request_changes("Blocked".to_string())
+        .update_review(8, "Still blocked".to_string())
+        .dismiss_review(7)
+        .set_reaction(Reaction::Blocked);
+
+    assert_eq!(actions.audit(), expected);
+}
+
+#[test]
+fn identical_comment() {
+    // Posted 99, 98 and 97 minutes ago.
+    let comments = vec![
+        comment(1, "bot", "Hello"),
+        comment(2, "someone", "Hi"),
+        comment(3, "bot", "Hi\n"),
+    ];
+    let find = |author: &str, body: &str, minutes_ago: i64| {
+        find_identical_comment(
+            &comments,
+            author,
+            body,
+            Utc::now() - Duration::minutes(minutes_ago),
+        )
+        .map(|comment| comment.id)
+    };
+
+    assert_eq!(find("bot", "Hi", 120), Some(3));
+    assert_eq!(find("bot", "Hello", 120), Some(1));
+    assert_eq!(find("bot", "Hey", 120), None);
+    // Too old.
+    assert_eq!(find("bot", "Hello", 60), None);
+    // Not from the bot.
+    assert_eq!(find("someone", "Hello", 120), None);
+}
+
+fn changed_file(path: &str, additions: u64, deletions: u64) -> ChangedFile {
+    ChangedFile {
+        path: path.to_owned(),
+        additions,
+        deletions,
+    }
+}
+
+#[tokio::test]
+async fn changed_files_are_fetched_once() {
+    let server = MockServer::new(vec![(
+        "200 OK",
+        r#"[{
+            "sha": "abc",
+            "filename": "src/lib.rs",
+            "status": "modified",
+            "additions": 10,
+            "deletions": 2,
+            "changes": 12,
+            "blob_url": null,
+            "raw_url": null,
+            "contents_url": "https://api.github.com/repos/org/the-project/contents/src/lib.rs"
+        }]"#,
+    )]);
+    let client = test_utils::client(&server);
+    let (pr, _, config) = make_context();
+    let analyzer = Analyzer::new(&pr, &client, &config);
+    for _ in 0..2 {
+        let files = analyzer.get_pr_changed_files().await.unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path, "src/lib.rs");
+        assert_eq!((files[0].additions, files[0].deletions), (10, 2));
+    }
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].line, "GET /repos/org/the-project/pulls/1/files");
+
+    // Local data never hits the API.
+    let server = MockServer::new(vec![]);
+    let client = test_utils::client(&server);
+    let mut analyzer = Analyzer::new(&pr, &client, &config);
+    analyzer.changed_files = RefCell::new(RemoteData::Local(vec![changed_file("a.rs", 1, 0)]));
+    assert_eq!(analyzer.get_pr_changed_files().await.unwrap().len(), 1);
+    assert!(server.requests().is_empty());
+}
+
+#[tokio::test]
+async fn too_large() {
+    macro_rules! assert_too_large {
+        ($max_files:expr, $max_changes:expr, $too_large:expr) => {{
+            let (pr, client, mut config) = make_context();
+            config.max_changed_files = $max_files;
+            config.max_total_changes = $max_changes;
+            let analyzer = make_analyzer(&pr, &client, &config);
+            *analyzer.changed_files.borrow_mut() = RemoteData::Local(vec![
+                changed_file("src/lib.rs", 10, 5),
+                changed_file("src/main.rs", 3, 2),
+            ]);
+            let mut reasons = HashSet::new();
+            analyzer
+                .analyze_extended_checks(&mut reasons)
+                .await
+                .unwrap();
+            assert_eq!(reasons.contains(&BlockReason::TooLarge), $too_large);
+        }};
+    }
+
+    assert_too_large!(None, None, false);
+
+    assert_too_large!(Some(2), None, false);
+    assert_too_large!(Some(1), None, true);
+
+    assert_too_large!(None, Some(20), false);
+    assert_too_large!(None, Some(19), true);
+
+    assert_too_large!(Some(2), Some(19), true);
+}
+
+#[tokio::test]
+async fn check_run_conclusion() {
+    let (pr, client, config) = make_context();
+    let analyzer = make_analyzer(&pr, &client, &config);
+    let check_run = |reasons: Vec<BlockReason>| analyzer.check_run(&reasons.into_iter().collect());
+
+    assert_eq!(
+        check_run(vec![]),
+        (CheckConclusion::Success, ALL_GOOD.to_owned())
+    );
+    assert_eq!(
+        check_run(vec![
+            BlockReason::CiNotPassing,
+            BlockReason::InsideGracePeriod
+        ]),
+        (
+            CheckConclusion::InProgress,
+            "- Github checks haven't passed yet.\n\
+             - In grace period; I'll retry in a bit.\n"
+                .to_owned()
+        )
+    );
+    assert_eq!(
+        check_run(vec![BlockReason::CiNotPassing, BlockReason::TooLarge]),
+        (
+            CheckConclusion::ActionRequired,
+            "- Github checks haven't passed yet.\n\
+             - This PR is large; please merge it manually.\n"
+                .to_owned()
+        )
+    );
+}
+
+#[tokio::test]
+async fn publish_check_run() {
+    let (pr, client, mut config) = make_context();
+    let analyzer = make_analyzer(&pr, &client, &config);
+    assert_eq!(analyzer.required_actions().await.unwrap().check_run, None);
+
+    config.publish_check_run = true;
+    let analyzer = make_analyzer(&pr, &client, &config);
+    let actions = analyzer.required_actions().await.unwrap();
+    assert!(actions.merge);
+    assert_eq!(
+        actions.check_run,
+        Some((CheckConclusion::Success, ALL_GOOD.to_owned()))
+    );
+}
+
+#[tokio::test]
+async fn require_up_to_date() {
+    macro_rules! assert_actions {
+        ($require:expr, $auto_update:expr, $behind:expr, $merge:expr, $update:expr) => {{
+            let (pr, client, mut config) = make_context();
+            config.require_up_to_date = $require;
+            config.auto_update_branch = $auto_update;
+            let mut analyzer = make_analyzer(&pr, &client, &config);
+            analyzer.commits_behind = RemoteData::Local($behind);
+            let actions = analyzer.required_actions().await.unwrap();
+            assert_eq!(actions.merge, $merge);
+            assert_eq!(actions.update_branch, $update);
+        }};
+    }
+
+    // Not required.
+    assert_actions!(false, false, 3, true, false);
+
+    // Up to date.
+    assert_actions!(true, true, 0, true, false);
+
+    // Behind.
+    assert_actions!(true, false, 3, false, false);
+    assert_actions!(true, true, 3, false, true);
+}
+
+#[tokio::test]
+async fn respond_to_authors() {
+    macro_rules! assert_answered {
+        ($filter:expr, $author:expr, $answered:expr) => {{
+            let (mut pr, client, mut config) = make_context();
+            *client.bot_nick.borrow_mut() = Some("bot".to_owned());
+            client
+                .collaborators
+                .borrow_mut()
+                .insert(("the-project".to_owned(), "collaborator".to_owned()), true);
+            client
+                .collaborators
+                .borrow_mut()
+                .insert(("the-project".to_owned(), "drive-by".to_owned()), false);
+            config.react_to_comments = true;
+            config.respond_to_authors = $filter;
+            pr.has_description = false;
+            let mut analyzer = make_analyzer(&pr, &client, &config);
+            analyzer.comments =
+                RefCell::new(RemoteData::Local(vec![comment(1, $author, "@bot why?")]));
+            let actions = analyzer.required_actions().await.unwrap();
+            assert_eq!(actions.post_comment.len(), $answered as usize);
+        }};
+    }
+
+    // Anyone by default.
+    assert_answered!(None, "drive-by", true);
+
+    // Allowlisted users.
+    let users = || Some(context::AuthorFilter::Users(vec!["maintainer".to_owned()]));
+    assert_answered!(users(), "maintainer", true);
+    assert_answered!(users(), "drive-by", false);
+
+    // Collaborators only.
+    let collaborators = || {
+        Some(context::AuthorFilter::Collaborators(
+            context::Collaborators::Collaborators,
+        ))
+    };
+    assert_answered!(collaborators(), "collaborator", true);
+    assert_answered!(collaborators(), "drive-by", false);
+}
+
+#[tokio::test]
+async fn required_deployments() {
+    use context::DeploymentState;
+
+    macro_rules! assert_merge {
+        ($deployments:expr, $merge:expr) => {{
+            let (pr, client, mut config) = make_context();
+            config.required_deployments = vec!["preview".to_string()];
+            let mut analyzer = make_analyzer(&pr, &client, &config);
+            analyzer.deployments = RemoteData::Local(
+                $deployments
+                    .into_iter()
+                    .map(|(env, state): (&str, DeploymentState)| (env.to_string(), state))
+                    .collect(),
+            );
+            assert_eq!(analyzer.required_actions().await.unwrap().merge, $merge);
+        }};
+    }
+
+    assert_merge!(vec![("preview", DeploymentState::Success)], true);
+    assert_merge!(vec![("preview", DeploymentState::Failure)], false);
+    assert_merge!(vec![("preview", DeploymentState::InProgress)], false);
+
+    // Not deployed yet, or only to other environments.
+    assert_merge!(vec![], false);
+    assert_merge!(vec![("staging", DeploymentState::Success)], false);
+}
+
+#[test]
+fn pick_auto_assigned_reviewers() {
+    let members: Vec<_> = ["dave", "author", "bob", "carol", "alice"]
+        .iter()
+        .map(|user| user.to_string())
+        .collect();
+
+    // The author is never picked, and each PR starts with the next member.
+    assert_eq!(
+        pick_reviewers(&members, "author", 2, 0),
+        vec!["alice", "bob"]
+    );
+    assert_eq!(
+        pick_reviewers(&members, "author", 2, 1),
+        vec!["bob", "carol"]
+    );
+    assert_eq!(
+        pick_reviewers(&members, "author", 2, 3),
+        vec!["dave", "alice"]
+    );
+    assert_eq!(pick_reviewers(&members, "author", 1, 6), vec!["carol"]);
+
+    // No more than the team's other members.
+    assert_eq!(
+        pick_reviewers(&members, "author", 10, 0),
+        vec!["alice", "bob", "carol", "dave"]
+    );
+    assert!(pick_reviewers(&["author".to_string()], "author", 2, 0).is_empty());
+}
+
+#[tokio::test]
+async fn auto_assign_reviewers() {
+    let (mut pr, client, mut config) = make_context();
+    config.auto_assign_reviewers = Some(context::AutoAssignReviewers {
+        from_team: "reviewers".to_owned(),
+        count: 1,
+    });
+    client.team_members.borrow_mut().insert(
+        "reviewers".to_owned(),
+        vec!["author".to_owned(), "alice".to_owned(), "bob".to_owned()],
+    );
+    *client.bot_nick.borrow_mut() = Some("bot".to_owned());
+
+    // Already reviewed.
+    let analysis = make_analyzer(&pr, &client, &config)
+        .analyze()
+        .await
+        .unwrap();
+    assert!(analysis.actions.request_reviewers.is_empty());
+
+    let mut analyzer = make_analyzer(&pr, &client, &config);
+    analyzer.reviews = RefCell::new(RemoteData::Local(Vec::new()));
+    let analysis = analyzer.analyze().await.unwrap();
+    assert_eq!(analysis.actions.request_reviewers, vec!["bob"]);
+    assert!(analysis.actions.audit().request_reviewers.is_empty());
+
+    // The bot's own review doesn't count.
+    let mut analyzer = make_analyzer(&pr, &client, &config);
+    analyzer.reviews = RefCell::new(RemoteData::Local(vec![review(
+        "bot",
+        ReviewState::ChangesRequested,
+    )]));
+    let analysis = analyzer.analyze().await.unwrap();
+    assert_eq!(analysis.actions.request_reviewers, vec!["bob"]);
+
+    // The bot already assigned reviewers, whose request was removed since.
+    let mut analyzer = make_analyzer(&pr, &client, &config);
+    analyzer.reviews = RefCell::new(RemoteData::Local(Vec::new()));
+    analyzer.timeline = RefCell::new(RemoteData::Local(vec![
+        context::TimelineEvent {
+            actor: Some("bot".to_owned()),
+            ..review_requested("bob")
+        },
+        context::TimelineEvent {
+            requested_reviewer: Some("bob".to_owned()),
+            ..timeline_event(models::Event::ReviewRequestRemoved, 60)
+        },
+    ]));
+    let analysis = analyzer.analyze().await.unwrap();
+    assert!(analysis.actions.request_reviewers.is_empty());
+
+    // Reviewers were already requested.
+    pr.requested_reviewers_remaining = 1;
+    let mut analyzer = make_analyzer(&pr, &client, &config);
+    analyzer.reviews = RefCell::new(RemoteData::Local(Vec::new()));
+    let analysis = analyzer.analyze().await.unwrap();
+    assert!(analysis.actions.request_reviewers.is_empty());
+
+    // Drafts aren't ready for reviews.
+    pr.requested_reviewers_remaining = 0;
+    pr.draft = true;
+    let mut analyzer = make_analyzer(&pr, &client, &config);
+    analyzer.reviews = RefCell::new(RemoteData::Local(Vec::new()));
+    let analysis = analyzer.analyze().await.unwrap();
+    assert!(analysis.actions.request_reviewers.is_empty());
+}
+
+#[tokio::test]
+async fn required_check_missing() {
+    let (pr, client, mut config) = make_context();
+    config.required_statuses = vec!["status1".to_string(), "status3".to_string()];
+
+    // `status3` never ran, while `status1` succeeded.
+    let analysis = make_analyzer(&pr, &client, &config)
+        .analyze()
+        .await
+        .unwrap();
+    assert!(!analysis.actions.merge);
+    assert_eq!(
+        analysis.block_reasons,
+        vec!["RequiredCheckMissing { context: \"status3\" }"]
+    );
+    assert!(analysis.actions.remove_labels.contains("ci-passed"));
+
+    // `status3` is reported, but still pending.
+    let mut analyzer = make_analyzer(&pr, &client, &config);
+    analyzer.statuses = RemoteData::Local(
+        vec![
+            ("status1".to_string(), status(StatusState::Success)),
+            ("status3".to_string(), status(StatusState::Pending)),
+        ]
+        .into_iter()
+        .collect(),
+    );
+    let analysis = analyzer.analyze().await.unwrap();
+    assert!(!analysis.actions.merge);
+    assert_eq!(analysis.block_reasons, vec!["CiNotPassing"]);
+}
+
+fn activity(actor: &str, label_change: bool, seconds_ago: i64) -> context::TimelineEvent {
+    let event = if label_change {
+        models::Event::Labeled
+    } else {
+        models::Event::Committed
+    };
+    context::TimelineEvent {
+        actor: Some(actor.to_owned()),
+        ..timeline_event(event, seconds_ago)
+    }
+}
+
+#[test]
+fn timeline_derivations() {
+    let timeline = vec![
+        timeline_event(models::Event::ReadyForReview, 7200),
+        review_requested("alice"),
+        review_requested("bob"),
+        context::TimelineEvent {
+            requested_reviewer: Some("alice".to_owned()),
+            ..timeline_event(models::Event::ReviewRequestRemoved, 1800)
+        },
+        context::TimelineEvent {
+            label: Some("risky".to_owned()),
+            ..timeline_event(models::Event::Labeled, 600)
+        },
+        context::TimelineEvent {
+            label: Some("other".to_owned()),
+            ..timeline_event(models::Event::Labeled, 60)
+        },
+        timeline_event(models::Event::ReadyForReview, 300),
+    ];
+    assert_eq!(ready_for_review_at(&timeline), Some(timeline[6].at));
+    assert_eq!(requested_reviewers(&timeline), vec!["bob"]);
+    assert_eq!(
+        labeled_at(&timeline, &["risky".to_owned()]),
+        Some(timeline[4].at)
+    );
+    assert_eq!(labeled_at(&timeline, &["missing".to_owned()]), None);
+}
+
+#[tokio::test]
+async fn timeline_is_fetched_once() {
+    let server = MockServer::new(vec![("200 OK", "[]")]);
+    let client = test_utils::client(&server);
+    let (pr, _, config) = make_context();
+    let analyzer = Analyzer::new(&pr, &client, &config);
+    assert_eq!(analyzer.get_pr_ready_for_review_at().await.unwrap(), None);
+    assert!(analyzer
+        .get_pr_requested_reviewers()
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(analyzer.get_pr_labeled_at().await.unwrap(), None);
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn ignore_bot_label_activity() {
+    let (mut pr, client, mut config) = make_context();
+    *client.bot_nick.borrow_mut() = Some("bot".to_owned());
+    pr.updated_at = Some(Utc::now() - Duration::seconds(5));
+    let bot_labeled = vec![activity("author", false, 1800), activity("bot", true, 5)];
+
+    macro_rules! assert_in_grace_period {
+        ($activity:expr, $inside:expr) => {{
+            let mut analyzer = make_analyzer(&pr, &client, &config);
+            analyzer.timeline = RefCell::new(RemoteData::Local($activity));
+            let analysis = analyzer.analyze().await.unwrap();
+            assert_eq!(
+                analysis
+                    .block_reasons
+                    .contains(&"InsideGracePeriod".to_owned()),
+                $inside,
+                "{:?}",
+                analysis.block_reasons
+            );
+        }};
+    }
+
+    // The bot's label change counts as an update by default.
+    assert_in_grace_period!(bot_labeled.clone(), true);
+
+    config.ignore_bot_label_activity = true;
+    assert_in_grace_period!(bot_labeled.clone(), false);
+
+    // Label changes by others, or other events of the bot, are activity.
+    assert_in_grace_period!(
+        vec![activity("author", false, 1800), activity("author", true, 5)],
+        true
+    );
+    assert_in_grace_period!(
+        vec![activity("author", false, 1800), activity("bot", false, 5)],
+        true
+    );
+
+    // Only the bot's label change happened recently: the PR is inactive.
+    pr.created_at = Utc::now() - Duration::hours(3);
+    let commit = activity("author", false, 7200);
+    assert_eq!(
+        meaningful_activity(&pr, &[commit.clone(), activity("bot", true, 5)], "bot"),
+        commit.at
+    );
+    let mut analyzer = make_analyzer(&pr, &client, &config);
+    analyzer.timeline = RefCell::new(RemoteData::Local(vec![commit, activity("bot", true, 5)]));
+    let analysis = analyzer.analyze().await.unwrap();
+    assert_eq!(analysis.block_reasons, vec!["InactivePr"]);
+}
+
+#[tokio::test]
+async fn grace_period_jitter() {
+    let (mut pr, client, mut config) = make_context();
+    config.automerge_grace_period = Some(60);
+    config.grace_period_jitter_secs = Some(120);
+    pr.updated_at = Some(Utc::now() - Duration::seconds(90));
+
+    // The same PR always gets the same jitter, within the range.
+    let jitters: Vec<_> = (1..=20)
+        .map(|n| super::grace_period_jitter(n, 120))
+        .collect();
+    assert_eq!(
+        jitters,
+        (1..=20)
+            .map(|n| super::grace_period_jitter(n, 120))
+            .collect::<Vec<_>>()
+    );
+    assert!(jitters.iter().all(|jitter| *jitter <= 120), "{:?}", jitters);
+    assert_eq!(super::grace_period_jitter(1, 0), 0);
+
+    // PRs updated at the same time clear the grace period at different times.
+    let deadline = |number| {
+        let mut pr = pr.clone();
+        pr.number = number;
+        let analyzer = Analyzer::new(&pr, &client, &config);
+        pr.updated_at.unwrap() + analyzer.grace_period().unwrap()
+    };
+    assert_ne!(deadline(1), deadline(2));
+
+    // Without jitter, the grace period has elapsed.
+    let within_grace_period = |pr: &Pr, config: &context::RepoConfig| {
+        !Analyzer::new(pr, &client, config).outside_grace_period()
+    };
+    pr.number = (1..)
+        .find(|n| super::grace_period_jitter(*n, 120) > 60)
+        .unwrap();
+    assert!(within_grace_period(&pr, &config));
+    config.grace_period_jitter_secs = None;
+    assert!(!within_grace_period(&pr, &config));
+}
+
+#[tokio::test]
+async fn not_rebaseable() {
+    let (pr, offline_client, mut config) = make_context();
+
+    macro_rules! assert_reasons {
+        ($commits:expr, $rebaseable:expr, $expected:expr) => {{
+            let body = format!(
+                r#"{{
+                    "url": "https://api.github.com/repos/org/the-project/pulls/{}",
+                    "id": 1,
+                    "number": {},
+                    "commits": {},
+                    "rebaseable": {},
+                    "head": {{ "ref": "feature", "sha": "abc" }},
+                    "base": {{ "ref": "main", "sha": "def" }}
+                }}"#,
+                pr.number, pr.number, $commits, $rebaseable
+            );
+            let server = MockServer::new(vec![("200 OK", Box::leak(body.into_boxed_str()))]);
+            let client = test_utils::client(&server);
+            let mut analyzer = make_analyzer(&pr, &client, &config);
+            analyzer.rebaseable = RemoteData::Remote;
+            let analysis = analyzer.analyze().await.unwrap();
+            let expected: Vec<&str> = $expected;
+            assert_eq!(analysis.block_reasons, expected);
+            server.requests()
+        }};
+    }
+
+    let requests = assert_reasons!(2, true, vec![]);
+    assert_eq!(
+        requests[0].line,
+        format!("GET /repos/org/the-project/pulls/{}", pr.number)
+    );
+    // Unknown until Github computes it.
+    assert_reasons!(2, "null", vec![]);
+    assert_reasons!(2, false, vec!["NotRebaseable"]);
+
+    // Only matters when the PR is rebased, which may depend on its commits.
+    config.merge_method = context::MergeMethod::Squash;
+    config.squash_only_if_multiple_commits = true;
+    assert_reasons!(1, false, vec!["NotRebaseable"]);
+    assert_reasons!(2, false, vec![]);
+
+    // The PR isn't fetched when it's never rebased.
+    config.squash_only_if_multiple_commits = false;
+    let mut analyzer = make_analyzer(&pr, &offline_client, &config);
+    analyzer.rebaseable = RemoteData::Remote;
+    assert!(analyzer.analyze().await.unwrap().block_reasons.is_empty());
+}
+
+#[tokio::test]
+async fn grace_period_once_per_commit() {
+    let (mut pr, client, mut config) = make_context();
+    *client.bot_nick.borrow_mut() = Some("bot".to_owned());
+    config.automerge_grace_period = Some(600);
+    config.grace_period_once_per_commit = true;
+    // Just touched, e.g. by a label change.
+    pr.updated_at = Some(Utc::now() - Duration::seconds(60));
+    let ready_since = |sha: &str, minutes: i64| {
+        comment(
+            1,
+            "bot",
+            &ready_since_comment(sha, Utc::now() - Duration::minutes(minutes)),
+        )
+    };
+
+    macro_rules! analyze {
+        ($comments:expr) => {{
+            let mut analyzer = make_analyzer(&pr, &client, &config);
+            analyzer.comments = RefCell::new(RemoteData::Local($comments));
+            analyzer.analyze().await.unwrap()
+        }};
+    }
+
+    // Becoming ready is recorded.
+    let analysis = analyze!(vec![]);
+    assert!(!analysis.actions.merge);
+    assert_eq!(analysis.block_reasons, vec!["InsideGracePeriod"]);
+    assert_eq!(analysis.actions.post_comment.len(), 1);
+    let since = super::ready_since(&analysis.actions.post_comment[0], "somesha").unwrap();
+    assert!(Utc::now() - since < Duration::minutes(1));
+
+    // Ready for longer than the grace period.
+    let analysis = analyze!(vec![ready_since("somesha", 60)]);
+    assert!(analysis.actions.merge);
+    assert!(analysis.actions.post_comment.is_empty());
+    assert!(analysis.actions.update_comment.is_empty());
+
+    // Ready, but not for long enough.
+    let analysis = analyze!(vec![ready_since("somesha", 5)]);
+    assert!(!analysis.actions.merge);
+    assert!(analysis.actions.update_comment.is_empty());
+
+    // A new commit was pushed since: it's ready again from now on.
+    let analysis = analyze!(vec![ready_since("oldsha", 60)]);
+    assert!(!analysis.actions.merge);
+    assert_eq!(analysis.block_reasons, vec!["InsideGracePeriod"]);
+    let (id, body) = &analysis.actions.update_comment[0];
+    assert_eq!(*id, 1);
+    let since = super::ready_since(body, "somesha").unwrap();
+    assert!(Utc::now() - since < Duration::minutes(1));
+
+    // Not ready anymore, e.g. because a review requested changes.
+    let mut analyzer = make_analyzer(&pr, &client, &config);
+    analyzer.comments = RefCell::new(RemoteData::Local(vec![ready_since("somesha", 60)]));
+    analyzer.reviews = RefCell::new(RemoteData::Local(vec![review(
+        "2",
+        ReviewState::ChangesRequested,
+    )]));
+    let analysis = analyzer.analyze().await.unwrap();
+    assert!(!analysis.actions.merge);
+    let (id, body) = &analysis.actions.update_comment[0];
+    assert_eq!(*id, 1);
+    assert_eq!(super::ready_since(body, "somesha"), None);
+}
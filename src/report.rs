@@ -0,0 +1,117 @@
+//! A report of the PRs evaluated during a run, written as CSV for archival.
+
+use std::{fs::OpenOptions, io::Write as _, path::Path};
+
+use crate::process::Decision;
+use anyhow::{Context as _, Result};
+use chrono::{DateTime, Utc};
+
+const HEADER: &str = "repo,pr,author,decision,block_reasons,timestamp\n";
+
+/// What was decided for a PR.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub repo: String,
+    pub pr: u64,
+    pub author: String,
+    pub decision: Decision,
+    pub block_reasons: Vec<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Quotes a CSV field if needed.
+fn field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+/// The CSV rows of the entries, without the header. Block reasons are separated by `; `.
+fn rows(entries: &[Entry]) -> String {
+    entries
+        .iter()
+        .map(|entry| {
+            format!(
+                "{},{},{},{},{},{}\n",
+                field(&entry.repo),
+                entry.pr,
+                field(&entry.author),
+                entry.decision.as_str(),
+                field(&entry.block_reasons.join("; ")),
+                entry.timestamp.to_rfc3339(),
+            )
+        })
+        .collect()
+}
+
+/// Writes the report, replacing the previous one unless appending to it.
+pub fn write(path: &Path, entries: &[Entry], append: bool) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)
+        .with_context(|| format!("could not open the report {}", path.display()))?;
+    let empty = file.metadata()?.len() == 0;
+    let header = if empty { HEADER } else { "" };
+    file.write_all(format!("{header}{}", rows(entries)).as_bytes())
+        .with_context(|| format!("could not write the report {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(pr: u64, decision: Decision, block_reasons: &[&str]) -> Entry {
+        Entry {
+            repo: "repo".to_owned(),
+            pr,
+            author: "author".to_owned(),
+            decision,
+            block_reasons: block_reasons.iter().map(|r| r.to_string()).collect(),
+            timestamp: "2024-01-01T00:00:00Z".parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn report_contents() {
+        let path = std::env::temp_dir().join(format!("octobors-report-{}.csv", std::process::id()));
+        let first = [
+            entry(1, Decision::Merged, &[]),
+            entry(3, Decision::Ready, &[]),
+            entry(
+                2,
+                Decision::Blocked,
+                &[
+                    "CiNotPassing",
+                    r#"MissingReviewApproval { from_users: ["a", "b"] }"#,
+                ],
+            ),
+        ];
+        write(&path, &first, false).unwrap();
+        let expected = "repo,pr,author,decision,block_reasons,timestamp\n\
+            repo,1,author,merged,,2024-01-01T00:00:00+00:00\n\
+            repo,3,author,ready,,2024-01-01T00:00:00+00:00\n\
+            repo,2,author,blocked,\"CiNotPassing; MissingReviewApproval { from_users: [\"\"a\"\", \
+            \"\"b\"\"] }\",2024-01-01T00:00:00+00:00\n";
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), expected);
+
+        // Appending keeps the previous runs.
+        let second = [entry(3, Decision::Noop, &["DraftPr"])];
+        write(&path, &second, true).unwrap();
+        let appended = format!("{expected}repo,3,author,noop,DraftPr,2024-01-01T00:00:00+00:00\n");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), appended);
+
+        // Otherwise the report is replaced.
+        write(&path, &second, false).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "repo,pr,author,decision,block_reasons,timestamp\n\
+             repo,3,author,noop,DraftPr,2024-01-01T00:00:00+00:00\n"
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
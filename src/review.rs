@@ -1,17 +1,24 @@
+use chrono::{DateTime, Duration, Utc};
 use octocrab::models::pulls::ReviewState;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Debug, Clone)]
 pub struct Review {
+    pub id: u64,
     pub user_name: String,
     pub state: ReviewState,
+    pub submitted_at: Option<DateTime<Utc>>,
+    pub body: String,
 }
 
 impl Review {
     pub fn from_octocrab_review(review: &octocrab::models::pulls::Review) -> Option<Self> {
         Some(Self {
+            id: *review.id,
             user_name: review.user.clone().map(|u| u.login).unwrap_or_default(),
             state: review.state?,
+            submitted_at: review.submitted_at,
+            body: review.body.clone().unwrap_or_default(),
         })
     }
 }
@@ -20,6 +27,10 @@ impl Review {
 pub enum Status {
     Approved,
     ChangeRequested,
+    /// Changes requested by an advisory reviewer, which don't block.
+    AdvisoryChangeRequest,
+    /// Approved too long ago to count.
+    ApprovalExpired,
 }
 
 #[derive(Debug, Clone)]
@@ -33,6 +44,23 @@ pub struct Reviews {
 
     /// PR author's nickname.
     author: String,
+
+    /// How long approvals count for, if they expire.
+    approval_max_age: Option<Duration>,
+
+    /// How many approvals override each change request, if they can.
+    approvals_per_change_request: Option<usize>,
+
+    /// Reviewers whose change requests are only advisories.
+    advisory_reviewers: HashSet<String>,
+
+    /// Reviewers whose review was requested, whose comments can request changes.
+    requested_reviewers: HashSet<String>,
+
+    /// Reviewers who started a review they haven't submitted yet. It doesn't change their
+    /// latest submitted review. Github only lists the pending review of the authenticated
+    /// user, so this only sees reviews started with the bot's account.
+    in_progress: HashSet<String>,
 }
 
 pub enum Approval {
@@ -43,6 +71,8 @@ pub enum Approval {
 #[derive(Debug, Clone, Copy)]
 pub enum CommentEffect {
     RequestsChange,
+    /// Only comments from requested reviewers request changes.
+    RequestsChangeFromRequestedReviewers,
     Ignore,
 }
 
@@ -52,27 +82,130 @@ impl Reviews {
             review_by_nick: HashMap::new(),
             author: author.into(),
             comment_effect,
+            approval_max_age: None,
+            approvals_per_change_request: None,
+            advisory_reviewers: HashSet::new(),
+            requested_reviewers: HashSet::new(),
+            in_progress: HashSet::new(),
         }
     }
 
-    /// Check whether all the reviews are approving.
+    /// Makes approvals older than the given age not count.
+    pub fn with_approval_max_age(mut self, max_age: Duration) -> Self {
+        self.approval_max_age = Some(max_age);
+        self
+    }
+
+    /// Makes change requests not block when there are at least the given number of approvals for
+    /// each of them.
+    pub fn with_approvals_per_change_request(mut self, approvals: usize) -> Self {
+        self.approvals_per_change_request = Some(approvals);
+        self
+    }
+
+    /// Makes the change requests of the given reviewers advisories, which don't block.
+    pub fn with_advisory_reviewers(mut self, reviewers: impl IntoIterator<Item = String>) -> Self {
+        self.advisory_reviewers = reviewers.into_iter().collect();
+        self
+    }
+
+    /// Sets the reviewers whose review was requested, for
+    /// `CommentEffect::RequestsChangeFromRequestedReviewers`.
+    pub fn with_requested_reviewers(mut self, reviewers: impl IntoIterator<Item = String>) -> Self {
+        self.requested_reviewers = reviewers.into_iter().collect();
+        self
+    }
+
+    /// Check whether all the reviews are approving, or enough of them to override the change
+    /// requests.
     pub fn approved(&self, approval_required: Approval) -> bool {
-        let mut approved = matches!(approval_required, Approval::Optional);
+        let mut approvals = 0;
+        let mut change_requests = 0;
         for (user, review) in self.review_by_nick.iter() {
             tracing::info!(user = %user, review = ?review, "review");
             match review {
-                Status::Approved => approved = true,
-                Status::ChangeRequested => return false,
+                Status::Approved => approvals += 1,
+                Status::ChangeRequested => change_requests += 1,
+                Status::AdvisoryChangeRequest | Status::ApprovalExpired => {}
             }
         }
-        approved
+        if change_requests > 0 {
+            return match self.approvals_per_change_request {
+                Some(per_change_request) => {
+                    approvals > 0 && approvals >= change_requests * per_change_request
+                }
+                None => false,
+            };
+        }
+        approvals > 0 || matches!(approval_required, Approval::Optional)
+    }
+
+    /// How many people approved.
+    pub fn approvals(&self) -> usize {
+        self.review_by_nick
+            .values()
+            .filter(|status| **status == Status::Approved)
+            .count()
+    }
+
+    /// Returns the github names of the reviewers who approved, sorted.
+    pub fn approvers(&self) -> Vec<String> {
+        let mut users: Vec<_> = self
+            .review_by_nick
+            .iter()
+            .filter(|(_, status)| **status == Status::Approved)
+            .map(|(nick, _)| nick.clone())
+            .collect();
+        users.sort();
+        users
     }
 
     /// Returns a list of the github names of missing approvers.
     pub fn missing_approvals_from_users(&self) -> Vec<String> {
         self.review_by_nick
             .iter()
-            .filter_map(|(nick, status)| matches!(status, Status::ChangeRequested).then(|| nick))
+            .filter(|(_, status)| {
+                matches!(status, Status::ChangeRequested | Status::ApprovalExpired)
+            })
+            .map(|(nick, _)| nick.clone())
+            .collect()
+    }
+
+    /// Returns the github names of advisory reviewers who requested changes, sorted.
+    pub fn advisory_concerns_from_users(&self) -> Vec<String> {
+        let mut users: Vec<_> = self
+            .review_by_nick
+            .iter()
+            .filter(|(_, status)| **status == Status::AdvisoryChangeRequest)
+            .map(|(nick, _)| nick.clone())
+            .collect();
+        users.sort();
+        users
+    }
+
+    /// Returns the github names of the given reviewers who are in the middle of a review, sorted.
+    pub fn reviews_in_progress_from<'a>(
+        &self,
+        users: impl IntoIterator<Item = &'a String>,
+    ) -> Vec<String> {
+        let mut users: Vec<_> = users
+            .into_iter()
+            .filter(|user| self.in_progress.contains(*user))
+            .cloned()
+            .collect();
+        users.sort();
+        users.dedup();
+        users
+    }
+
+    /// Returns the given users who haven't approved.
+    pub fn missing_approvals_from<'a>(
+        &self,
+        users: impl IntoIterator<Item = &'a String>,
+    ) -> Vec<String> {
+        users
+            .into_iter()
+            .filter(|user| self.review_by_nick.get(*user) != Some(&Status::Approved))
             .cloned()
             .collect()
     }
@@ -92,10 +225,33 @@ impl Reviews {
             return;
         }
 
-        let status = match (review.state, self.comment_effect) {
-            (ReviewState::Approved, _) => Some(Status::Approved),
-            (ReviewState::ChangesRequested, _) => Some(Status::ChangeRequested),
-            (ReviewState::Commented, CommentEffect::RequestsChange) => {
+        if review.state == ReviewState::Pending {
+            self.in_progress.insert(review.user_name);
+            return;
+        }
+        self.in_progress.remove(&review.user_name);
+
+        let comment_requests_change = match self.comment_effect {
+            CommentEffect::RequestsChange => true,
+            CommentEffect::RequestsChangeFromRequestedReviewers => {
+                self.requested_reviewers.contains(&review.user_name)
+            }
+            CommentEffect::Ignore => false,
+        };
+        let status = match review.state {
+            ReviewState::Approved => {
+                let expired = match (self.approval_max_age, review.submitted_at) {
+                    (Some(max_age), Some(submitted_at)) => submitted_at < Utc::now() - max_age,
+                    _ => false,
+                };
+                if expired {
+                    Some(Status::ApprovalExpired)
+                } else {
+                    Some(Status::Approved)
+                }
+            }
+            ReviewState::ChangesRequested => Some(Status::ChangeRequested),
+            ReviewState::Commented if comment_requests_change => {
                 if let Some(Status::Approved) = self.review_by_nick.get(&review.user_name) {
                     // As a very special case, don't count comments that are newer than an approval
                     // review as request for changes.
@@ -107,6 +263,14 @@ impl Reviews {
             _ => None,
         };
 
+        let status = match status {
+            Some(Status::ChangeRequested)
+                if self.advisory_reviewers.contains(&review.user_name) =>
+            {
+                Some(Status::AdvisoryChangeRequest)
+            }
+            status => status,
+        };
         if let Some(status) = status {
             let _ = self.review_by_nick.insert(review.user_name, status);
         }
@@ -119,11 +283,199 @@ mod tests {
 
     fn review(name: &str, state: ReviewState) -> Review {
         Review {
+            id: 0,
             user_name: name.to_string(),
             state,
+            submitted_at: None,
+            body: String::new(),
         }
     }
 
+    #[test]
+    fn expired_approvals() {
+        let approval = |name: &str, days_ago: i64| Review {
+            submitted_at: Some(Utc::now() - Duration::days(days_ago)),
+            ..review(name, ReviewState::Approved)
+        };
+        let reviews = |list: Vec<Review>| {
+            Reviews::new("example", CommentEffect::Ignore)
+                .with_approval_max_age(Duration::days(7))
+                .record_reviews(list)
+        };
+
+        // Inside the window.
+        let recent = reviews(vec![approval("a", 2)]);
+        assert!(recent.approved(Approval::Required));
+        assert!(recent.missing_approvals_from_users().is_empty());
+
+        // Outside of it.
+        let old = reviews(vec![approval("a", 10)]);
+        assert!(!old.approved(Approval::Required));
+        assert!(old.approved(Approval::Optional));
+        assert_eq!(old.missing_approvals_from_users(), vec!["a"]);
+
+        // Approved again since.
+        let renewed = reviews(vec![approval("a", 10), approval("a", 1)]);
+        assert!(renewed.approved(Approval::Required));
+
+        // Someone else's recent approval is enough.
+        let other = reviews(vec![approval("a", 10), approval("b", 1)]);
+        assert!(other.approved(Approval::Required));
+    }
+
+    #[test]
+    fn overridden_change_requests() {
+        let reviews = |list: &[(&str, ReviewState)]| {
+            Reviews::new("example", CommentEffect::Ignore)
+                .with_approvals_per_change_request(2)
+                .record_reviews(
+                    list.iter()
+                        .map(|(name, state)| review(name, *state))
+                        .collect(),
+                )
+        };
+        use ReviewState::{Approved, ChangesRequested};
+
+        // Not enough approvals.
+        let one = reviews(&[("a", Approved), ("b", ChangesRequested)]);
+        assert!(!one.approved(Approval::Required));
+        assert!(!one.approved(Approval::Optional));
+
+        // Two approvals override one change request.
+        let two = reviews(&[("a", Approved), ("b", ChangesRequested), ("c", Approved)]);
+        assert!(two.approved(Approval::Required));
+        assert!(two.approved(Approval::Optional));
+
+        // But not two change requests.
+        let three = reviews(&[
+            ("a", Approved),
+            ("b", ChangesRequested),
+            ("c", Approved),
+            ("d", ChangesRequested),
+            ("e", Approved),
+        ]);
+        assert!(!three.approved(Approval::Required));
+        let four = reviews(&[
+            ("a", Approved),
+            ("b", ChangesRequested),
+            ("c", Approved),
+            ("d", ChangesRequested),
+            ("e", Approved),
+            ("f", Approved),
+        ]);
+        assert!(four.approved(Approval::Required));
+
+        // Approvals turned into change requests don't count.
+        let changed = reviews(&[
+            ("a", Approved),
+            ("b", ChangesRequested),
+            ("c", Approved),
+            ("c", ChangesRequested),
+        ]);
+        assert!(!changed.approved(Approval::Required));
+
+        // Change requests alone are never overridden.
+        let none = Reviews::new("example", CommentEffect::Ignore)
+            .with_approvals_per_change_request(0)
+            .record_reviews(vec![review("a", ChangesRequested)]);
+        assert!(!none.approved(Approval::Optional));
+    }
+
+    #[test]
+    fn advisory_change_requests() {
+        let reviews = |list: &[(&str, ReviewState)]| {
+            Reviews::new("example", CommentEffect::RequestsChange)
+                .with_advisory_reviewers(vec!["style-bot".to_owned()])
+                .record_reviews(
+                    list.iter()
+                        .map(|(name, state)| review(name, *state))
+                        .collect(),
+                )
+        };
+        use ReviewState::{Approved, ChangesRequested, Commented};
+
+        let advisory = reviews(&[("a", Approved), ("style-bot", ChangesRequested)]);
+        assert!(advisory.approved(Approval::Required));
+        assert!(advisory.missing_approvals_from_users().is_empty());
+        assert_eq!(advisory.advisory_concerns_from_users(), vec!["style-bot"]);
+
+        // Comments requesting changes are advisories too.
+        let commented = reviews(&[("style-bot", Commented)]);
+        assert!(commented.approved(Approval::Optional));
+        assert!(!commented.approved(Approval::Required));
+
+        // Other reviewers still block.
+        let blocking = reviews(&[("a", Approved), ("b", ChangesRequested)]);
+        assert!(!blocking.approved(Approval::Required));
+        assert!(blocking.advisory_concerns_from_users().is_empty());
+
+        // The latest review counts.
+        let approved = reviews(&[("style-bot", ChangesRequested), ("style-bot", Approved)]);
+        assert!(approved.advisory_concerns_from_users().is_empty());
+        assert_eq!(approved.approvals(), 1);
+    }
+
+    #[test]
+    fn comments_from_requested_reviewers() {
+        let reviews = |list: &[(&str, ReviewState)]| {
+            Reviews::new(
+                "example",
+                CommentEffect::RequestsChangeFromRequestedReviewers,
+            )
+            .with_requested_reviewers(vec!["a".to_owned()])
+            .record_reviews(
+                list.iter()
+                    .map(|(name, state)| review(name, *state))
+                    .collect(),
+            )
+        };
+        use ReviewState::{Approved, Commented};
+
+        let requested = reviews(&[("a", Commented)]);
+        assert!(!requested.approved(Approval::Optional));
+        assert_eq!(requested.missing_approvals_from_users(), vec!["a"]);
+
+        // Anyone else can comment without blocking.
+        let other = reviews(&[("b", Commented)]);
+        assert!(other.approved(Approval::Optional));
+        assert!(other.missing_approvals_from_users().is_empty());
+
+        // Comments after an approval still don't count.
+        let approved = reviews(&[("a", Approved), ("a", Commented), ("b", Commented)]);
+        assert!(approved.approved(Approval::Required));
+    }
+
+    #[test]
+    fn reviews_in_progress() {
+        let requested = vec!["a".to_owned(), "b".to_owned()];
+        let reviews = |list: &[(&str, ReviewState)]| {
+            Reviews::new("example", CommentEffect::RequestsChange).record_reviews(
+                list.iter()
+                    .map(|(name, state)| review(name, *state))
+                    .collect(),
+            )
+        };
+        use ReviewState::{Approved, Commented, Pending};
+
+        let pending = reviews(&[("a", Approved), ("b", Pending)]);
+        assert_eq!(pending.reviews_in_progress_from(&requested), vec!["b"]);
+        assert!(pending.approved(Approval::Required));
+        assert!(pending.missing_approvals_from_users().is_empty());
+
+        // The previous review still counts while the next one is in progress.
+        let approved = reviews(&[("a", Approved), ("a", Pending)]);
+        assert_eq!(approved.reviews_in_progress_from(&requested), vec!["a"]);
+        assert_eq!(approved.approvals(), 1);
+
+        let submitted = reviews(&[("a", Pending), ("a", Commented)]);
+        assert!(submitted.reviews_in_progress_from(&requested).is_empty());
+
+        // Only requested reviewers are reported.
+        let unrequested = reviews(&[("c", Pending)]);
+        assert!(unrequested.reviews_in_progress_from(&requested).is_empty());
+        assert_eq!(submitted.missing_approvals_from_users(), vec!["a"]);
+    }
+
     #[test]
     fn empty() {
         let reviews = Reviews::new("example", CommentEffect::Ignore);
@@ -131,6 +483,16 @@ mod tests {
         assert!(reviews.approved(Approval::Optional));
     }
 
+    #[test]
+    fn missing_approvals_from() {
+        let mut reviews = Reviews::new("example", CommentEffect::Ignore);
+        reviews.record(review("a", ReviewState::Approved));
+        reviews.record(review("b", ReviewState::ChangesRequested));
+        reviews.record(review("c", ReviewState::Commented));
+        let users: Vec<_> = ["a", "b", "c", "d"].iter().map(|u| u.to_string()).collect();
+        assert_eq!(reviews.missing_approvals_from(&users), vec!["b", "c", "d"]);
+    }
+
     #[test]
     fn commented() {
         let mut reviews = Reviews::new("example", CommentEffect::Ignore);
@@ -0,0 +1,115 @@
+//! Helpers shared by the unit tests.
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    thread,
+};
+
+/// A request received by a [`MockServer`].
+#[derive(Debug)]
+pub struct Request {
+    /// e.g. `GET /repos/org/repo/pulls`
+    pub line: String,
+    /// The (lowercased name, value) of each header.
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+/// A minimal HTTP server, answering requests with canned responses, in order.
+pub struct MockServer {
+    pub url: String,
+    handle: thread::JoinHandle<Vec<Request>>,
+}
+
+impl MockServer {
+    /// Starts serving the given (status, body) responses, e.g. `("200 OK", "{}")`.
+    pub fn new(responses: Vec<(&'static str, &'static str)>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for (status, body) in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end().trim_end_matches(" HTTP/1.1").to_owned();
+
+                let mut content_length = 0;
+                let mut headers = Vec::new();
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    if header == "\r\n" {
+                        break;
+                    }
+                    if let Some((name, value)) = header.split_once(':') {
+                        let name = name.trim().to_lowercase();
+                        if name == "content-length" {
+                            content_length = value.trim().parse().unwrap();
+                        }
+                        headers.push((name, value.trim().to_owned()));
+                    }
+                }
+                let mut request_body = vec![0; content_length];
+                reader.read_exact(&mut request_body).unwrap();
+
+                write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 {status}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                    body.len()
+                )
+                .unwrap();
+
+                requests.push(Request {
+                    line,
+                    headers,
+                    body: String::from_utf8(request_body).unwrap(),
+                });
+            }
+            requests
+        });
+        Self { url, handle }
+    }
+
+    /// Waits for all the responses to be served, and returns the received requests.
+    pub fn requests(self) -> Vec<Request> {
+        self.handle.join().unwrap()
+    }
+}
+
+/// A client talking to the given mock server.
+pub fn client(server: &MockServer) -> crate::context::Client {
+    let mut client =
+        crate::context::Client::new("token".to_owned(), "org".to_owned(), Some(&server.url), &[])
+            .unwrap();
+    client.retry_delay = std::time::Duration::ZERO;
+    // Its nick is known, rather than fetched from the server.
+    *client.bot_nick.borrow_mut() = Some("bot".to_owned());
+    client
+}
+
+/// An open PR, with a description, targeting `main`. Tests override the fields they care about.
+pub fn pr(number: u64) -> crate::process::Pr {
+    crate::process::Pr {
+        id: number,
+        node_id: format!("PR_{number}"),
+        author: "author".to_owned(),
+        author_is_bot: false,
+        number,
+        commit_sha: "somesha".to_owned(),
+        base_ref: "main".to_owned(),
+        draft: false,
+        state: None,
+        created_at: chrono::Utc::now(),
+        updated_at: None,
+        labels: Default::default(),
+        has_description: true,
+        body: String::new(),
+        requested_reviewers_remaining: 0,
+        requested_users: Vec::new(),
+        requested_teams: Vec::new(),
+    }
+}
@@ -0,0 +1,144 @@
+//! Throttling the requests made to Github as the rate limit budget shrinks.
+//!
+//! PRs are analyzed concurrently, so on large fleets octobors could burn
+//! through the rate limit quickly. Every request takes a permit of a
+//! semaphore, whose size follows the remaining quota: the fewer requests are
+//! left, the fewer can be in flight at once. Once the limit is reset, the
+//! remaining quota goes back up and so does the concurrency.
+
+use std::cell::Cell;
+
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// How many requests can be in flight when plenty of quota is left.
+pub const MAX_PERMITS: usize = 16;
+
+/// How many remaining requests each permit needs.
+const REQUESTS_PER_PERMIT: u64 = 250;
+
+/// How many concurrent requests to allow with the given remaining quota.
+pub fn permits_for(remaining: u64) -> usize {
+    (remaining / REQUESTS_PER_PERMIT).clamp(1, MAX_PERMITS as u64) as usize
+}
+
+/// A semaphore resized from the remaining rate limit quota.
+pub struct Throttle {
+    semaphore: Semaphore,
+    /// How many permits the semaphore should have.
+    permits: Cell<usize>,
+    /// Permits that were in use when the semaphore shrank, to forget once released.
+    debt: Cell<usize>,
+}
+
+/// Allows one request to be made, until dropped.
+pub struct Permit<'a> {
+    permit: Option<SemaphorePermit<'a>>,
+    throttle: &'a Throttle,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        let debt = self.throttle.debt.get();
+        if debt > 0 {
+            self.throttle.debt.set(debt - 1);
+            if let Some(permit) = self.permit.take() {
+                permit.forget();
+            }
+        }
+    }
+}
+
+impl Default for Throttle {
+    fn default() -> Self {
+        Self {
+            semaphore: Semaphore::new(MAX_PERMITS),
+            permits: Cell::new(MAX_PERMITS),
+            debt: Cell::new(0),
+        }
+    }
+}
+
+impl Throttle {
+    /// Waits until a request can be made.
+    pub async fn acquire(&self) -> Permit<'_> {
+        Permit {
+            // The semaphore is never closed.
+            permit: self.semaphore.acquire().await.ok(),
+            throttle: self,
+        }
+    }
+
+    /// How many requests can currently be in flight.
+    pub fn permits(&self) -> usize {
+        self.permits.get()
+    }
+
+    /// Resizes the semaphore for the remaining quota reported by Github.
+    pub fn observe(&self, remaining: u64) {
+        let wanted = permits_for(remaining);
+        let current = self.permits.get();
+        if wanted > current {
+            // Cancel the pending shrinking first.
+            let mut added = wanted - current;
+            let cancelled = added.min(self.debt.get());
+            self.debt.set(self.debt.get() - cancelled);
+            added -= cancelled;
+            self.semaphore.add_permits(added);
+        } else if wanted < current {
+            let removed = current - wanted;
+            let forgotten = self.semaphore.forget_permits(removed);
+            self.debt.set(self.debt.get() + removed - forgotten);
+        }
+        self.permits.set(wanted);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn permits_shrink_with_the_quota() {
+        let permits: Vec<_> = [5000, 4000, 3000, 1000, 400, 100, 0]
+            .iter()
+            .map(|remaining| permits_for(*remaining))
+            .collect();
+        assert_eq!(permits, vec![16, 16, 12, 4, 1, 1, 1]);
+    }
+
+    #[tokio::test]
+    async fn semaphore_follows_the_quota() {
+        let throttle = Throttle::default();
+        assert_eq!(throttle.semaphore.available_permits(), MAX_PERMITS);
+
+        for (remaining, permits) in &[(3000, 12), (1000, 4), (400, 1)] {
+            throttle.observe(*remaining);
+            assert_eq!(throttle.permits(), *permits);
+            assert_eq!(throttle.semaphore.available_permits(), *permits);
+        }
+
+        // Ramps back up after the reset.
+        throttle.observe(5000);
+        assert_eq!(throttle.semaphore.available_permits(), MAX_PERMITS);
+    }
+
+    #[tokio::test]
+    async fn permits_in_use_are_forgotten_once_released() {
+        let throttle = Throttle::default();
+        throttle.observe(1000);
+        let held: Vec<_> = futures::future::join_all((0..4).map(|_| throttle.acquire())).await;
+        assert_eq!(throttle.semaphore.available_permits(), 0);
+
+        throttle.observe(500);
+        assert_eq!(throttle.permits(), 2);
+        drop(held);
+        assert_eq!(throttle.semaphore.available_permits(), 2);
+
+        // Growing again cancels what's left to forget.
+        let held: Vec<_> = futures::future::join_all((0..2).map(|_| throttle.acquire())).await;
+        throttle.observe(250);
+        throttle.observe(1000);
+        drop(held);
+        assert_eq!(throttle.semaphore.available_permits(), 4);
+    }
+}